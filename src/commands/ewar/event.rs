@@ -1,5 +1,6 @@
 use std::num::NonZeroUsize;
 use crate::model::{EventNumber, StandingEvent};
+use crate::util::league::league;
 use crate::util::paginate::{EmbedLinePaginator, PaginatorOptions};
 use crate::{BotError, Context};
 use bson::doc;
@@ -28,12 +29,12 @@ pub(crate) async fn log(
     };
 
     let mut lines = Vec::new();
-    let mut cur = ctx.data().mongo.collection::<StandingEvent>("events")
+    let mut cur = league(ctx).mongo.collection::<StandingEvent>("events")
         .find(filter_doc)
         .sort(doc! { "_id": -1 })
-        .limit(LOG_LIMIT)
+        .limit(*LOG_LIMIT)
         .await?;
-    while let Some(event) = cur.try_next().await? { lines.push(event.short_summary(&ctx.data().mongo).await?) }
+    while let Some(event) = cur.try_next().await? { lines.push(event.short_summary(&league(ctx).mongo).await?) }
 
     EmbedLinePaginator::new(lines, PaginatorOptions::new()
         .max_lines(NonZeroUsize::new(10).unwrap())
@@ -2,17 +2,20 @@ use crate::commands::ewar::user::try_lookup_player;
 use crate::commands::ewar::user::UserLookupType::{DiscordID, SystemID};
 use crate::ewar::game::BadPlacementType::*;
 use crate::model::StandingEventInner::GameEnd;
-use crate::model::{ApprovalStatus, Player};
-use crate::model::{Game, GameID, LeagueInfo, StandingEvent};
-use crate::util::base_embed;
+use crate::model::{ApprovalStatus, EventNumber, Player, PlayerID};
+use crate::model::{Game, GameID, StandingEvent};
+use crate::store::{Counter, Store};
+use crate::util::league::league;
+use crate::util::metrics::METRICS;
+use crate::util::{base_embed, remove_markdown};
 use crate::util::checks::{_is_league_moderator, has_system_account};
 use crate::util::constants::LOG_LIMIT;
 use crate::util::paginate::{EmbedLinePaginator, PaginatorOptions};
 use crate::util::rating::RatingExtra;
-use crate::util::rating::{advance_approve_pointer, expected_outcome, game_affect_ratings};
+use crate::util::rating::{expected_outcome, game_affect_ratings};
 use crate::{BotError, Context};
 use bson::doc;
-use chrono::{TimeDelta, Utc};
+use chrono::{DateTime, TimeDelta, Utc};
 use futures::TryStreamExt;
 use itertools::Itertools;
 use mongodb::Database;
@@ -28,6 +31,7 @@ use timeago::TimeUnit::Seconds;
 enum BadPlacementType {
     DuplicateUser,
     UserNotFound { offending: UserId },
+    UserSuspended { offending: UserId, until: DateTime<Utc> },
 }
 
 impl BadPlacementType {
@@ -41,6 +45,12 @@ impl BadPlacementType {
                     .embed(base_embed(ctx)
                         .description(format!("{} has no account on this bot", user.mention())))
             }
+            UserSuspended { offending: user, until } => {
+                CreateReply::default()
+                    .embed(base_embed(ctx)
+                        .description(format!("{} is suspended until <t:{}:f> and can't play in league games",
+                                             user.mention(), until.timestamp())))
+            }
         }
     }
 }
@@ -114,16 +124,24 @@ pub(crate) async fn post(
     ].into_iter().filter_map(identity).collect_vec();
 
     // part 1: validate proposed game
-    let poster_info = try_lookup_player(&ctx.data().mongo, DiscordID(ctx.author().id.get())).await?
+    let poster_info = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?
         .expect("user disappeared after check");
 
-    let poster_not_moderator = !_is_league_moderator(ctx).await?;
-    if poster_not_moderator && placement_discord.iter().all(|u| u != ctx.author()) {
+    if let Some(suspension) = poster_info.active_suspension() {
+        ctx.send(CreateReply::default()
+            .content(format!(":x: you are suspended until <t:{}:f> ({}) and can't post games",
+                             suspension.until.timestamp(), remove_markdown(&suspension.reason)))
+            .ephemeral(true)).await?;
+        return Ok(());
+    }
+
+    let poster_is_moderator = _is_league_moderator(ctx).await?;
+    if !poster_is_moderator && placement_discord.iter().all(|u| u != ctx.author()) {
         ctx.reply(":x: you must be a party to a game to log it").await?;
         return Ok(());
     }
 
-    let placement_players = match lookup_placement(&ctx.data().mongo, &placement_discord).await? {
+    let placement_players = match lookup_placement(&league(ctx).mongo, &placement_discord).await? {
         Err(reason) => {
             ctx.send(reason.create_error_message(ctx)).await?;
             return Ok(());
@@ -131,6 +149,20 @@ pub(crate) async fn post(
         Ok(ret) => ret
     };
 
+    if let Some((user, suspension)) = placement_discord.iter().zip(placement_players.iter())
+        .find_map(|(user, player)| player.active_suspension().map(|suspension| (user, suspension))) {
+        ctx.send(UserSuspended { offending: user.id, until: suspension.until }.create_error_message(ctx)).await?;
+        return Ok(());
+    }
+
+    let participant_system_ids = placement_players.iter().map(|player| player._id).collect_vec();
+
+    // a moderator who played in this game is treated like any other player unless the league allows otherwise
+    let review_policy = &league(ctx).review_policy;
+    let poster_not_moderator = !poster_is_moderator
+        || !review_policy.moderator_may_self_post(poster_info._id, &participant_system_ids);
+    let poster_approves_immediately = !poster_not_moderator && review_policy.quorum_met(&[poster_info._id]);
+
     // part 2: submitter must confirm
     let emb_desc = format!(
         "you are logging a game with the following result:\n{}\n{}",
@@ -139,8 +171,12 @@ pub(crate) async fn post(
                 "{}. {} ({})", index + 1, player.short_summary(), player.reference_no_discord())
             )
             .join("\n"),
-        if !poster_not_moderator {
+        if poster_approves_immediately {
             "\n**as a moderator, your confirmation will submit and approve the game immediately**"
+        } else if !poster_not_moderator {
+            "\n**as a moderator, your confirmation will skip signoff and count as one approval**"
+        } else if poster_is_moderator {
+            "\n**you played in this game, so it needs signoff and another moderator's review like any other**"
         } else { "" });
 
     let initial_confirm_timeout = 15;
@@ -226,10 +262,21 @@ pub(crate) async fn post(
                             .components(signoff_components)).await?;
 
                     party_sign_stage_msg.reply(ctx.http(), "timed out, this game is voided for submission").await?;
+                    METRICS.signoff(&league(ctx).name, false);
 
                     return Ok(());
                 }
                 Some(ixn) => {
+                    // a suspension may have started since the game was posted
+                    let signer = try_lookup_player(&league(ctx).mongo, DiscordID(ixn.user.id.get())).await?;
+                    if let Some(suspension) = signer.as_ref().and_then(Player::active_suspension) {
+                        ixn.create_response(ctx.http(), CreateInteractionResponse::Message(CreateInteractionResponseMessage::new()
+                            .content(format!(":x: you are suspended until <t:{}:f> and can't sign off on games",
+                                             suspension.until.timestamp()))
+                            .ephemeral(true))).await?;
+                        continue;
+                    }
+
                     ixn.create_response(ctx.http(), CreateInteractionResponse::Message(CreateInteractionResponseMessage::new()
                         .content("ok, signed off on this game")
                         .ephemeral(true))).await?;
@@ -247,6 +294,8 @@ pub(crate) async fn post(
             }
         }
 
+        METRICS.signoff(&league(ctx).name, true);
+
         let (_, signoff_components) = make_signoff_msg(&not_signed_off, true);
         party_sign_stage_msg.edit(
             ctx.http(),
@@ -255,55 +304,74 @@ pub(crate) async fn post(
     }
 
     // part 4: log it
-
-    // increment, but the previous value is what we'll use
-    // big idea is to prevent someone else from messing with us, so reserve then use
-    let LeagueInfo { available_game_id, available_event_number, .. } = ctx.data().mongo
-        .collection::<LeagueInfo>("league_info")
-        .find_one_and_update(
-            doc! {},
-            doc! { "$inc": { "available_game_id": 1, "available_event_number": 1, } })
-        .await?
-        .expect("league_info struct missing");
-
-    let participant_system_ids = placement_players.iter().map(|player| player._id).collect_vec();
-
-    let signed_game = Game {
-        game_id: available_game_id,
-        ranking: participant_system_ids.clone(),
-        length: time_seconds,
-    };
-
-    let event = StandingEvent {
-        _id: available_event_number,
-        approval_status: if poster_not_moderator { None } else {
+    let (available_game_id, available_event_number) = record_game(
+        &league(ctx).store,
+        participant_system_ids.clone(),
+        time_seconds,
+        submitted_time,
+        if poster_approves_immediately {
             Some(ApprovalStatus { approved: true, reviewer: Some(poster_info._id) })
-        },
-        inner: GameEnd(signed_game),
-        when: submitted_time,
-    };
+        } else { None },
+        if poster_not_moderator { vec![] } else { vec![poster_info._id] },
+    ).await?;
 
-    ctx.data().mongo.collection::<StandingEvent>("events").insert_one(event).await?;
-
-    if !poster_not_moderator {
-        advance_approve_pointer(ctx.data(), None).await?;
+    if poster_approves_immediately {
+        league(ctx).processor.advance(None).await?;
     }
 
     // part 5: moderator must sign later
     ctx.send(CreateReply::default().content(
-        if poster_not_moderator {
+        if poster_approves_immediately {
+            // if poster was a moderator, it has already been approved
+            format!("ok, game with ID {available_game_id} approved as event {available_event_number} bypassing player signoff")
+        } else if !poster_not_moderator {
             format!(
-                "ok, game with ID {available_game_id} submitted for moderator verification\n\
-                **any moderator, please approve or reject this game with `/review {available_game_id}`.**",
+                "ok, game with ID {available_game_id} submitted with your approval ({})\n\
+                **another moderator, please approve or reject this game with `/review {available_game_id}`.**",
+                review_policy.approval_progress(&[poster_info._id]),
             )
         } else {
-            // if poster was a moderator, it has already been approved
-            format!("ok, game with ID {available_game_id} approved as event {available_event_number} bypassing player signoff")
+            format!(
+                "ok, game with ID {available_game_id} submitted for moderator verification\n\
+                **any moderator{}, please approve or reject this game with `/review {available_game_id}`.**",
+                if review_policy.allow_self_review { "" } else { " who did not play" },
+            )
         })).await?;
 
     Ok(())
 }
 
+/// append a signed-off game to the record under the next game ID; returns the game ID and event number it got
+pub(crate) async fn record_game(
+    store: &Store,
+    ranking: Vec<PlayerID>,
+    length: u32,
+    when: DateTime<Utc>,
+    approval_status: Option<ApprovalStatus>,
+    approvals: Vec<PlayerID>,
+) -> Result<(GameID, EventNumber), BotError> {
+    // increment, but the previous value is what we'll use
+    // big idea is to prevent someone else from messing with us, so reserve then use
+    let event = store.events.append(&[Counter::GameId], Box::new(move |reserved| StandingEvent {
+        _id: reserved.available_event_number,
+        approval_status: approval_status.clone(),
+        approvals: approvals.clone(),
+        inner: GameEnd(Game {
+            game_id: reserved.available_game_id,
+            ranking: ranking.clone(),
+            length,
+            expected: vec![],
+            deltas: vec![],
+        }),
+        when,
+    })).await?;
+
+    match event.inner {
+        GameEnd(Game { game_id, .. }) => Ok((game_id, event._id)),
+        _ => unreachable!("recorded game is not a game"),
+    }
+}
+
 /// See the results of a potential match
 #[poise::command(prefix_command, slash_command)]
 pub(crate) async fn whatif(
@@ -326,7 +394,7 @@ pub(crate) async fn whatif(
         user7, user8, user9, user10, user11,
     ].into_iter().filter_map(identity).collect_vec();
 
-    let placement_players = match lookup_placement(&ctx.data().mongo, &placement_discord).await? {
+    let placement_players = match lookup_placement(&league(ctx).mongo, &placement_discord).await? {
         Err(reason) => {
             ctx.send(reason.create_error_message(ctx)).await?;
             return Ok(());
@@ -375,7 +443,7 @@ pub(crate) async fn whatif(
 /// Get a game by ID
 #[poise::command(prefix_command, slash_command)]
 pub(crate) async fn query(ctx: Context<'_>, #[description = "ID of game to get"] game_id: GameID) -> Result<(), BotError> {
-    let event = match ctx.data().mongo
+    let event = match league(ctx).mongo
         .collection::<StandingEvent>("events")
         .find_one(doc! { "inner.GameEnd.game_id": game_id }).await? {
         None => {
@@ -389,7 +457,7 @@ pub(crate) async fn query(ctx: Context<'_>, #[description = "ID of game to get"]
 
     let mut users_info = Vec::with_capacity(game.ranking.len());
     for player_id in game.ranking {
-        users_info.push(try_lookup_player(&ctx.data().mongo, SystemID(player_id)).await?.expect("user in game DNE"));
+        users_info.push(try_lookup_player(&league(ctx).mongo, SystemID(player_id)).await?.expect("user in game DNE"));
     }
 
     let mut time_formatter = timeago::Formatter::new();
@@ -412,9 +480,9 @@ pub(crate) async fn query(ctx: Context<'_>, #[description = "ID of game to get"]
                 time_formatter.convert_chrono(event.when, Utc::now())
             ), true)
             .field("reviewer", match event.approval_status {
-                None => String::from("not approved yet"),
+                None => format!("not approved yet ({})", league(ctx).review_policy.approval_progress(&event.approvals)),
                 Some(approval_status) => String::from(
-                    approval_status.short_summary(&ctx.data().mongo).await?),
+                    approval_status.short_summary(&league(ctx).mongo).await?),
             }, true)
             .field("length (pre-overtime)", format!(
                 "{:02}:{:02}", chrono_game_length.num_minutes(), chrono_game_length.num_seconds() % 60
@@ -443,13 +511,13 @@ pub(crate) async fn log(
     };
 
     let mut lines = Vec::new();
-    let mut cur = ctx.data().mongo.collection::<StandingEvent>("events")
+    let mut cur = league(ctx).mongo.collection::<StandingEvent>("events")
         .find(filter_doc)
         .sort(doc! { "_id": -1 })
-        .limit(LOG_LIMIT)
+        .limit(*LOG_LIMIT)
         .await?;
     while let Some(event) = cur.try_next().await? {
-        lines.push(event.short_summary(&ctx.data().mongo).await?)
+        lines.push(event.short_summary(&league(ctx).mongo).await?)
     }
 
     EmbedLinePaginator::new(lines, PaginatorOptions::new()
@@ -1,75 +1,355 @@
-use crate::model::{LeagueInfo, Player};
-use crate::util::constants::PROVISIONAL_DEVIATION_THRESHOLD;
+use crate::commands::ewar::user::try_lookup_player;
+use crate::commands::ewar::user::UserLookupType::{DiscordID, SystemID};
+use crate::model::{LeagueInfo, LiveLeaderboard, LiveStanding, Player, PlayerID, Season, SeasonID};
+use crate::util::checks::is_league_moderator;
+use crate::util::constants::{LIVE_LEADERBOARD_MOVERS, LIVE_LEADERBOARD_SIZE, PROVISIONAL_DEVIATION_THRESHOLD};
+use crate::util::league::league;
 use crate::util::paginate::{EmbedLinePaginator, PaginatorOptions};
 use crate::util::rating::RatingExtra;
 use crate::util::remove_markdown;
 use crate::{BotError, Context};
-use bson::doc;
+use bson::{doc, Document};
+use chrono::{TimeDelta, Utc};
 use futures::TryStreamExt;
 use itertools::Itertools;
+use mongodb::Database;
+use serde::Deserialize;
+use serenity::all::{ChannelId, CreateEmbed, CreateEmbedFooter, CreateMessage, EditMessage, GuildId, Http, MessageId, Timestamp};
+use std::collections::{HashMap, HashSet};
+
+#[derive(Deserialize)]
+struct GamesPlayed {
+    _id: PlayerID,
+    games: u32,
+}
 
 /// see the highest rated players
 #[poise::command(prefix_command, slash_command)]
 pub(crate) async fn leaderboard(
     ctx: Context<'_>,
     #[description = "Include players with provisional ratings"] include_provisional: Option<bool>,
+    #[description = "Show the final standings of a closed season"] season: Option<SeasonID>,
+    #[description = "Only players with at least this many approved games"] min_games: Option<u32>,
+    #[description = "Only players who have played in this many days"] active_within_days: Option<u32>,
+    #[description = "Only players who are in this server"] members_only: Option<bool>,
+    #[description = "Open on the page with your own rank"] around_me: Option<bool>,
 ) -> Result<(), BotError> {
     ctx.defer().await?;
 
-    let LeagueInfo { leaderboard_blacklist, .. } = ctx.data().mongo
+    let me = match around_me.unwrap_or(false) {
+        false => None,
+        true => match try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await? {
+            None => {
+                ctx.reply(":x: you aren't registered, so you aren't on the leaderboard").await?;
+                return Ok(());
+            }
+            Some(player) => Some(player._id),
+        }
+    };
+
+    // in leaderboard order, not yet numbered
+    let entries = match season {
+        Some(season) => {
+            if min_games.is_some() || active_within_days.is_some() {
+                ctx.reply(":x: games played and activity filters only work on the current season").await?;
+                return Ok(());
+            }
+
+            let Some(archived) = league(ctx).mongo.collection::<Season>("seasons").find_one(doc! { "_id": season }).await? else {
+                let current = league(ctx).store.league_info.current_season().await?;
+                ctx.reply(match season == current {
+                    true => format!(":x: season {season} is in progress and hasn't closed yet"),
+                    false => format!(":x: no such season (season {current} is in progress)"),
+                }).await?;
+                return Ok(());
+            };
+
+            archived.standings.into_iter()
+                .filter(|standing| include_provisional.unwrap_or(false) || standing.place.is_some())
+                .map(|standing| {
+                    let line = format!("{}: {}", remove_markdown(&standing.username), standing.rating_struct().format_rating());
+                    (standing.player, if standing.place.is_none() { format!("~~{}~~", line) } else { line })
+                })
+                .collect_vec()
+        }
+        None => live_entries(ctx, include_provisional.unwrap_or(false), min_games, active_within_days).await?
+    };
+
+    let entries = match members_only.unwrap_or(false) {
+        false => entries,
+        true => {
+            let Some(guild_id) = ctx.guild_id() else {
+                ctx.reply(":x: can only filter to members inside a server").await?;
+                return Ok(());
+            };
+
+            let members = guild_player_ids(ctx, guild_id).await?;
+            entries.into_iter()
+                .filter(|(player_id, _)| members.contains(player_id))
+                .collect_vec()
+        }
+    };
+
+    let my_line = me.and_then(|me| entries.iter().position(|(player_id, _)| *player_id == me));
+    if me.is_some() && my_line.is_none() {
+        ctx.reply(":x: you aren't on this leaderboard; try fewer filters").await?;
+        return Ok(());
+    }
+
+    if entries.is_empty() {
+        ctx.reply("no users found").await?;
+        return Ok(());
+    }
+
+    let lb_lines = entries.into_iter()
+        .enumerate()
+        .map(|(ind, (_, line))| match Some(ind) == my_line {
+            true => format!("**{}. {}** ⬅️", ind + 1, line),
+            false => format!("{}. {}", ind + 1, line),
+        }.into_boxed_str())
+        .collect_vec();
+
+    let mut paginator = EmbedLinePaginator::new(lb_lines, PaginatorOptions::new());
+    if let Some(my_line) = my_line {
+        paginator = paginator.start_at_line(my_line);
+    }
+    paginator.run(ctx).await?;
+
+    Ok(())
+}
+
+async fn live_entries(
+    ctx: Context<'_>,
+    include_provisional: bool,
+    min_games: Option<u32>,
+    active_within_days: Option<u32>,
+) -> Result<Vec<(PlayerID, String)>, BotError> {
+    let league_info = league(ctx).mongo
         .collection::<LeagueInfo>("league_info")
         .find_one(doc! {})
         .await?
         .expect("league_info struct missing");
 
+    let mut filter_doc = Document::new();
+    if !include_provisional {
+        filter_doc.insert("deviation", doc! {"$lte": *PROVISIONAL_DEVIATION_THRESHOLD});
+    }
+    if let Some(days) = active_within_days {
+        filter_doc.insert("last_played", doc! {"$gte": bson::DateTime::from_chrono(Utc::now() - TimeDelta::days(days as i64))});
+    }
+
     let sort_doc = doc! {"$sort": { "lb_rating": -1 }};
     let new_root_doc = doc! {"$replaceRoot": {"newRoot": "$inner"}};
-    let aggregate_players = ctx.data().mongo.collection::<Player>("players").aggregate(if include_provisional.unwrap_or(false) {
-        let agg_doc = doc! {
+    let agg_doc = if include_provisional {
+        doc! {
             "$project": {
                 "lb_rating": {
                     "$cond": {
-                        "if": {"$gt": ["$deviation", PROVISIONAL_DEVIATION_THRESHOLD]},
+                        "if": {"$gt": ["$deviation", *PROVISIONAL_DEVIATION_THRESHOLD]},
                         "then": {"$multiply": [{"$subtract": ["$rating", "$deviation"]}, 10]},
                         "else": {"$multiply": ["$rating", 10]},
                     }
                 },
                 "inner": "$$ROOT"
             }
-        };
-        vec![agg_doc, sort_doc, new_root_doc]
+        }
     } else {
-        let filter_doc = doc! {
-            "$match": {"deviation": {"$lte": PROVISIONAL_DEVIATION_THRESHOLD}}
-        };
-        let agg_doc = doc! {
+        doc! {
             "$project": {
                 "lb_rating": {"$multiply": ["$rating", 10]},
                 "inner": "$$ROOT"
             }
-        };
-        vec![filter_doc, agg_doc, sort_doc, new_root_doc]
-    }).with_type::<Player>().await?
+        }
+    };
+
+    let aggregate_players = league(ctx).mongo.collection::<Player>("players")
+        .aggregate(vec![doc! {"$match": filter_doc}, agg_doc, sort_doc, new_root_doc])
+        .with_type::<Player>().await?
+        .try_collect::<Vec<_>>().await?;
+
+    let games_played = match min_games {
+        None => HashMap::new(),
+        Some(_) => league(ctx).mongo.collection::<Document>("events")
+            .aggregate(vec![
+                doc! {"$match": {"inner.GameEnd": {"$exists": true}, "approval_status.approved": true}},
+                doc! {"$unwind": "$inner.GameEnd.ranking"},
+                doc! {"$group": {"_id": "$inner.GameEnd.ranking", "games": {"$sum": 1}}},
+            ])
+            .with_type::<GamesPlayed>().await?
+            .try_collect::<Vec<_>>().await?
+            .into_iter()
+            .map(|played| (played._id, played.games))
+            .collect::<HashMap<_, _>>(),
+    };
+
+    Ok(aggregate_players.into_iter()
+        .filter(|p| league_info.blacklist_entry(p._id).is_none())
+        .filter(|p| min_games.is_none_or(|min_games| games_played.get(&p._id).copied().unwrap_or(0) >= min_games))
+        .map(|player| {
+            let line = format!("{}: {}", remove_markdown(&player.username), player.rating_struct().format_rating());
+            (player._id, if player.rating_struct().is_provisional() { format!("~~{}~~", line) } else { line })
+        })
+        .collect_vec())
+}
+
+/// players with a discord account in this guild
+async fn guild_player_ids(ctx: Context<'_>, guild_id: GuildId) -> Result<HashSet<PlayerID>, BotError> {
+    let member_ids = guild_id.members_iter(ctx.serenity_context())
+        .map_ok(|member| member.user.id.get() as i64)
+        .try_collect::<Vec<_>>().await?;
+
+    Ok(league(ctx).mongo.collection::<Player>("players")
+        .find(doc! { "discord_ids": { "$in": member_ids } })
+        .await?
         .try_collect::<Vec<_>>().await?
         .into_iter()
-        .filter(|p| !leaderboard_blacklist.contains(&p._id))
-        .collect_vec();
+        .map(|player| player._id)
+        .collect())
+}
 
-    if aggregate_players.is_empty() {
-        ctx.reply("no users found").await?;
-        return Ok(());
+/// League moderators: keep an always current leaderboard message in a channel
+#[poise::command(prefix_command, slash_command, subcommands("post", "take_down"), check = is_league_moderator
+)]
+pub(crate) async fn live_leaderboard(ctx: Context<'_>) -> Result<(), BotError> {
+    ctx.reply("base command is noop, try a subcommand").await?;
+
+    Ok(())
+}
+
+/// League moderators: post the live leaderboard here or in another channel, replacing any old one
+#[poise::command(prefix_command, slash_command, check = is_league_moderator)]
+async fn post(
+    ctx: Context<'_>,
+    #[description = "Channel to post in, this one if not given"] channel: Option<ChannelId>,
+) -> Result<(), BotError> {
+    let channel = channel.unwrap_or(ctx.channel_id());
+    let standings = live_standings(&league(ctx).mongo).await?;
+
+    let message = channel.send_message(ctx, CreateMessage::new()
+        .embed(live_leaderboard_embed(&league(ctx).mongo, &standings, None).await?)).await?;
+    let pinned = message.pin(ctx).await.is_ok();
+
+    let old = league(ctx).mongo.collection::<LeagueInfo>("league_info")
+        .find_one_and_update(doc! {}, doc! { "$set": { "live_leaderboard": bson::to_bson(&LiveLeaderboard {
+            channel: channel.get(),
+            message: message.id.get(),
+            standings,
+        })? } })
+        .await?
+        .expect("league_info struct missing")
+        .live_leaderboard;
+
+    // the old message would otherwise sit there going stale
+    if let Some(old) = old {
+        let _ = ChannelId::new(old.channel).delete_message(ctx, MessageId::new(old.message)).await;
     }
 
-    let mut lb_lines = Vec::with_capacity(aggregate_players.len());
-    for (ind, player) in aggregate_players.into_iter().enumerate() {
-        let mut line = format!("{}: {}", remove_markdown(&*player.username), player.rating_struct().format_rating());
-        line = if player.rating_struct().is_provisional() { format!("~~{}~~", line) } else { line };
+    ctx.reply(format!("ok, the live leaderboard is at {}{}", message.link(),
+                      if pinned { "" } else { " (couldn't pin it, so you may want to)" })).await?;
+    Ok(())
+}
+
+/// League moderators: stop keeping a live leaderboard
+#[poise::command(prefix_command, slash_command, check = is_league_moderator)]
+async fn take_down(ctx: Context<'_>) -> Result<(), BotError> {
+    let old = league(ctx).mongo.collection::<LeagueInfo>("league_info")
+        .find_one_and_update(doc! {}, doc! { "$set": { "live_leaderboard": null } })
+        .await?
+        .expect("league_info struct missing")
+        .live_leaderboard;
+
+    match old {
+        None => ctx.reply(":x: there is no live leaderboard").await?,
+        Some(old) => {
+            let _ = ChannelId::new(old.channel).delete_message(ctx, MessageId::new(old.message)).await;
+            ctx.reply("ok, the live leaderboard is gone").await?
+        }
+    };
+
+    Ok(())
+}
 
-        lb_lines.push(format!("{}. {}", ind + 1, line).into_boxed_str());
+/// the board as the live message shows it: rated, non-provisional and not blacklisted
+async fn live_standings(mongo: &Database) -> Result<Vec<LiveStanding>, BotError> {
+    let league_info = mongo
+        .collection::<LeagueInfo>("league_info")
+        .find_one(doc! {})
+        .await?
+        .expect("league_info struct missing");
+
+    Ok(mongo.collection::<Player>("players")
+        .find(doc! { "deviation": {"$lte": *PROVISIONAL_DEVIATION_THRESHOLD}, "last_played": {"$ne": null} })
+        .await?
+        .try_collect::<Vec<_>>().await?
+        .into_iter()
+        .filter(|p| league_info.blacklist_entry(p._id).is_none())
+        .map(|p| LiveStanding { player: p._id, rating: p.rating_struct().leaderboard_rating() })
+        .sorted_by(|a, b| b.rating.total_cmp(&a.rating))
+        .collect_vec())
+}
+
+async fn live_leaderboard_embed(mongo: &Database, standings: &[LiveStanding], previous: Option<&[LiveStanding]>) -> Result<CreateEmbed, BotError> {
+    let mut lines = Vec::with_capacity(*LIVE_LEADERBOARD_SIZE);
+    for (ind, standing) in standings.iter().take(*LIVE_LEADERBOARD_SIZE).enumerate() {
+        let player = try_lookup_player(mongo, SystemID(standing.player)).await?.expect("ranked player DNE");
+        lines.push(format!("{}. {}: {:.2}", ind + 1, remove_markdown(&player.username), standing.rating));
     }
 
-    EmbedLinePaginator::new(lb_lines, PaginatorOptions::new())
-        .run(ctx).await?;
+    let mut embed = CreateEmbed::default()
+        .color(0xfcc11b)
+        .title("leaderboard")
+        .description(if lines.is_empty() { String::from("no users found") } else { lines.join("\n") })
+        .footer(CreateEmbedFooter::new("updates as games are approved"))
+        .timestamp(Timestamp::now());
+
+    if let Some(previous) = previous {
+        let old_places = previous.iter().enumerate()
+            .map(|(ind, standing)| (standing.player, (ind, standing.rating)))
+            .collect::<HashMap<_, _>>();
+
+        let movers = standings.iter().enumerate()
+            .filter_map(|(ind, standing)| match old_places.get(&standing.player) {
+                None => Some((f64::INFINITY, standing.player, format!("new at #{}", ind + 1))),
+                Some((old_ind, old_rating)) => (standing.rating != *old_rating || ind != *old_ind).then(|| (
+                    (standing.rating - old_rating).abs(),
+                    standing.player,
+                    format!("{:+.2} (#{} → #{})", standing.rating - old_rating, old_ind + 1, ind + 1),
+                )),
+            })
+            .sorted_by(|(a, ..), (b, ..)| b.total_cmp(a))
+            .take(*LIVE_LEADERBOARD_MOVERS)
+            .collect_vec();
+
+        if !movers.is_empty() {
+            let mut mover_lines = Vec::with_capacity(movers.len());
+            for (_, player_id, change) in movers {
+                let player = try_lookup_player(mongo, SystemID(player_id)).await?.expect("ranked player DNE");
+                mover_lines.push(format!("{}: {change}", remove_markdown(&player.username)));
+            }
+            embed = embed.field("biggest movers since last update", mover_lines.join("\n"), false);
+        }
+    }
+
+    Ok(embed)
+}
+
+/// edit the live leaderboard message, if there is one, to match present-day ratings
+pub(crate) async fn refresh_live_leaderboard(http: &Http, mongo: &Database) -> Result<(), BotError> {
+    let Some(live) = mongo.collection::<LeagueInfo>("league_info")
+        .find_one(doc! {})
+        .await?
+        .expect("league_info struct missing")
+        .live_leaderboard else { return Ok(()) };
+
+    let standings = live_standings(mongo).await?;
+    let embed = live_leaderboard_embed(mongo, &standings, Some(&live.standings)).await?;
+    ChannelId::new(live.channel)
+        .edit_message(http, MessageId::new(live.message), EditMessage::new().embed(embed))
+        .await?;
+
+    mongo.collection::<LeagueInfo>("league_info")
+        .update_one(doc! {}, doc! { "$set": { "live_leaderboard.standings": bson::to_bson(&standings)? } })
+        .await?;
 
     Ok(())
 }
@@ -3,3 +3,5 @@ pub(crate) mod game;
 pub(crate) mod leaderboard;
 pub(crate) mod moderation;
 pub(crate) mod event;
+pub(crate) mod records;
+pub(crate) mod season;
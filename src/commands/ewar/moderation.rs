@@ -1,28 +1,34 @@
 use crate::commands::ewar::user::UserLookupType::{DiscordID, SystemID, Username};
 use crate::commands::ewar::user::{register_user, try_lookup_player};
-use crate::model::StandingEventInner::{GameEnd, Penalty};
-use crate::model::{ApprovalStatus, Game, GameID, LeagueInfo, PlayerID, StandingEvent};
+use crate::model::StandingEventInner::{GameEnd, LinkDiscord, MergePlayers, Penalty, Rename, Suspend, UnlinkDiscord, Unsuspend};
+use crate::model::{ApprovalStatus, BlacklistEntry, EventNumber, GameID, LeagueInfo, PlayerID, RenameRequest, StandingEvent};
+use crate::store::{Change, Store};
+use crate::util::autocomplete::{autocomplete_player, autocomplete_unreviewed_game};
 use crate::util::checks::{has_system_account, is_league_moderator};
-use crate::util::rating::advance_approve_pointer;
+use crate::util::league::league;
+use crate::util::paginate::{EmbedLinePaginator, PaginatorOptions};
+use crate::util::review::ReviewPolicy;
 use crate::util::{base_embed, remove_markdown};
 use crate::{BotError, Context};
 use bson::{doc, Bson};
-use chrono::Utc;
+use chrono::{TimeDelta, Utc};
 use futures::TryStreamExt;
 use itertools::Itertools;
+use mongodb::Database;
 use poise::CreateReply;
-use serenity::all::{CreateActionRow, CreateButton, CreateEmbedFooter, CreateInteractionResponse, EmojiId, GuildId, User};
+use serenity::all::{CreateActionRow, CreateButton, CreateEmbedFooter, CreateInteractionResponse, EmojiId, GuildId, Mentionable, ReactionType, User};
+use std::num::NonZeroUsize;
 use std::time::Duration;
+use tracing::info;
 
 /// League moderators: review game for league record; approve or reject
 #[poise::command(prefix_command, slash_command, check = has_system_account, check = is_league_moderator
 )]
 pub(crate) async fn review(
     ctx: Context<'_>,
-    #[description = "ID of game to approve"] game_id: GameID,
+    #[description = "ID of game to approve"] #[autocomplete = "autocomplete_unreviewed_game"] game_id: GameID,
     #[description = "whether to accept or reject this game"] approved: bool) -> Result<(), BotError> {
-    let corresponding_event = match ctx.data().mongo.collection::<StandingEvent>("events").find_one(
-        doc! { "inner.GameEnd.game_id": game_id }).await? {
+    let corresponding_event = match league(ctx).store.events.game(game_id).await? {
         None => {
             ctx.send(CreateReply::default()
                 .content(":x: that game DNE")
@@ -32,7 +38,7 @@ pub(crate) async fn review(
         Some(game) => game
     };
 
-    let StandingEvent { inner: GameEnd(Game { .. }), .. } = corresponding_event else {
+    let StandingEvent { inner: GameEnd(ref game), .. } = corresponding_event else {
         return Err(format!("event resembling game with game ID {game_id} is invalid").into())
     };
 
@@ -43,20 +49,31 @@ pub(crate) async fn review(
         return Ok(());
     }
 
-    let player = try_lookup_player(&ctx.data().mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
+    let player = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
 
-    let StandingEvent { _id: event_number, .. } = ctx.data().mongo.collection::<StandingEvent>("events").find_one_and_update(
-        doc! { "_id": corresponding_event._id },
-        doc! {
-            "$set": {
-                "approval_status": {
-                    "approved": approved,
-                    "reviewer": Some(player._id),
-                }
-            }
-        })
-        .await?
-        .expect("standing event magically disappeared");
+    let review_policy = &league(ctx).review_policy;
+    if let Err(conflict) = review_policy.check_review(player._id, &corresponding_event, game, approved) {
+        ctx.send(CreateReply::default()
+            .content(conflict.explain())
+            .ephemeral(true)).await?;
+        return Ok(());
+    }
+
+    let event_number = match cast_review(&league(ctx).store, review_policy, corresponding_event._id, player._id, approved).await? {
+        ReviewOutcome::Waiting(approvals) => {
+            ctx.send(CreateReply::default()
+                .content(format!("approval recorded for game {game_id}; now has {}, waiting on another moderator",
+                                 review_policy.approval_progress(&approvals)))).await?;
+            return Ok(());
+        }
+        ReviewOutcome::AlreadyDecided => {
+            ctx.send(CreateReply::default()
+                .content(":x: that game already reviewed")
+                .ephemeral(true)).await?;
+            return Ok(());
+        }
+        ReviewOutcome::Decided(event_number) => event_number,
+    };
 
     if approved {
         ctx.send(CreateReply::default()
@@ -66,15 +83,46 @@ pub(crate) async fn review(
             .content(format!("rejected game {game_id}, event number {event_number}"))).await?;
     }
 
-    advance_approve_pointer(ctx.data(), None).await?;
+    league(ctx).processor.advance(None).await?;
     Ok(())
 }
 
 
+pub(crate) enum ReviewOutcome {
+    /// approval counted, but the quorum isn't met yet; these are the approvals so far
+    Waiting(Vec<PlayerID>),
+    /// the game entered (or was kept out of) the record as this event
+    Decided(EventNumber),
+    /// someone else settled it first
+    AlreadyDecided,
+}
+
+/// count one moderator's review of an unreviewed game, settling it once the quorum is met or on any rejection.
+/// conflicts of interest are checked beforehand with `ReviewPolicy::check_review`
+pub(crate) async fn cast_review(store: &Store, review_policy: &ReviewPolicy, event_number: EventNumber, reviewer: PlayerID, approved: bool) -> Result<ReviewOutcome, BotError> {
+    if approved {
+        let Some(with_vote) = store.events.add_approval(event_number, reviewer).await? else {
+            return Ok(ReviewOutcome::AlreadyDecided);
+        };
+
+        if !review_policy.quorum_met(&with_vote.approvals) {
+            info!(event_number, reviewer, approvals = with_vote.approvals.len(), "approval recorded");
+            return Ok(ReviewOutcome::Waiting(with_vote.approvals));
+        }
+    }
+
+    if !store.events.decide(event_number, ApprovalStatus { approved, reviewer: Some(reviewer) }).await? {
+        return Ok(ReviewOutcome::AlreadyDecided);
+    }
+    info!(event_number, approved, reviewer, "review settled");
+
+    Ok(ReviewOutcome::Decided(event_number))
+}
+
 /// League moderators: check for unreviewed games
 #[poise::command(prefix_command, slash_command, check = is_league_moderator)]
 pub(crate) async fn unreviewed(ctx: Context<'_>) -> Result<(), BotError> {
-    let find = ctx.data().mongo.collection::<StandingEvent>("events")
+    let find = league(ctx).mongo.collection::<StandingEvent>("events")
         .find(doc! {
             "inner.GameEnd": { "$exists": true },
             "approval_status": Bson::Null,
@@ -84,20 +132,43 @@ pub(crate) async fn unreviewed(ctx: Context<'_>) -> Result<(), BotError> {
         .await?;
 
     let events: Vec<_> = find.try_collect().await?;
-    if events.is_empty() {
-        ctx.reply("no unreviewed games at this time").await?;
+    let rename_requests: Vec<_> = league(ctx).mongo.collection::<RenameRequest>("rename_requests")
+        .find(doc! {})
+        .sort(doc! { "when": 1 })
+        .limit(10)
+        .await?
+        .try_collect().await?;
+    if events.is_empty() && rename_requests.is_empty() {
+        ctx.reply("no unreviewed games or renames at this time").await?;
         return Ok(());
     }
 
     let mut event_lines = Vec::with_capacity(events.len());
     for evt in events {
-        event_lines.push(format!("#{} - {}", evt._id, evt.short_summary(&ctx.data().mongo).await?));
+        event_lines.push(format!("#{} - {} ({})", evt._id, evt.short_summary(&league(ctx).mongo).await?,
+                                 league(ctx).review_policy.approval_progress(&evt.approvals)));
+    }
+    if event_lines.is_empty() {
+        event_lines.push(String::from("no unreviewed games at this time"));
+    }
+
+    let mut embed = base_embed(ctx)
+        .description(event_lines.into_iter().join("\n"))
+        .footer(CreateEmbedFooter::new("only showing earliest 10 unreviewed games and renames"));
+
+    if !rename_requests.is_empty() {
+        let mut rename_lines = Vec::with_capacity(rename_requests.len());
+        for request in rename_requests {
+            let player = try_lookup_player(&league(ctx).mongo, SystemID(request._id)).await?
+                .expect("player asking for rename DNE");
+            rename_lines.push(format!("{} wants to be {} (<t:{}:R>)",
+                                      player.reference_no_discord(), request.new_username, request.when.timestamp()));
+        }
+        embed = embed.field("pending renames", rename_lines.join("\n"), false);
     }
 
     ctx.send(CreateReply::default()
-        .embed(base_embed(ctx)
-            .description(event_lines.into_iter().join("\n"))
-            .footer(CreateEmbedFooter::new("only showing earliest 10 unreviewed games")))
+        .embed(embed)
         .reply(true)).await?;
 
     Ok(())
@@ -111,7 +182,7 @@ pub(crate) async fn force_register(
     #[description = "username to give them"] victim: Option<User>,
 ) -> Result<(), BotError> {
     if victim.is_some() {
-        match try_lookup_player(&ctx.data().mongo, DiscordID(victim.as_ref().unwrap().id.get())).await? {
+        match try_lookup_player(&league(ctx).mongo, DiscordID(victim.as_ref().unwrap().id.get())).await? {
             Some(player) => {
                 ctx.reply(
                     format!("cannot bind that discord user to a second player (currently bound to user {})",
@@ -123,14 +194,30 @@ pub(crate) async fn force_register(
         };
     }
 
-    if try_lookup_player(&ctx.data().mongo, Username(&*username)).await?.is_some() {
+    if try_lookup_player(&league(ctx).mongo, Username(&*username)).await?.is_some() {
+        ctx.reply(format!("user by name {username} already exists")).await?;
+        return Ok(());
+    }
+
+    // someone who plays in another league joins this one as who they already are
+    let known = match victim.as_ref() {
+        Some(victim) => league(ctx).store.identities.by_discord(victim.id.get()).await?,
+        None => league(ctx).store.identities.by_username(&username).await?,
+    };
+    if let Some(identity) = known {
+        let joined = register_user(&league(ctx).processor, Some(identity._id), victim.as_ref(), identity.username).await?;
+        ctx.reply(format!("ok, {} joined this league", joined.reference_no_discord())).await?;
+        return Ok(());
+    }
+    // the name may still belong to someone in another league
+    if victim.is_some() && league(ctx).store.identities.by_username(&username).await?.is_some() {
         ctx.reply(format!("user by name {username} already exists")).await?;
         return Ok(());
     }
 
     // no username validation lmao
 
-    let new_player = register_user(&ctx.data().mongo, victim.as_ref(), username).await?;
+    let new_player = register_user(&league(ctx).processor, None, victim.as_ref(), username).await?;
 
     ctx.reply(format!("ok, new user {} created", new_player.reference_no_discord())).await?;
     Ok(())
@@ -141,11 +228,11 @@ pub(crate) async fn force_register(
 )]
 pub(crate) async fn penalize(
     ctx: Context<'_>,
-    #[description = "ID of player to penalize"] target: PlayerID,
+    #[description = "ID of player to penalize"] #[autocomplete = "autocomplete_player"] target: PlayerID,
     #[description = "amount of true rating to take"] amount: f64,
     #[description = "reason you're doing this"] reason: String,
 ) -> Result<(), BotError> {
-    let victim = match try_lookup_player(&ctx.data().mongo, SystemID(target)).await? {
+    let victim = match try_lookup_player(&league(ctx).mongo, SystemID(target)).await? {
         None => {
             ctx.reply(":x: i don't know who that is").await?;
             return Ok(());
@@ -177,32 +264,25 @@ pub(crate) async fn penalize(
         Some(ixn) => ixn.create_response(ctx.http(), CreateInteractionResponse::Acknowledge).await?
     };
 
-    let responsible_moderator = try_lookup_player(&ctx.data().mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
+    let responsible_moderator = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
 
-    let LeagueInfo { available_event_number, .. } = ctx.data().mongo
-        .collection::<LeagueInfo>("league_info")
-        .find_one_and_update(
-            doc! {},
-            doc! { "$inc": { "available_event_number": 1, } })
-        .await?
-        .expect("league_info struct missing");
-
-    ctx.data().mongo.collection::<StandingEvent>("events").insert_one(StandingEvent {
-        _id: available_event_number,
+    let StandingEvent { _id: available_event_number, .. } = league(ctx).store.append_event(move |event_number| StandingEvent {
+        _id: event_number,
         approval_status: Some(ApprovalStatus {
             approved: true,
             reviewer: Some(responsible_moderator._id),
         }),
+        approvals: vec![],
         inner: Penalty {
             victims: vec![target],
             delta_rating: -amount,
-            reason,
+            reason: reason.clone(),
         },
         when: Utc::now(),
     }).await?;
 
     ctx.reply(format!("ok, this is event number {available_event_number} and will take effect as the approve pointer moves forward")).await?;
-    advance_approve_pointer(ctx.data(), None).await?;
+    league(ctx).processor.advance(None).await?;
 
     Ok(())
 }
@@ -215,33 +295,41 @@ pub(crate) async fn lb_blacklist(ctx: Context<'_>) -> Result<(), BotError> {
     Ok(())
 }
 
+/// rewrite the whole blacklist, which also upgrades any entries stored as bare player IDs
+async fn set_blacklist(mongo: &Database, entries: &[BlacklistEntry]) -> Result<(), BotError> {
+    mongo.collection::<LeagueInfo>("league_info")
+        .update_one(doc! {}, doc! { "$set": { "leaderboard_blacklist": bson::to_bson(entries)? } })
+        .await?;
+
+    Ok(())
+}
+
 /// league moderators: see who is leaderboard blacklisted
 #[poise::command(prefix_command, slash_command, check = is_league_moderator)]
 pub(crate) async fn list(ctx: Context<'_>) -> Result<(), BotError> {
-    let LeagueInfo { leaderboard_blacklist, .. } = ctx.data().mongo
+    let LeagueInfo { leaderboard_blacklist, .. } = league(ctx).mongo
         .collection::<LeagueInfo>("league_info")
         .find_one(doc! {})
         .await?
         .expect("league_info struct missing");
 
+    let leaderboard_blacklist = leaderboard_blacklist.into_iter()
+        .filter(BlacklistEntry::is_active)
+        .collect_vec();
+
     if leaderboard_blacklist.is_empty() {
         ctx.reply("nobody is blacklisted right now").await?;
         return Ok(())
     }
 
     let mut desc = Vec::with_capacity(leaderboard_blacklist.len());
-    for player_id in leaderboard_blacklist {
-        desc.push(
-            format!("* {}",
-                    try_lookup_player(&ctx.data().mongo, SystemID(player_id))
-                        .await?.unwrap()
-                        .short_summary()));
+    for entry in leaderboard_blacklist {
+        desc.push(format!("* {}", entry.describe(&league(ctx).mongo).await?));
     };
 
-    ctx.send(CreateReply::default()
-        .embed(base_embed(ctx)
-            .description(desc.join("\n"))))
-        .await?;
+    EmbedLinePaginator::new(desc.into_iter().map(String::into_boxed_str).collect_vec(), PaginatorOptions::new()
+        .max_lines(NonZeroUsize::new(10).unwrap())
+    ).run(ctx).await?;
     Ok(())
 }
 
@@ -249,9 +337,11 @@ pub(crate) async fn list(ctx: Context<'_>) -> Result<(), BotError> {
 #[poise::command(prefix_command, slash_command, check = is_league_moderator)]
 pub(crate) async fn add(
     ctx: Context<'_>,
-    #[description = "ID of player to blacklist"] target: PlayerID,
+    #[description = "ID of player to blacklist"] #[autocomplete = "autocomplete_player"] target: PlayerID,
+    #[description = "reason you're doing this"] reason: String,
+    #[description = "lift automatically after this many days"] days: Option<u32>,
 ) -> Result<(), BotError> {
-    let summary = match try_lookup_player(&ctx.data().mongo, SystemID(target)).await? {
+    let summary = match try_lookup_player(&league(ctx).mongo, SystemID(target)).await? {
         None => {
             ctx.reply("can't find that user; who is that?").await?;
             return Ok(());
@@ -259,23 +349,37 @@ pub(crate) async fn add(
         Some(user) => user.short_summary()
     };
 
-    let LeagueInfo { leaderboard_blacklist, .. } = ctx.data().mongo
+    let league_info = league(ctx).mongo
         .collection::<LeagueInfo>("league_info")
         .find_one(doc! {})
         .await?
         .expect("league_info struct missing");
 
-    if leaderboard_blacklist.contains(&target) {
+    if league_info.blacklist_entry(target).is_some() {
         ctx.reply("that person is already blacklisted").await?;
         return Ok(());
     }
 
-    ctx.data().mongo
-        .collection::<LeagueInfo>("league_info")
-        .update_one(doc! {}, doc! {"$addToSet": {"leaderboard_blacklist": target}})
-        .await?;
-
-    ctx.reply(format!("ok, {} now blacklisted from leaderboard", summary)).await?;
+    let now = Utc::now();
+    let expires = days.map(|days| now + TimeDelta::days(days as i64));
+    let responsible_moderator = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?;
+
+    let mut entries = league_info.leaderboard_blacklist;
+    // drop any lapsed entry the scheduler hasn't gotten to yet
+    entries.retain(|entry| entry.player != target);
+    entries.push(BlacklistEntry {
+        player: target,
+        added_by: responsible_moderator.map(|moderator| moderator._id),
+        reason: Some(reason),
+        when: Some(now),
+        expires,
+    });
+    set_blacklist(&league(ctx).mongo, &entries).await?;
+
+    ctx.reply(format!("ok, {} now blacklisted from leaderboard{}", summary, match expires {
+        None => String::from(""),
+        Some(expires) => format!(" until <t:{}:f>", expires.timestamp()),
+    })).await?;
     Ok(())
 }
 
@@ -283,9 +387,9 @@ pub(crate) async fn add(
 #[poise::command(prefix_command, slash_command, check = is_league_moderator)]
 pub(crate) async fn remove(
     ctx: Context<'_>,
-    #[description = "ID of player to blacklist"] target: PlayerID,
+    #[description = "ID of player to blacklist"] #[autocomplete = "autocomplete_player"] target: PlayerID,
 ) -> Result<(), BotError> {
-    let summary = match try_lookup_player(&ctx.data().mongo, SystemID(target)).await? {
+    let summary = match try_lookup_player(&league(ctx).mongo, SystemID(target)).await? {
         None => {
             ctx.reply("can't find that user; who is that?").await?;
             return Ok(());
@@ -293,22 +397,377 @@ pub(crate) async fn remove(
         Some(user) => user.short_summary()
     };
 
-    let LeagueInfo { leaderboard_blacklist, .. } = ctx.data().mongo
+    let league_info = league(ctx).mongo
         .collection::<LeagueInfo>("league_info")
         .find_one(doc! {})
         .await?
         .expect("league_info struct missing");
 
-    if !leaderboard_blacklist.contains(&target) {
+    if league_info.blacklist_entry(target).is_none() {
         ctx.reply("that person isn't blacklisted though").await?;
         return Ok(());
     }
 
-    ctx.data().mongo
-        .collection::<LeagueInfo>("league_info")
-        .update_one(doc! {}, doc! {"$pull": {"leaderboard_blacklist": target}})
-        .await?;
+    let mut entries = league_info.leaderboard_blacklist;
+    entries.retain(|entry| entry.player != target);
+    set_blacklist(&league(ctx).mongo, &entries).await?;
 
     ctx.reply(format!("ok, {} no longer blacklisted from leaderboard", summary)).await?;
     Ok(())
 }
+
+/// League moderators: bar someone from posting, playing in, or signing off on games for a while
+#[poise::command(prefix_command, slash_command, check = is_league_moderator, check = has_system_account
+)]
+pub(crate) async fn suspend(
+    ctx: Context<'_>,
+    #[description = "ID of player to suspend"] #[autocomplete = "autocomplete_player"] target: PlayerID,
+    #[description = "length of suspension in days"] days: u32,
+    #[description = "reason you're doing this"] reason: String,
+) -> Result<(), BotError> {
+    let victim = match try_lookup_player(&league(ctx).mongo, SystemID(target)).await? {
+        None => {
+            ctx.reply(":x: i don't know who that is").await?;
+            return Ok(());
+        }
+        Some(victim) => victim
+    };
+
+    if days == 0 {
+        ctx.reply(":x: a suspension has to last at least a day").await?;
+        return Ok(());
+    }
+
+    if let Some(suspension) = victim.active_suspension() {
+        ctx.reply(format!(":x: {} is already suspended until <t:{}:f>; lift that first to change it",
+                          victim.short_summary(), suspension.until.timestamp())).await?;
+        return Ok(());
+    }
+
+    let responsible_moderator = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
+
+    let now = Utc::now();
+    let until = now + TimeDelta::days(days as i64);
+
+    let event = league(ctx).processor.record(
+        vec![Change::Suspend { player: target, until, reason: reason.clone() }],
+        move |available_event_number| StandingEvent {
+            _id: available_event_number,
+            approval_status: Some(ApprovalStatus {
+                approved: true,
+                reviewer: Some(responsible_moderator._id),
+            }),
+            approvals: vec![],
+            inner: Suspend {
+                victims: vec![target],
+                until,
+                reason: reason.clone(),
+            },
+            when: now,
+        },
+    ).await?;
+
+    ctx.reply(format!("ok, {} suspended until <t:{}:f> (event number {})",
+                      victim.short_summary(), until.timestamp(), event._id)).await?;
+    Ok(())
+}
+
+/// League moderators: lift someone's suspension early
+#[poise::command(prefix_command, slash_command, check = is_league_moderator, check = has_system_account
+)]
+pub(crate) async fn unsuspend(
+    ctx: Context<'_>,
+    #[description = "ID of player to unsuspend"] #[autocomplete = "autocomplete_player"] target: PlayerID,
+    #[description = "reason you're doing this"] reason: String,
+) -> Result<(), BotError> {
+    let victim = match try_lookup_player(&league(ctx).mongo, SystemID(target)).await? {
+        None => {
+            ctx.reply(":x: i don't know who that is").await?;
+            return Ok(());
+        }
+        Some(victim) => victim
+    };
+
+    if victim.active_suspension().is_none() {
+        ctx.reply(":x: that person isn't suspended though").await?;
+        return Ok(());
+    }
+
+    let responsible_moderator = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
+
+    let event = league(ctx).processor.record(
+        vec![Change::Unsuspend { players: vec![target] }],
+        move |available_event_number| StandingEvent {
+            _id: available_event_number,
+            approval_status: Some(ApprovalStatus {
+                approved: true,
+                reviewer: Some(responsible_moderator._id),
+            }),
+            approvals: vec![],
+            inner: Unsuspend {
+                victims: vec![target],
+                reason: reason.clone(),
+            },
+            when: Utc::now(),
+        },
+    ).await?;
+
+    ctx.reply(format!("ok, {} is no longer suspended (event number {})", victim.short_summary(), event._id)).await?;
+    Ok(())
+}
+
+/// League moderators: fold a duplicate account into another and replay the record
+#[poise::command(prefix_command, slash_command, check = is_league_moderator, check = has_system_account
+)]
+pub(crate) async fn merge_players(
+    ctx: Context<'_>,
+    #[description = "ID of the duplicate player, which will stop existing"] #[autocomplete = "autocomplete_player"] from: PlayerID,
+    #[description = "ID of the player to keep"] #[autocomplete = "autocomplete_player"] into: PlayerID,
+) -> Result<(), BotError> {
+    if from == into {
+        ctx.reply(":x: can't merge a player into themselves").await?;
+        return Ok(());
+    }
+
+    let (merged, kept) = match (
+        try_lookup_player(&league(ctx).mongo, SystemID(from)).await?,
+        try_lookup_player(&league(ctx).mongo, SystemID(into)).await?,
+    ) {
+        (Some(merged), Some(kept)) => (merged, kept),
+        _ => {
+            ctx.reply(":x: i don't know who one of those is").await?;
+            return Ok(());
+        }
+    };
+
+    // placements can't be combined if both accounts sat at the same table
+    if let Some(shared) = league(ctx).mongo.collection::<StandingEvent>("events")
+        .find_one(doc! { "inner.GameEnd.ranking": { "$all": [from, into] } }).await? {
+        ctx.reply(format!(":x: both accounts played in the same game (event number {}); fix that game first", shared._id)).await?;
+        return Ok(());
+    }
+
+    let handle = ctx.send(CreateReply::default()
+        .embed(base_embed(ctx)
+            .description(format!(
+                "**you are merging {} ({}) into {} ({})!** every game, penalty and other event of the first will \
+                belong to the second, the first account will be deleted, and all ratings will be recomputed.\n\
+                please confirm (10 seconds)",
+                merged.short_summary(), merged.reference_no_discord(),
+                kept.short_summary(), kept.reference_no_discord())))
+        .components(vec![
+            CreateActionRow::Buttons(vec![
+                CreateButton::new("merge_players_confirm")
+                    .emoji(ReactionType::Unicode(String::from("✅")))
+            ])
+        ])
+        .reply(true)
+    ).await?;
+
+    match handle.message().await?.await_component_interaction(&ctx.serenity_context().shard)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![String::from("merge_players_confirm")])
+        .timeout(Duration::from_secs(10)).await {
+        None => {
+            ctx.reply("ok, nevermind then").await?;
+            return Ok(());
+        }
+        Some(ixn) => ixn.create_response(ctx.http(), CreateInteractionResponse::Acknowledge).await?
+    };
+
+    let responsible_moderator = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
+
+    // keep whichever suspension runs longer
+    let suspension = match (kept.active_suspension(), merged.active_suspension()) {
+        (Some(kept_suspension), Some(merged_suspension)) => Some(
+            if merged_suspension.until > kept_suspension.until { merged_suspension } else { kept_suspension }),
+        (kept_suspension, merged_suspension) => kept_suspension.or(merged_suspension),
+    }.cloned();
+
+    // everything about the merge lands at once or not at all
+    let merged_username = merged.username.clone();
+    let event = league(ctx).processor.record(
+        vec![Change::Merge { from, into, suspension }],
+        move |available_event_number| StandingEvent {
+            _id: available_event_number,
+            approval_status: Some(ApprovalStatus {
+                approved: true,
+                reviewer: Some(responsible_moderator._id),
+            }),
+            approvals: vec![],
+            inner: MergePlayers {
+                victims: vec![into],
+                merged: from,
+                merged_username: merged_username.clone(),
+            },
+            when: Utc::now(),
+        },
+    ).await?;
+
+    league(ctx).processor.replay().await?;
+
+    ctx.reply(format!("ok, {} merged into {} as event number {}; ratings have been replayed",
+                      merged.reference_no_discord(), kept.reference_no_discord(), event._id)).await?;
+    Ok(())
+}
+
+/// League moderators: approve or reject a player's pending rename
+#[poise::command(prefix_command, slash_command, check = is_league_moderator, check = has_system_account
+)]
+pub(crate) async fn review_rename(
+    ctx: Context<'_>,
+    #[description = "ID of player who asked to be renamed"] #[autocomplete = "autocomplete_player"] target: PlayerID,
+    #[description = "whether to accept or reject this rename"] approved: bool,
+) -> Result<(), BotError> {
+    let responsible_moderator = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
+
+    if let Err(conflict) = league(ctx).review_policy.check_rename_review(responsible_moderator._id, target) {
+        ctx.send(CreateReply::default()
+            .content(conflict.explain())
+            .ephemeral(true)).await?;
+        return Ok(());
+    }
+
+    let rename_requests = league(ctx).mongo.collection::<RenameRequest>("rename_requests");
+    let request = match rename_requests.find_one(doc! { "_id": target }).await? {
+        None => {
+            ctx.reply(":x: that player has no pending rename").await?;
+            return Ok(());
+        }
+        Some(request) => request
+    };
+
+    let player = try_lookup_player(&league(ctx).mongo, SystemID(target)).await?
+        .expect("player asking for rename DNE");
+
+    if !approved {
+        rename_requests.delete_one(doc! { "_id": target }).await?;
+        ctx.reply(format!("ok, rejected rename of {} to {}", player.reference_no_discord(), request.new_username)).await?;
+        return Ok(());
+    }
+
+    // the name may have been taken since it was asked for
+    if try_lookup_player(&league(ctx).mongo, Username(&request.new_username)).await?.is_some() {
+        rename_requests.delete_one(doc! { "_id": target }).await?;
+        ctx.reply(format!(":x: user by name {} already exists now, so this rename was dropped", request.new_username)).await?;
+        return Ok(());
+    }
+
+    // the request goes away with the rename, so it is still pending if recording the rename fails
+    let old_username = player.username.clone();
+    let new_username = request.new_username.clone();
+    let event = league(ctx).processor.record(
+        vec![Change::Rename { player: target, username: request.new_username.clone() }],
+        move |available_event_number| StandingEvent {
+            _id: available_event_number,
+            approval_status: Some(ApprovalStatus {
+                approved: true,
+                reviewer: Some(responsible_moderator._id),
+            }),
+            approvals: vec![],
+            inner: Rename {
+                victims: vec![target],
+                old_username: old_username.clone(),
+                new_username: new_username.clone(),
+            },
+            when: Utc::now(),
+        },
+    ).await?;
+
+    ctx.reply(format!("ok, {} is now {} (event number {})",
+                      player.reference_no_discord(), request.new_username, event._id)).await?;
+    Ok(())
+}
+
+/// League moderators: bind another discord account to a player
+#[poise::command(prefix_command, slash_command, check = is_league_moderator, check = has_system_account
+)]
+pub(crate) async fn link_discord(
+    ctx: Context<'_>,
+    #[description = "ID of player to link to"] #[autocomplete = "autocomplete_player"] target: PlayerID,
+    #[description = "discord account to link"] user: User,
+) -> Result<(), BotError> {
+    let player = match try_lookup_player(&league(ctx).mongo, SystemID(target)).await? {
+        None => {
+            ctx.reply(":x: i don't know who that is").await?;
+            return Ok(());
+        }
+        Some(player) => player
+    };
+
+    if let Some(bound) = try_lookup_player(&league(ctx).mongo, DiscordID(user.id.get())).await? {
+        ctx.reply(format!("cannot bind that discord user to a second player (currently bound to user {})",
+                          bound.reference_no_discord())).await?;
+        return Ok(());
+    }
+
+    let responsible_moderator = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
+
+    let discord_id = user.id.get();
+    let event = league(ctx).processor.record(
+        vec![Change::LinkDiscord { player: target, discord_id }],
+        move |available_event_number| StandingEvent {
+            _id: available_event_number,
+            approval_status: Some(ApprovalStatus {
+                approved: true,
+                reviewer: Some(responsible_moderator._id),
+            }),
+            approvals: vec![],
+            inner: LinkDiscord {
+                victims: vec![target],
+                discord_id,
+            },
+            when: Utc::now(),
+        },
+    ).await?;
+
+    ctx.reply(format!("ok, {} now linked to {} (event number {})",
+                      user.mention(), player.reference_no_discord(), event._id)).await?;
+    Ok(())
+}
+
+/// League moderators: unbind a discord account from a player
+#[poise::command(prefix_command, slash_command, check = is_league_moderator, check = has_system_account
+)]
+pub(crate) async fn unlink_discord(
+    ctx: Context<'_>,
+    #[description = "ID of player to unlink from"] #[autocomplete = "autocomplete_player"] target: PlayerID,
+    #[description = "discord account to unlink"] user: User,
+) -> Result<(), BotError> {
+    let player = match try_lookup_player(&league(ctx).mongo, SystemID(target)).await? {
+        None => {
+            ctx.reply(":x: i don't know who that is").await?;
+            return Ok(());
+        }
+        Some(player) => player
+    };
+
+    if !player.discord_ids.contains(&user.id.get()) {
+        ctx.reply(format!(":x: {} isn't linked to that player", user.mention())).await?;
+        return Ok(());
+    }
+
+    let responsible_moderator = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
+
+    let discord_id = user.id.get();
+    let event = league(ctx).processor.record(
+        vec![Change::UnlinkDiscord { player: target, discord_id }],
+        move |available_event_number| StandingEvent {
+            _id: available_event_number,
+            approval_status: Some(ApprovalStatus {
+                approved: true,
+                reviewer: Some(responsible_moderator._id),
+            }),
+            approvals: vec![],
+            inner: UnlinkDiscord {
+                victims: vec![target],
+                discord_id,
+            },
+            when: Utc::now(),
+        },
+    ).await?;
+
+    ctx.reply(format!("ok, {} no longer linked to {} (event number {})",
+                      user.mention(), player.reference_no_discord(), event._id)).await?;
+    Ok(())
+}
@@ -0,0 +1,184 @@
+use crate::commands::ewar::user::try_lookup_player;
+use crate::commands::ewar::user::UserLookupType::SystemID;
+use crate::model::{EventNumber, GameID, LeagueInfo, Player, PlayerID, StandingEvent};
+use crate::util::base_embed;
+use crate::util::league::league;
+use crate::{BotError, Context};
+use bson::doc;
+use chrono::TimeDelta;
+use futures::TryStreamExt;
+use poise::CreateReply;
+use serde::Deserialize;
+
+#[derive(Deserialize)]
+struct GameRecord {
+    #[serde(rename = "_id")]
+    event_number: EventNumber,
+    game_id: GameID,
+    player: PlayerID,
+    value: f64,
+}
+
+#[derive(Deserialize)]
+struct BusiestDay {
+    player: PlayerID,
+    day: String,
+    games: i64,
+}
+
+#[derive(Deserialize)]
+struct GameRecordsAggregate {
+    longest: Vec<GameRecord>,
+    biggest_table: Vec<GameRecord>,
+    biggest_gain: Vec<GameRecord>,
+    biggest_loss: Vec<GameRecord>,
+    biggest_upset: Vec<GameRecord>,
+    busiest_day: Vec<BusiestDay>,
+}
+
+async fn name_of(ctx: Context<'_>, player_id: PlayerID) -> Result<Box<str>, BotError> {
+    Ok(try_lookup_player(&league(ctx).mongo, SystemID(player_id)).await?
+        .expect("record holder DNE")
+        .short_summary())
+}
+
+/// See league-wide all-time records
+#[poise::command(prefix_command, slash_command)]
+pub(crate) async fn records(ctx: Context<'_>) -> Result<(), BotError> {
+    ctx.defer().await?;
+
+    let league_info = league(ctx).mongo
+        .collection::<LeagueInfo>("league_info")
+        .find_one(doc! {})
+        .await?
+        .expect("league_info struct missing");
+    let hidden = league_info.leaderboard_blacklist.iter()
+        .filter(|entry| entry.is_active())
+        .map(|entry| entry.player)
+        .collect::<Vec<_>>();
+
+    let players = league(ctx).mongo.collection::<Player>("players");
+    let highest = players.find_one(doc! { "peak": { "$ne": null }, "_id": { "$nin": &hidden } })
+        .sort(doc! { "peak.rating": -1 })
+        .await?;
+    let lowest = players.find_one(doc! { "trough": { "$ne": null }, "_id": { "$nin": &hidden } })
+        .sort(doc! { "trough.rating": 1 })
+        .await?;
+
+    // one game's standout value and who it belongs to
+    let per_player = |values: &str, sort: i32| vec![
+        doc! {"$unwind": {"path": format!("$inner.GameEnd.{values}"), "includeArrayIndex": "index"}},
+        doc! {"$sort": {format!("inner.GameEnd.{values}"): sort}},
+        doc! {"$limit": 1},
+        doc! {"$project": {
+            "game_id": "$inner.GameEnd.game_id",
+            "player": {"$arrayElemAt": ["$inner.GameEnd.ranking", "$index"]},
+            "value": format!("$inner.GameEnd.{values}"),
+        }},
+    ];
+
+    let game_records = league(ctx).mongo.collection::<StandingEvent>("events").aggregate(vec![
+        doc! {"$match": {"approval_status.approved": true, "inner.GameEnd": {"$exists": true}}},
+        doc! {
+            "$facet": {
+                "longest": [
+                    {"$sort": {"inner.GameEnd.length": -1}},
+                    {"$limit": 1},
+                    {"$project": {
+                        "game_id": "$inner.GameEnd.game_id",
+                        "player": {"$arrayElemAt": ["$inner.GameEnd.ranking", 0]},
+                        "value": {"$toDouble": "$inner.GameEnd.length"},
+                    }},
+                ],
+                "biggest_table": [
+                    {"$addFields": {"size": {"$size": "$inner.GameEnd.ranking"}}},
+                    {"$sort": {"size": -1}},
+                    {"$limit": 1},
+                    {"$project": {
+                        "game_id": "$inner.GameEnd.game_id",
+                        "player": {"$arrayElemAt": ["$inner.GameEnd.ranking", 0]},
+                        "value": {"$toDouble": "$size"},
+                    }},
+                ],
+                "biggest_gain": per_player("deltas", -1),
+                "biggest_loss": per_player("deltas", 1),
+                "biggest_upset": [
+                    {"$match": {"inner.GameEnd.expected.1": {"$exists": true}}},
+                    {"$addFields": {"margin": {"$subtract": [
+                        {"$max": "$inner.GameEnd.expected"},
+                        {"$arrayElemAt": ["$inner.GameEnd.expected", 0]},
+                    ]}}},
+                    {"$sort": {"margin": -1}},
+                    {"$limit": 1},
+                    {"$project": {
+                        "game_id": "$inner.GameEnd.game_id",
+                        "player": {"$arrayElemAt": ["$inner.GameEnd.ranking", 0]},
+                        "value": {"$arrayElemAt": ["$inner.GameEnd.expected", 0]},
+                    }},
+                ],
+                "busiest_day": [
+                    {"$unwind": "$inner.GameEnd.ranking"},
+                    {"$group": {
+                        "_id": {
+                            "player": "$inner.GameEnd.ranking",
+                            "day": {"$dateToString": {"format": "%Y-%m-%d", "date": "$when"}},
+                        },
+                        "games": {"$sum": 1},
+                    }},
+                    {"$sort": {"games": -1, "_id.day": 1}},
+                    {"$limit": 1},
+                    {"$project": {"_id": 0, "player": "$_id.player", "day": "$_id.day", "games": 1}},
+                ],
+            }
+        },
+    ]).with_type::<GameRecordsAggregate>().await?
+        .try_next().await?
+        .expect("facet always gives a document");
+
+    let mut lines = Vec::new();
+
+    if let Some(Player { _id, peak: Some(peak), .. }) = highest {
+        lines.push(format!("**highest rating ever:** {} by {}", peak.describe(), name_of(ctx, _id).await?));
+    }
+    if let Some(Player { _id, trough: Some(trough), .. }) = lowest {
+        lines.push(format!("**lowest established rating:** {} by {}", trough.describe(), name_of(ctx, _id).await?));
+    }
+    if let Some(record) = game_records.longest.first() {
+        let length = TimeDelta::seconds(record.value as i64);
+        lines.push(format!("**longest game:** {:02}:{:02}, game ID {} (event {}), won by {}",
+                           length.num_minutes(), length.num_seconds() % 60,
+                           record.game_id, record.event_number, name_of(ctx, record.player).await?));
+    }
+    if let Some(record) = game_records.biggest_table.first() {
+        lines.push(format!("**biggest table:** {} players, game ID {} (event {}), won by {}",
+                           record.value, record.game_id, record.event_number, name_of(ctx, record.player).await?));
+    }
+    if let Some(record) = game_records.busiest_day.first() {
+        lines.push(format!("**most games in a day:** {} by {} on {}",
+                           record.games, name_of(ctx, record.player).await?, record.day));
+    }
+    if let Some(record) = game_records.biggest_gain.first() {
+        lines.push(format!("**biggest single-game gain:** {:+.2} by {}, game ID {} (event {})",
+                           record.value, name_of(ctx, record.player).await?, record.game_id, record.event_number));
+    }
+    if let Some(record) = game_records.biggest_loss.first() {
+        lines.push(format!("**biggest single-game loss:** {:+.2} by {}, game ID {} (event {})",
+                           record.value, name_of(ctx, record.player).await?, record.game_id, record.event_number));
+    }
+    if let Some(record) = game_records.biggest_upset.first() {
+        lines.push(format!("**biggest upset:** {} won with a {:.2}% chance, game ID {} (event {})",
+                           name_of(ctx, record.player).await?, record.value * 100.0, record.game_id, record.event_number));
+    }
+
+    if lines.is_empty() {
+        ctx.reply("no records yet; play some games").await?;
+        return Ok(());
+    }
+
+    ctx.send(CreateReply::default()
+        .embed(base_embed(ctx)
+            .description(lines.join("\n")))
+        .reply(true)).await?;
+
+    Ok(())
+}
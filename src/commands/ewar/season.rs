@@ -0,0 +1,170 @@
+use crate::commands::ewar::user::try_lookup_player;
+use crate::commands::ewar::user::UserLookupType::DiscordID;
+use crate::model::StandingEventInner::SoftReset;
+use crate::model::{ApprovalStatus, EventNumber, LeagueInfo, Player, PlayerID, SeasonID, SeasonStanding, StandingEvent};
+use crate::store::{Change, Fence, Store};
+use crate::util::base_embed;
+use crate::util::checks::{has_system_account, is_league_moderator};
+use crate::util::constants::{SOFT_RESET_DELTA_DEVIATION, SOFT_RESET_PULL};
+use crate::util::league::league;
+use crate::util::rating::RatingExtra;
+use crate::{BotError, Context};
+use chrono::Utc;
+use itertools::Itertools;
+use poise::CreateReply;
+use serenity::all::{CreateActionRow, CreateButton, CreateInteractionResponse, ReactionType};
+use skillratings::trueskill::TrueSkillRating;
+use std::time::Duration;
+
+impl SeasonStanding {
+    pub(crate) fn rating_struct(&self) -> TrueSkillRating {
+        TrueSkillRating { rating: self.rating, uncertainty: self.deviation }
+    }
+
+    /// where this player finished, for listing alongside other seasons
+    pub(crate) fn describe_finish(&self) -> String {
+        match self.place {
+            Some(place) => format!("#{place} ({})", self.rating_struct().format_rating()),
+            None => format!("unplaced ({})", self.rating_struct().format_rating()),
+        }
+    }
+}
+
+/// League moderators: archive the leaderboard and start a new season with softened ratings
+#[poise::command(prefix_command, slash_command, check = is_league_moderator, check = has_system_account
+)]
+pub(crate) async fn close_season(
+    ctx: Context<'_>,
+    #[description = "How far back toward the default rating to pull everyone, 0 to 1"] pull: Option<f64>,
+    #[description = "Deviation to add to everyone"] delta_deviation: Option<f64>,
+) -> Result<(), BotError> {
+    let pull = pull.unwrap_or(*SOFT_RESET_PULL);
+    let delta_deviation = delta_deviation.unwrap_or(*SOFT_RESET_DELTA_DEVIATION);
+    if !(0.0..=1.0).contains(&pull) || !delta_deviation.is_finite() || delta_deviation < 0.0 {
+        ctx.reply(":x: pull must be between 0 and 1 and deviation can't go down").await?;
+        return Ok(());
+    }
+
+    ctx.defer().await?;
+
+    // checked again when the season actually closes; this is so nobody confirms a close that can't happen
+    let first_unreviewed = league(ctx).processor.advance(None).await?;
+    let LeagueInfo { available_event_number, .. } = league(ctx).store.league_info.league_info().await?;
+    if first_unreviewed < available_event_number {
+        ctx.reply(format!(":x: event number {first_unreviewed} still needs review; clear the review queue first")).await?;
+        return Ok(());
+    }
+
+    let season = league(ctx).store.league_info.current_season().await?;
+
+    let handle = ctx.send(CreateReply::default()
+        .embed(base_embed(ctx)
+            .description(format!(
+                "**you are closing season {season}!** the leaderboard will be archived, then every rating will be \
+                pulled {:.0}% of the way to the default and gain {delta_deviation} deviation.\n\
+                please confirm (10 seconds)",
+                pull * 100.0)))
+        .components(vec![
+            CreateActionRow::Buttons(vec![
+                CreateButton::new("close_season_confirm")
+                    .emoji(ReactionType::Unicode(String::from("✅")))
+            ])
+        ])
+        .reply(true)
+    ).await?;
+
+    match handle.message().await?.await_component_interaction(&ctx.serenity_context().shard)
+        .author_id(ctx.author().id)
+        .custom_ids(vec![String::from("close_season_confirm")])
+        .timeout(Duration::from_secs(10)).await {
+        None => {
+            ctx.reply("ok, nevermind then").await?;
+            return Ok(());
+        }
+        Some(ixn) => ixn.create_response(ctx.http(), CreateInteractionResponse::Acknowledge).await?
+    };
+
+    let responsible_moderator = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?.unwrap();
+
+    let closed = league(ctx).processor.close_season(responsible_moderator._id, pull, delta_deviation).await?;
+    match closed {
+        SeasonClose::Closed { season, event } => {
+            ctx.reply(format!("ok, season {season} is archived and season {} has begun (event number {})",
+                              season + 1, event._id)).await?;
+        }
+        SeasonClose::Unreviewed(first_unreviewed) => {
+            ctx.reply(format!(":x: event number {first_unreviewed} came in and still needs review; clear the review queue first")).await?;
+        }
+    }
+    Ok(())
+}
+
+/// how closing a season went
+pub(crate) enum SeasonClose {
+    Closed { season: SeasonID, event: StandingEvent },
+    // an event still waiting on review would be left out of the archive
+    Unreviewed(EventNumber),
+}
+
+/// archive the standings and soft-reset everyone, as long as every event has been processed. the rating
+/// processor runs this under its lease, so no ratings move between reading them and the reset
+pub(crate) async fn close_season_inner(
+    store: &Store,
+    fence: &mut dyn Fence,
+    reviewer: PlayerID,
+    pull: f64,
+    delta_deviation: f64,
+) -> Result<SeasonClose, BotError> {
+    let league_info = store.league_info.league_info().await?;
+    if league_info.first_unreviewed_event_number < league_info.available_event_number {
+        return Ok(SeasonClose::Unreviewed(league_info.first_unreviewed_event_number));
+    }
+
+    let season = store.league_info.current_season().await?;
+    let players = store.players.players().await?;
+    let standings = season_standings(&players, &league_info);
+
+    // the archive and the reset that follows it land together
+    let victims = players.iter().map(|player| player._id).collect_vec();
+    let event = store.record(
+        vec![Change::ArchiveSeason { season, standings }],
+        move |event_number| StandingEvent {
+            _id: event_number,
+            approval_status: Some(ApprovalStatus {
+                approved: true,
+                reviewer: Some(reviewer),
+            }),
+            approvals: vec![],
+            inner: SoftReset {
+                victims: victims.clone(),
+                season,
+                pull,
+                delta_deviation,
+            },
+            when: Utc::now(),
+        },
+        fence,
+    ).await?;
+
+    Ok(SeasonClose::Closed { season, event })
+}
+
+/// everyone who played and isn't blacklisted, in leaderboard order; provisional players go unplaced
+fn season_standings(players: &[Player], league_info: &LeagueInfo) -> Vec<SeasonStanding> {
+    let mut place = 0;
+    players.iter()
+        .filter(|player| player.last_played.is_some() && league_info.blacklist_entry(player._id).is_none())
+        .sorted_by(|a, b| b.rating_struct().leaderboard_rating()
+            .total_cmp(&a.rating_struct().leaderboard_rating()))
+        .map(|player| SeasonStanding {
+            player: player._id,
+            username: player.username.clone(),
+            rating: player.rating,
+            deviation: player.deviation,
+            place: (!player.rating_struct().is_provisional()).then(|| {
+                place += 1;
+                place
+            }),
+        })
+        .collect_vec()
+}
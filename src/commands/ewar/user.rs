@@ -1,13 +1,18 @@
 use std::convert::identity;
 use crate::commands::ewar::user::UserLookupType::{DiscordID, SystemID, Username};
 use crate::model::StandingEventInner::{InactivityDecay, JoinLeague};
-use crate::model::{ApprovalStatus, GameID, LeagueInfo, Player, PlayerID, StandingEvent};
-use crate::util::constants::{DEFAULT_RATING, PROVISIONAL_DEVIATION_THRESHOLD};
-use crate::util::rating::RatingExtra;
+use crate::model::{ApprovalStatus, EventNumber, GameID, LeagueInfo, Player, PlayerID, RatingMark, RenameRequest, Season, StandingEvent, Unlock, VICTIM_VARIANTS};
+use crate::store::BuildRegistration;
+use crate::util::autocomplete::{autocomplete_player, autocomplete_username, search_players};
+use crate::util::checks::{_is_league_moderator, has_system_account};
+use crate::util::constants::{DEFAULT_RATING, PROVISIONAL_DEVIATION_THRESHOLD, RENAME_COOLDOWN_DAYS};
+use crate::util::league::league;
+use crate::util::processor::RatingProcessor;
+use crate::util::rating::{expected_outcome, RatingExtra};
 use crate::util::{base_embed, remove_markdown};
 use crate::{BotError, Context};
-use bson::doc;
-use chrono::Utc;
+use bson::{doc, Document};
+use chrono::{NaiveDate, TimeDelta, Utc};
 use futures::TryStreamExt;
 use itertools::Itertools;
 use mongodb::Database;
@@ -38,16 +43,71 @@ struct WinLossAggregate {
     wins: GameID,
     losses: GameID,
 }
+
+/// which games count toward records and stats
+struct RecordFilter {
+    since: Option<chrono::DateTime<Utc>>,
+    until: Option<chrono::DateTime<Utc>>,
+    include_pending: bool,
+}
+
+impl RecordFilter {
+    /// dates are whole days in UTC, both ends inclusive
+    fn parse(since: Option<String>, until: Option<String>, include_pending: Option<bool>) -> Result<Self, String> {
+        let parse_day = |day: &str| NaiveDate::parse_from_str(day.trim(), "%Y-%m-%d")
+            .map(|day| day.and_hms_opt(0, 0, 0).unwrap().and_utc())
+            .map_err(|_| format!(":x: can't read date {}; dates look like 2024-12-31", remove_markdown(day)));
+
+        Ok(Self {
+            since: since.as_deref().map(parse_day).transpose()?,
+            until: until.as_deref().map(parse_day).transpose()?.map(|day| day + TimeDelta::days(1)),
+            include_pending: include_pending.unwrap_or(false),
+        })
+    }
+
+    /// conditions on events to add to a $match; rejected games never count
+    fn match_doc(&self) -> Document {
+        let mut ret = if self.include_pending {
+            doc! { "$or": [{ "approval_status.approved": true }, { "approval_status": null }] }
+        } else {
+            doc! { "approval_status.approved": true }
+        };
+
+        let mut when = Document::new();
+        if let Some(since) = self.since {
+            when.insert("$gte", bson::DateTime::from_chrono(since));
+        }
+        if let Some(until) = self.until {
+            when.insert("$lt", bson::DateTime::from_chrono(until));
+        }
+        if !when.is_empty() {
+            ret.insert("when", when);
+        }
+
+        ret
+    }
+
+    fn describe(&self) -> String {
+        let mut parts = vec![String::from(if self.include_pending { "approved and pending games" } else { "approved games" })];
+        if let Some(since) = self.since {
+            parts.push(format!("since <t:{}:d>", since.timestamp()));
+        }
+        if let Some(until) = self.until {
+            parts.push(format!("before <t:{}:d>", until.timestamp()));
+        }
+
+        parts.join(" ")
+    }
+}
+
 /// shared postlude to every lookup method; just show the user
-async fn display_lookup_result(ctx: Context<'_>, looked_up: Player) -> Result<(), BotError> {
-    let events = ctx.data().mongo.collection::<StandingEvent>("events")
+async fn display_lookup_result(ctx: Context<'_>, looked_up: Player, filter: RecordFilter) -> Result<(), BotError> {
+    let events = league(ctx).mongo.collection::<StandingEvent>("events")
         .find(doc! {
-            "$or": [
-                { "inner.Penalty.victims": looked_up._id },
-                { "inner.InactivityDecay.victims": looked_up._id },
-                { "inner.JoinLeague.victims": looked_up._id },
-                { "inner.GameEnd.ranking": looked_up._id },
-            ]
+            "$or": VICTIM_VARIANTS.iter()
+                .map(|variant| doc! { format!("inner.{variant}.victims"): looked_up._id })
+                .chain([doc! { "inner.GameEnd.ranking": looked_up._id }])
+                .collect_vec()
         }).sort(doc! {"_id": -1})
         .limit(10).await?
         .try_collect::<Vec<_>>().await?;
@@ -64,13 +124,13 @@ async fn display_lookup_result(ctx: Context<'_>, looked_up: Player) -> Result<()
                     n => event_lines.push(format!("<inactivity decay> x{n}").into_boxed_str())
                 }
                 consec_decay = 0;
-                event_lines.push(event.short_summary(&ctx.data().mongo).await?);
+                event_lines.push(event.short_summary(&league(ctx).mongo).await?);
             }
         }
     }
 
-    let win_loss = ctx.data().mongo.collection::<StandingEvent>("events").aggregate(vec![
-        doc! {"$match": {"inner.GameEnd.ranking": looked_up._id}},
+    let win_loss = league(ctx).mongo.collection::<StandingEvent>("events").aggregate(vec![
+        doc! {"$match": {"$and": [{"inner.GameEnd.ranking": looked_up._id}, filter.match_doc()]}},
         doc! {"$replaceRoot": {"newRoot": "$inner.GameEnd"}},
         doc! {
             "$group": {
@@ -119,8 +179,41 @@ async fn display_lookup_result(ctx: Context<'_>, looked_up: Player) -> Result<()
 
     let rating = looked_up.rating_struct();
 
+    let mut embed = base_embed(ctx);
+    if _is_league_moderator(ctx).await? {
+        let league_info = league(ctx).mongo.collection::<LeagueInfo>("league_info")
+            .find_one(doc! {})
+            .await?
+            .expect("league_info struct missing");
+
+        if let Some(entry) = league_info.blacklist_entry(looked_up._id) {
+            embed = embed.field("hidden from leaderboard (visible to moderators only)",
+                                entry.describe(&league(ctx).mongo).await?, false);
+        }
+    }
+    let past_seasons = league(ctx).mongo.collection::<Season>("seasons")
+        .find(doc! { "standings.player": looked_up._id })
+        .sort(doc! { "_id": 1 })
+        .await?
+        .try_collect::<Vec<_>>().await?;
+    if !past_seasons.is_empty() {
+        embed = embed.field("past seasons", past_seasons.iter()
+            .filter_map(|season| season.standings.iter()
+                .find(|standing| standing.player == looked_up._id)
+                .map(|standing| format!("season {}: {}", season._id, standing.describe_finish())))
+            .join("\n"), true);
+    }
+
+    if let Some(suspension) = looked_up.active_suspension() {
+        embed = embed.field("suspended", format!(
+            "until <t:{0}:f> (<t:{0}:R>) for {1}",
+            suspension.until.timestamp(),
+            remove_markdown(&suspension.reason),
+        ), false);
+    }
+
     ctx.send(CreateReply::default()
-        .embed(base_embed(ctx)
+        .embed(embed
             .field("user",
                    format!("{} (ID {})",
                            remove_markdown(&*looked_up.username),
@@ -131,7 +224,7 @@ async fn display_lookup_result(ctx: Context<'_>, looked_up: Player) -> Result<()
                 rating.rating,
                 rating.uncertainty,
                 if rating.is_provisional() {
-                    format!("; __this rating is provisional until deviation falls under {PROVISIONAL_DEVIATION_THRESHOLD}__")
+                    format!("; __this rating is provisional until deviation falls under {}__", *PROVISIONAL_DEVIATION_THRESHOLD)
                 } else {
                     String::from("")
                 }
@@ -140,14 +233,23 @@ async fn display_lookup_result(ctx: Context<'_>, looked_up: Player) -> Result<()
                 .map(|dt| format!("<t:{}:f> ({})", dt.timestamp(), time_formatter.convert_chrono(dt, Utc::now())))
                 .unwrap_or("never".to_string()),
                    true)
+            .field("highs and lows", format!(
+                "peak {}\nlowest since leaving provisional {}",
+                looked_up.peak.as_ref().map(RatingMark::describe).unwrap_or(String::from("<none yet>")),
+                looked_up.trough.as_ref().map(RatingMark::describe).unwrap_or(String::from("<none yet>")),
+            ), true)
+            .field("achievements", match looked_up.achievements.is_empty() {
+                true => String::from("none yet"),
+                false => looked_up.achievements.iter().map(Unlock::describe).join("\n"),
+            }, false)
             .field("associated discord accounts", assoc_accounts, true)
-            .field("record", format!("{} - {}", win_loss.wins, win_loss.losses), true)
+            .field("record", format!("{} - {}\n-# {}", win_loss.wins, win_loss.losses, filter.describe()), true)
             .description(format!("recent events:\n\n{}", event_lines.into_iter().join("\n"))))).await?;
     Ok(())
 }
 
 /// Look up a user in the database
-#[poise::command(prefix_command, slash_command, subcommands("by_discord", "by_username", "by_id"))]
+#[poise::command(prefix_command, slash_command, subcommands("by_discord", "by_username", "by_id", "vs", "stats"))]
 pub(crate) async fn user(ctx: Context<'_>) -> Result<(), BotError> {
     ctx.reply("base command is noop, try a subcommand").await?;
 
@@ -156,15 +258,29 @@ pub(crate) async fn user(ctx: Context<'_>) -> Result<(), BotError> {
 
 /// defaults to you; look up a player by discord user
 #[poise::command(prefix_command, slash_command)]
-async fn by_discord(ctx: Context<'_>, #[description = "Discord user to lookup by"] user: Option<User>) -> Result<(), BotError> {
+async fn by_discord(
+    ctx: Context<'_>,
+    #[description = "Discord user to lookup by"] user: Option<User>,
+    #[description = "Only count games on or after this day (YYYY-MM-DD)"] since: Option<String>,
+    #[description = "Only count games on or before this day (YYYY-MM-DD)"] until: Option<String>,
+    #[description = "Also count games not reviewed yet"] include_pending: Option<bool>,
+) -> Result<(), BotError> {
     let user = user.as_ref().unwrap_or(ctx.author());
+    let filter = match RecordFilter::parse(since, until, include_pending) {
+        Err(complaint) => {
+            ctx.reply(complaint).await?;
+            return Ok(());
+        }
+        Ok(filter) => filter
+    };
+
 
-    match try_lookup_player(&ctx.data().mongo, UserLookupType::DiscordID(user.id.into())).await? {
+    match try_lookup_player(&league(ctx).mongo, UserLookupType::DiscordID(user.id.into())).await? {
         None => {
             ctx.reply("could not find player with that discord user").await?;
         }
         Some(looked_up) => {
-            display_lookup_result(ctx, looked_up).await?
+            display_lookup_result(ctx, looked_up, filter).await?
         }
     }
 
@@ -173,13 +289,37 @@ async fn by_discord(ctx: Context<'_>, #[description = "Discord user to lookup by
 
 /// look up a player by handle
 #[poise::command(prefix_command, slash_command)]
-async fn by_username(ctx: Context<'_>, #[description = "System handle to lookup by"] handle: String) -> Result<(), BotError> {
-    match try_lookup_player(&ctx.data().mongo, Username(handle.as_str())).await? {
+async fn by_username(
+    ctx: Context<'_>,
+    #[description = "System handle to lookup by"] #[autocomplete = "autocomplete_username"] handle: String,
+    #[description = "Only count games on or after this day (YYYY-MM-DD)"] since: Option<String>,
+    #[description = "Only count games on or before this day (YYYY-MM-DD)"] until: Option<String>,
+    #[description = "Also count games not reviewed yet"] include_pending: Option<bool>,
+) -> Result<(), BotError> {
+    let filter = match RecordFilter::parse(since, until, include_pending) {
+        Err(complaint) => {
+            ctx.reply(complaint).await?;
+            return Ok(());
+        }
+        Ok(filter) => filter
+    };
+
+    if let Some(looked_up) = try_lookup_player(&league(ctx).mongo, Username(handle.as_str())).await? {
+        display_lookup_result(ctx, looked_up, filter).await?;
+        return Ok(());
+    }
+
+    // no exact match, so show whoever is closest
+    let closest = search_players(&league(ctx).store, &handle, 1).await?;
+    match closest.first() {
         None => {
             ctx.reply("could not find player by that handle").await?;
         }
-        Some(looked_up) => {
-            display_lookup_result(ctx, looked_up).await?
+        Some(closest) => {
+            let looked_up = try_lookup_player(&league(ctx).mongo, SystemID(closest._id)).await?
+                .expect("searched player DNE");
+            ctx.reply(format!("no player is named `{handle}`; showing closest match `{}`", looked_up.username)).await?;
+            display_lookup_result(ctx, looked_up, filter).await?;
         }
     }
 
@@ -188,64 +328,413 @@ async fn by_username(ctx: Context<'_>, #[description = "System handle to lookup
 
 /// look up a player by database ID
 #[poise::command(prefix_command, slash_command)]
-async fn by_id(ctx: Context<'_>, #[description = "System ID to lookup by"] id: PlayerID) -> Result<(), BotError> {
-    match try_lookup_player(&ctx.data().mongo, UserLookupType::SystemID(id)).await? {
+async fn by_id(
+    ctx: Context<'_>,
+    #[description = "System ID to lookup by"] #[autocomplete = "autocomplete_player"] id: PlayerID,
+    #[description = "Only count games on or after this day (YYYY-MM-DD)"] since: Option<String>,
+    #[description = "Only count games on or before this day (YYYY-MM-DD)"] until: Option<String>,
+    #[description = "Also count games not reviewed yet"] include_pending: Option<bool>,
+) -> Result<(), BotError> {
+    let filter = match RecordFilter::parse(since, until, include_pending) {
+        Err(complaint) => {
+            ctx.reply(complaint).await?;
+            return Ok(());
+        }
+        Ok(filter) => filter
+    };
+
+    match try_lookup_player(&league(ctx).mongo, UserLookupType::SystemID(id)).await? {
         None => {
             ctx.reply("could not find player by that ID").await?;
         }
         Some(looked_up) => {
-            display_lookup_result(ctx, looked_up).await?
+            display_lookup_result(ctx, looked_up, filter).await?
         }
     }
 
     Ok(())
 }
 
-pub(crate) async fn register_user(mongo: &Database, user: Option<&User>, proposed_name: String) -> Result<Player, BotError> {
-    let TrueSkillRating { rating, uncertainty, .. } = DEFAULT_RATING;
+#[derive(Deserialize)]
+#[derive(Debug)]
+struct HeadToHeadAggregate {
+    games: i64,
+    a_above: i64,
+    b_above: i64,
+    // places b finished behind a, on average
+    gap: f64,
+}
 
-    let LeagueInfo { available_event_number, available_player_id, .. } = mongo
-        .collection::<LeagueInfo>("league_info")
-        .find_one_and_update(
-            doc! {},
-            doc! { "$inc": { "available_event_number": 1, "available_player_id": 1, } })
+/// compare two players' results in the games they both played
+#[poise::command(prefix_command, slash_command)]
+async fn vs(
+    ctx: Context<'_>,
+    #[description = "ID of the first player"] #[autocomplete = "autocomplete_player"] player_a: PlayerID,
+    #[description = "ID of the second player"] #[autocomplete = "autocomplete_player"] player_b: PlayerID,
+    #[description = "Only count games on or after this day (YYYY-MM-DD)"] since: Option<String>,
+    #[description = "Only count games on or before this day (YYYY-MM-DD)"] until: Option<String>,
+    #[description = "Also count games not reviewed yet"] include_pending: Option<bool>,
+) -> Result<(), BotError> {
+    let filter = match RecordFilter::parse(since, until, include_pending) {
+        Err(complaint) => {
+            ctx.reply(complaint).await?;
+            return Ok(());
+        }
+        Ok(filter) => filter
+    };
+
+    if player_a == player_b {
+        ctx.reply("that's the same person twice").await?;
+        return Ok(());
+    }
+
+    let (a, b) = match (
+        try_lookup_player(&league(ctx).mongo, SystemID(player_a)).await?,
+        try_lookup_player(&league(ctx).mongo, SystemID(player_b)).await?,
+    ) {
+        (Some(a), Some(b)) => (a, b),
+        (None, _) => {
+            ctx.reply(format!("could not find player by ID {player_a}")).await?;
+            return Ok(());
+        }
+        (_, None) => {
+            ctx.reply(format!("could not find player by ID {player_b}")).await?;
+            return Ok(());
+        }
+    };
+
+    ctx.defer().await?;
+
+    let shared_filter = doc! {
+        "$and": [{ "inner.GameEnd.ranking": { "$all": [a._id, b._id] } }, filter.match_doc()],
+    };
+
+    let head_to_head = league(ctx).mongo.collection::<StandingEvent>("events").aggregate(vec![
+        doc! {"$match": shared_filter.clone()},
+        doc! {
+            "$project": {
+                "a": {"$indexOfArray": ["$inner.GameEnd.ranking", a._id]},
+                "b": {"$indexOfArray": ["$inner.GameEnd.ranking", b._id]},
+            }
+        },
+        doc! {
+            "$group": {
+                "_id": null,
+                "games": {"$sum": 1},
+                "a_above": {"$sum": {"$cond": [{"$lt": ["$a", "$b"]}, 1, 0]}},
+                "b_above": {"$sum": {"$cond": [{"$lt": ["$b", "$a"]}, 1, 0]}},
+                "gap": {"$avg": {"$subtract": ["$b", "$a"]}},
+            }
+        },
+        doc! {"$project": {"_id": 0}},
+    ]).with_type::<HeadToHeadAggregate>().await?
+        .try_next().await?;
+
+    let head_to_head = match head_to_head {
+        None => {
+            ctx.reply(format!("{} and {} have no shared games among {}",
+                              a.short_summary(), b.short_summary(), filter.describe())).await?;
+            return Ok(());
+        }
+        Some(head_to_head) => head_to_head
+    };
+
+    let recent = league(ctx).mongo.collection::<StandingEvent>("events")
+        .find(shared_filter)
+        .sort(doc! { "_id": -1 })
+        .limit(5)
         .await?
-        .expect("league_info struct missing");
-
-    // add player
-    let new_player = Player {
-        _id: available_player_id,
-        username: proposed_name,
-        rating,
-        deviation: uncertainty,
-        last_played: None,
-        discord_ids: vec![user].into_iter().filter_map(identity).map(|u| u.id.get()).collect_vec(),
+        .try_collect::<Vec<_>>().await?;
+
+    let mut recent_lines = Vec::with_capacity(recent.len());
+    for event in recent {
+        recent_lines.push(event.short_summary(&league(ctx).mongo).await?);
+    }
+
+    let chances = expected_outcome(&vec![a.rating_struct(), b.rating_struct()]);
+
+    ctx.send(CreateReply::default()
+        .embed(base_embed(ctx)
+            .field("shared games", head_to_head.games.to_string(), true)
+            .field("finished above the other", format!(
+                "{}: {}\n{}: {}",
+                a.short_summary(), head_to_head.a_above,
+                b.short_summary(), head_to_head.b_above,
+            ), true)
+            .field("average placement gap", format!(
+                "{} finishes {:.2} places {} {} on average",
+                a.short_summary(),
+                head_to_head.gap.abs(),
+                if head_to_head.gap >= 0.0 { "ahead of" } else { "behind" },
+                b.short_summary(),
+            ), true)
+            .field("expected outcome now", format!(
+                "{}: {:.2}%\n{}: {:.2}%",
+                a.short_summary(), chances[0] * 100.0,
+                b.short_summary(), chances[1] * 100.0,
+            ), true)
+            .description(format!("{} vs {}, counting {}\n\nmost recent shared games:\n{}",
+                                 a.reference_no_discord(), b.reference_no_discord(), filter.describe(),
+                                 recent_lines.into_iter().join("\n"))))
+        .reply(true)).await?;
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct PlacementCount {
+    #[serde(rename = "_id")]
+    place: i64,
+    count: i64,
+}
+
+#[derive(Deserialize)]
+struct StatsSummary {
+    games: i64,
+    // 0 is first place, 1 is last place
+    avg_normalized: f64,
+    avg_length: f64,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    first: chrono::DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct MedianRow {
+    median: f64,
+}
+
+#[derive(Deserialize)]
+struct GameOutcome {
+    won: bool,
+    top_half: bool,
+}
+
+#[derive(Deserialize)]
+struct UpsetRow {
+    #[serde(rename = "_id")]
+    event_number: EventNumber,
+    chance: f64,
+    margin: f64,
+}
+
+#[derive(Deserialize)]
+struct StatsAggregate {
+    distribution: Vec<PlacementCount>,
+    summary: Vec<StatsSummary>,
+    median: Vec<MedianRow>,
+    sequence: Vec<GameOutcome>,
+    upset: Vec<UpsetRow>,
+}
+
+/// (current, longest) run of trues, oldest first
+fn streaks(outcomes: impl Iterator<Item=bool>) -> (usize, usize) {
+    outcomes.fold((0, 0), |(current, longest), outcome| {
+        let current = if outcome { current + 1 } else { 0 };
+        (current, longest.max(current))
+    })
+}
+
+/// in-depth numbers on how a player does
+#[poise::command(prefix_command, slash_command)]
+async fn stats(
+    ctx: Context<'_>,
+    #[description = "Discord user to lookup by, defaults to you"] user: Option<User>,
+    #[description = "Only count games on or after this day (YYYY-MM-DD)"] since: Option<String>,
+    #[description = "Only count games on or before this day (YYYY-MM-DD)"] until: Option<String>,
+    #[description = "Also count games not reviewed yet"] include_pending: Option<bool>,
+) -> Result<(), BotError> {
+    let user = user.as_ref().unwrap_or(ctx.author());
+    let filter = match RecordFilter::parse(since, until, include_pending) {
+        Err(complaint) => {
+            ctx.reply(complaint).await?;
+            return Ok(());
+        }
+        Ok(filter) => filter
+    };
+
+
+    let looked_up = match try_lookup_player(&league(ctx).mongo, DiscordID(user.id.get())).await? {
+        None => {
+            ctx.reply("could not find player with that discord user").await?;
+            return Ok(());
+        }
+        Some(looked_up) => looked_up
     };
-    mongo.collection::<Player>("players").insert_one(&new_player).await?;
-
-    // add league join event
-    mongo.collection::<StandingEvent>("events").insert_one(StandingEvent {
-        _id: available_event_number,
-        approval_status: Some(ApprovalStatus {
-            approved: true,
-            reviewer: None,
-        }),
-        inner: JoinLeague {
-            victims: vec![available_player_id],
-            initial_rating: rating,
-            initial_deviation: uncertainty,
+
+    ctx.defer().await?;
+
+    let stats = league(ctx).mongo.collection::<StandingEvent>("events").aggregate(vec![
+        doc! {"$match": {"$and": [{"inner.GameEnd.ranking": looked_up._id}, filter.match_doc()]}},
+        doc! {"$sort": {"_id": 1}},
+        doc! {
+            "$project": {
+                "when": 1,
+                "place": {"$add": [{"$indexOfArray": ["$inner.GameEnd.ranking", looked_up._id]}, 1]},
+                "size": {"$size": "$inner.GameEnd.ranking"},
+                "length": "$inner.GameEnd.length",
+                "expected": "$inner.GameEnd.expected",
+            }
         },
-        when: Utc::now(),
-    }).await?;
+        doc! {
+            "$addFields": {
+                "normalized": {"$divide": [{"$subtract": ["$place", 1]}, {"$max": [{"$subtract": ["$size", 1]}, 1]}]},
+                "won": {"$eq": ["$place", 1]},
+                "top_half": {"$lte": [{"$multiply": ["$place", 2]}, "$size"]},
+            }
+        },
+        doc! {
+            "$facet": {
+                "distribution": [
+                    {"$group": {"_id": "$place", "count": {"$sum": 1}}},
+                    {"$sort": {"_id": 1}},
+                ],
+                "summary": [
+                    {"$group": {
+                        "_id": null,
+                        "games": {"$sum": 1},
+                        "avg_normalized": {"$avg": "$normalized"},
+                        "avg_length": {"$avg": "$length"},
+                        "first": {"$min": "$when"},
+                    }},
+                ],
+                "median": [
+                    {"$sort": {"normalized": 1}},
+                    {"$group": {"_id": null, "values": {"$push": "$normalized"}}},
+                    {"$project": {"_id": 0, "median": {
+                        "$arrayElemAt": ["$values", {"$floor": {"$divide": [{"$size": "$values"}, 2]}}]
+                    }}},
+                ],
+                "sequence": [
+                    {"$project": {"_id": 0, "won": 1, "top_half": 1}},
+                ],
+                "upset": [
+                    {"$match": {"won": true, "expected.1": {"$exists": true}}},
+                    {"$project": {
+                        "chance": {"$arrayElemAt": ["$expected", 0]},
+                        "margin": {"$subtract": [{"$max": "$expected"}, {"$arrayElemAt": ["$expected", 0]}]},
+                    }},
+                    {"$sort": {"margin": -1}},
+                    {"$limit": 1},
+                ],
+            }
+        },
+    ]).with_type::<StatsAggregate>().await?
+        .try_next().await?
+        .expect("facet always gives a document");
+
+    let summary = match stats.summary.first() {
+        None => {
+            ctx.reply(format!("{} has no {}", looked_up.short_summary(), filter.describe())).await?;
+            return Ok(());
+        }
+        Some(summary) => summary
+    };
 
-    Ok(new_player)
+    let weeks = ((Utc::now() - summary.first).num_seconds() as f64 / TimeDelta::weeks(1).num_seconds() as f64).max(1.0);
+    let (current_wins, longest_wins) = streaks(stats.sequence.iter().map(|outcome| outcome.won));
+    let (current_top_half, longest_top_half) = streaks(stats.sequence.iter().map(|outcome| outcome.top_half));
+
+    let distribution = stats.distribution.iter()
+        .map(|PlacementCount { place, count }| format!(
+            "#{place}: {count} ({:.1}%)", *count as f64 / summary.games as f64 * 100.0))
+        .join("\n");
+
+    let chrono_avg_length = TimeDelta::seconds(summary.avg_length.round() as i64);
+
+    ctx.send(CreateReply::default()
+        .embed(base_embed(ctx)
+            .description(format!("stats for {} over {} {}",
+                                 looked_up.reference_no_discord(), summary.games, filter.describe()))
+            .field("placements", distribution, true)
+            .field("placement (0 is first, 1 is last)", format!(
+                "average {:.2}\nmedian {:.2}",
+                summary.avg_normalized,
+                stats.median.first().map(|row| row.median).unwrap_or(summary.avg_normalized),
+            ), true)
+            .field("activity", format!(
+                "{:.2} games per week\naverage game {:02}:{:02}",
+                summary.games as f64 / weeks,
+                chrono_avg_length.num_minutes(), chrono_avg_length.num_seconds() % 60,
+            ), true)
+            .field("win streak", format!("current {current_wins}, longest {longest_wins}"), true)
+            .field("top-half streak", format!("current {current_top_half}, longest {longest_top_half}"), true)
+            .field("biggest upset won", match stats.upset.first() {
+                None => String::from("none yet"),
+                Some(upset) => format!(
+                    "event {}, won with a {:.2}% chance ({:.2} points below the favorite)",
+                    upset.event_number, upset.chance * 100.0, upset.margin * 100.0),
+            }, true))
+        .reply(true)).await?;
+
+    Ok(())
+}
+
+/// rules for any name a player picks for themselves
+pub(crate) fn validate_username(proposed_name: &str) -> Result<(), &'static str> {
+    let valid_pattern = RegexBuilder::new(r"^[a-z\d_.]{1,32}$")
+        .case_insensitive(true)
+        .build().unwrap();
+
+    if proposed_name.len() > 32 {
+        Err("name too long, sorry")
+    } else if !valid_pattern.is_match(proposed_name) {
+        Err("only alphanumeric, `_`, or `.`, sorry")
+    } else {
+        Ok(())
+    }
+}
+
+/// register a player through the league's rating processor, under the `identity` they already have in another league if any
+pub(crate) async fn register_user(processor: &RatingProcessor, identity: Option<PlayerID>, user: Option<&User>, proposed_name: String) -> Result<Player, BotError> {
+    processor.register(identity, registration(user, proposed_name)).await
+}
+
+/// the new player and the join event recording them, for whichever player ID and event number get drawn
+pub(crate) fn registration(user: Option<&User>, proposed_name: String) -> BuildRegistration {
+    let TrueSkillRating { rating, uncertainty, .. } = *DEFAULT_RATING;
+    let discord_ids = vec![user].into_iter().filter_map(identity).map(|u| u.id.get()).collect_vec();
+
+    Box::new(move |available_player_id, event_number| {
+        // add player
+        let new_player = Player {
+            _id: available_player_id,
+            username_lower: proposed_name.to_lowercase(),
+            username: proposed_name.clone(),
+            rating,
+            deviation: uncertainty,
+            last_played: None,
+            discord_ids: discord_ids.clone(),
+            suspension: None,
+            peak: None,
+            trough: None,
+            achievements: vec![],
+        };
+
+        // add league join event
+        let join = StandingEvent {
+            _id: event_number,
+            approval_status: Some(ApprovalStatus {
+                approved: true,
+                reviewer: None,
+            }),
+            approvals: vec![],
+            inner: JoinLeague {
+                victims: vec![available_player_id],
+                initial_rating: rating,
+                initial_deviation: uncertainty,
+            },
+            when: Utc::now(),
+        };
+
+        (new_player, join)
+    })
 }
 
 #[poise::command(prefix_command, slash_command)]
 pub(crate) async fn register(ctx: Context<'_>, #[description = "Defaults to your Discord username - name you want upon registration"] desired_name: Option<String>) -> Result<(), BotError> {
     let proposed_name = desired_name.unwrap_or(ctx.author().name.clone()).to_lowercase();
 
-    match try_lookup_player(&ctx.data().mongo, DiscordID(ctx.author().id.get())).await? {
+    match try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await? {
         Some(player) => {
             ctx.reply(
                 format!("cannot bind your discord account to a second player (currently bound to user {})",
@@ -256,25 +745,83 @@ pub(crate) async fn register(ctx: Context<'_>, #[description = "Defaults to your
         None => {}
     };
 
-    if try_lookup_player(&ctx.data().mongo, Username(&*proposed_name)).await?.is_some() {
+    // someone who plays in another league joins this one as who they already are
+    if let Some(identity) = league(ctx).store.identities.by_discord(ctx.author().id.get()).await? {
+        let joined = register_user(&league(ctx).processor, Some(identity._id), Some(ctx.author()), identity.username).await?;
+        ctx.reply(format!("ok, {} joined this league", joined.reference_no_discord())).await?;
+        return Ok(());
+    }
+
+    if league(ctx).store.identities.by_username(&proposed_name).await?.is_some() {
         ctx.reply(format!("user by name {proposed_name} already exists")).await?;
         return Ok(());
     }
 
-    let valid_pattern = RegexBuilder::new(r"^[a-z\d_.]{1,32}$")
-        .case_insensitive(true)
-        .build().unwrap();
+    if let Err(complaint) = validate_username(&proposed_name) {
+        ctx.reply(complaint).await?;
+        return Ok(());
+    }
 
-    if proposed_name.len() > 32 {
-        ctx.reply("name too long, sorry").await?;
+    let new_player = register_user(&league(ctx).processor, None, Some(ctx.author()), proposed_name).await?;
+
+    ctx.reply(format!("ok, new user {} created", new_player.reference_no_discord())).await?;
+    Ok(())
+}
+
+/// Ask league moderators to change your username
+#[poise::command(prefix_command, slash_command, check = has_system_account)]
+pub(crate) async fn rename(ctx: Context<'_>, #[description = "Name you want to go by"] desired_name: String) -> Result<(), BotError> {
+    let proposed_name = desired_name.to_lowercase();
+
+    let player = try_lookup_player(&league(ctx).mongo, DiscordID(ctx.author().id.get())).await?
+        .expect("user disappeared after check");
+
+    if player.username == proposed_name {
+        ctx.reply("that's already your name").await?;
+        return Ok(());
+    }
+
+    if let Err(complaint) = validate_username(&proposed_name) {
+        ctx.reply(complaint).await?;
         return Ok(());
-    } else if !valid_pattern.is_match(&*proposed_name) {
-        ctx.reply("only alphanumeric, `_`, or `.`, sorry").await?;
+    }
+
+    if try_lookup_player(&league(ctx).mongo, Username(&proposed_name)).await?.is_some() {
+        ctx.reply(format!("user by name {proposed_name} already exists")).await?;
+        return Ok(());
+    }
+
+    let last_rename = league(ctx).mongo.collection::<StandingEvent>("events")
+        .find_one(doc! { "inner.Rename.victims": player._id })
+        .sort(doc! { "_id": -1 })
+        .await?;
+    if let Some(last_rename) = last_rename {
+        let next_allowed = last_rename.when + TimeDelta::days(*RENAME_COOLDOWN_DAYS);
+        if next_allowed > Utc::now() {
+            ctx.reply(format!(":x: you can only rename once every {} days; try again <t:{}:R>",
+                              *RENAME_COOLDOWN_DAYS, next_allowed.timestamp())).await?;
+            return Ok(());
+        }
+    }
+
+    let rename_requests = league(ctx).mongo.collection::<RenameRequest>("rename_requests");
+    if rename_requests.find_one(doc! { "new_username": &proposed_name, "_id": { "$ne": player._id } }).await?.is_some() {
+        ctx.reply(":x: someone else has already asked for that name").await?;
         return Ok(());
     }
 
-    let new_player = register_user(&ctx.data().mongo, Some(ctx.author()), proposed_name).await?;
+    // asking again replaces whatever was pending
+    let replaced = rename_requests.find_one_and_replace(doc! { "_id": player._id }, RenameRequest {
+        _id: player._id,
+        new_username: proposed_name.clone(),
+        when: Utc::now(),
+    }).upsert(true).await?;
 
-    ctx.reply(format!("ok, new user {} created", new_player.reference_no_discord())).await?;
+    ctx.reply(format!(
+        "ok, asked moderators to rename you to {proposed_name}{}\n\
+        **any moderator, please approve or reject this with `/review_rename {}`.**",
+        replaced.map(|old| format!(" (replacing your request for {})", old.new_username)).unwrap_or_default(),
+        player._id,
+    )).await?;
     Ok(())
 }
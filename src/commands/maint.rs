@@ -1,17 +1,19 @@
 use crate::model::StandingEventInner::GameEnd;
-use crate::model::{EventNumber, Game, LeagueInfo, Player, StandingEvent};
+use crate::model::{EventNumber, Game, LeagueInfo, StandingEvent};
+use crate::store::Store;
 use crate::util::checks::is_league_moderator;
-use crate::util::rating::advance_approve_pointer;
+use crate::util::indexes::{index_report, IndexReport};
+use crate::util::league::league;
 use crate::{inactivity_decay_inner, BotError, Context};
-use bson::Bson::{Int64, Null};
 use bson::{doc, Bson, Document};
-use futures::TryStreamExt;
+use itertools::Itertools;
 use poise::CreateReply;
 use serde::de::DeserializeOwned;
 use serenity::all::{CreateActionRow, CreateButton, CreateInteractionResponse, ReactionType};
 use std::cmp::min;
 use std::error::Error;
 use std::time::Duration;
+use tracing::info;
 use crate::util::base_embed;
 
 /// attempt to advance the approve pointer (be careful)
@@ -22,14 +24,14 @@ pub(crate) async fn advance_pointer(
 ) -> Result<(), BotError> {
     ctx.defer().await?;
 
-    let LeagueInfo { first_unreviewed_event_number, .. } = ctx.data().mongo
+    let LeagueInfo { first_unreviewed_event_number, .. } = league(ctx).mongo
         .collection::<LeagueInfo>("league_info")
         .find_one(doc! {})
         .await?
         .expect("league_info struct missing");
 
     let stopped_before = first_unreviewed_event_number;
-    let new_stopped_before = advance_approve_pointer(&ctx.data(), stop_before).await?;
+    let new_stopped_before = league(ctx).processor.advance(stop_before).await?;
 
     ctx.reply(match stopped_before == new_stopped_before {
         true => format!("ok, stopped at event number {} (no change)", stopped_before),
@@ -43,16 +45,7 @@ pub(crate) async fn advance_pointer(
 /// move the advance pointer back to 0, clear all ratings
 #[poise::command(prefix_command, slash_command, check = is_league_moderator)]
 pub(crate) async fn force_reprocess(ctx: Context<'_>) -> Result<(), BotError> {
-    ctx.data().mongo
-        .collection::<LeagueInfo>("league_info")
-        .update_one(doc! {}, doc! { "$set": {"first_unreviewed_event_number": Int64(0) } })
-        .await?;
-
-    ctx.data().mongo.collection::<Player>("players").update_many(doc! {}, doc! {"$set": {
-        "rating": 0,
-        "deviation": 0,
-        "last_played": Null
-    }}).await?;
+    league(ctx).processor.reset().await?;
 
     ctx.reply("ok").await?;
     Ok(())
@@ -66,21 +59,43 @@ where
     Ok(parsed)
 }
 
+/// what fsck found wrong with a league's event log
+pub(crate) struct FsckReport {
+    pub(crate) problems: Vec<String>,
+    /// league_info with the approve pointer fixed and free event/game numbers trimmed as necessary
+    pub(crate) repaired: LeagueInfo,
+}
+
 /// check integrity of event log
 #[poise::command(prefix_command, slash_command, owners_only)]
 pub(crate) async fn fsck(ctx: Context<'_>, #[description = "attempt repairs"] repair: Option<bool>) -> Result<(), BotError> {
-    // TODO: check players collection and counter, check validity of player references
     ctx.defer().await?;
 
-    let mut had_err = false;
+    let FsckReport { problems, repaired } = check_event_log(&league(ctx).store).await?;
+    for problem in problems.iter() {
+        ctx.reply(problem).await?;
+    }
+
+    if problems.is_empty() {
+        ctx.reply("all ok").await?;
+    } else if repair.unwrap_or(false) {
+        league(ctx).store.league_info.replace(repaired).await?;
+        ctx.reply("fixing approve pointer, trimming free event/game numbers as necessary").await?;
+    }
+
+    Ok(())
+}
+
+pub(crate) async fn check_event_log(store: &Store) -> Result<FsckReport, BotError> {
+    // TODO: check players collection and counter, check validity of player references
+    let mut problems = Vec::new();
 
     let mut first_missing_event = 0;
     let mut first_unreviewed_event = 0;
     let mut first_missing_game = 0;
 
-    let mut events = ctx.data().mongo.collection::<Document>("events").find(doc! {}).sort(doc! {"_id": 1}).await?;
-    while let Some(out) = events.try_next().await? {
-        let to_send = match try_make::<StandingEvent>(out.clone()) {
+    for out in store.events.raw_events().await? {
+        let problem = match try_make::<StandingEvent>(out.clone()) {
             Ok(evt) => {
                 if evt._id != first_missing_event {
                     let out = format!("event {first_missing_event} is missing");
@@ -113,48 +128,30 @@ pub(crate) async fn fsck(ctx: Context<'_>, #[description = "attempt repairs"] re
             }
         };
         // we will never be here if everything is okay
-        had_err = true;
-        ctx.reply(to_send).await?;
+        problems.push(problem);
     }
 
-    let league_info = match ctx.data().mongo.collection::<LeagueInfo>("league_info").find_one(doc! {}).await? {
-        None => {
-            ctx.reply("league_info DNE").await?;
-            return Ok(());
-        }
-        Some(info) => info
-    };
+    let league_info = store.league_info.league_info().await?;
 
     if league_info.available_event_number != first_missing_event {
-        ctx.reply(format!("league_info available event number {} != actual {first_missing_event}, INSPECT AND FIX",
-                          league_info.available_event_number)).await?;
-        had_err = true;
+        problems.push(format!("league_info available event number {} != actual {first_missing_event}, INSPECT AND FIX",
+                              league_info.available_event_number));
     }
 
     if league_info.first_unreviewed_event_number != first_unreviewed_event {
-        ctx.reply(format!("league_info unreviewed event number {} != actual {first_unreviewed_event}", league_info.first_unreviewed_event_number)).await?;
-        had_err = true;
+        problems.push(format!("league_info unreviewed event number {} != actual {first_unreviewed_event}", league_info.first_unreviewed_event_number));
     }
 
     if league_info.available_game_id != first_missing_game {
-        ctx.reply(format!("league_info available game number {} != actual {first_missing_game}, INSPECT AND FIX", league_info.available_game_id)).await?;
-        had_err = true;
+        problems.push(format!("league_info available game number {} != actual {first_missing_game}, INSPECT AND FIX", league_info.available_game_id));
     }
 
-    if !had_err {
-        ctx.reply("all ok").await?;
-    } else {
-        if repair.unwrap_or(false) {
-            let mut fix_league_info = LeagueInfo::from(league_info);
-            fix_league_info.available_event_number = min(fix_league_info.available_event_number, first_missing_event);
-            fix_league_info.available_game_id = min(fix_league_info.available_game_id, first_missing_game);
-            fix_league_info.first_unreviewed_event_number = first_unreviewed_event;
-            ctx.data().mongo.collection::<LeagueInfo>("league_info").find_one_and_replace(doc! {}, fix_league_info).await?;
-            ctx.reply("fixing approve pointer, trimming free event/game numbers as necessary").await?;
-        }
-    }
+    let mut repaired = league_info;
+    repaired.available_event_number = min(repaired.available_event_number, first_missing_event);
+    repaired.available_game_id = min(repaired.available_game_id, first_missing_game);
+    repaired.first_unreviewed_event_number = first_unreviewed_event;
 
-    Ok(())
+    Ok(FsckReport { problems, repaired })
 }
 
 /// forcibly do deviation decay now
@@ -162,7 +159,8 @@ pub(crate) async fn fsck(ctx: Context<'_>, #[description = "attempt repairs"] re
 pub(crate) async fn do_decay(ctx: Context<'_>) -> Result<(), BotError> {
     ctx.defer().await?;
 
-    inactivity_decay_inner(&ctx.data().mongo).await?;
+    inactivity_decay_inner(&league(ctx).store).await?;
+    league(ctx).processor.advance(None).await?;
     ctx.reply("ok").await?;
 
     Ok(())
@@ -171,18 +169,9 @@ pub(crate) async fn do_decay(ctx: Context<'_>) -> Result<(), BotError> {
 /// league moderators: remove the latest event from the record irreversibly
 #[poise::command(prefix_command, slash_command, check = is_league_moderator)]
 pub(crate) async fn pop_event(ctx: Context<'_>) -> Result<(), BotError> {
-    let mutex = ctx.data().core_state_lock.clone();
-    mutex.lock().await;
+    let LeagueInfo { available_event_number, .. } = league(ctx).store.league_info.league_info().await?;
 
-    let LeagueInfo { available_event_number, .. } = ctx.data().mongo
-        .collection::<LeagueInfo>("league_info")
-        .find_one(doc! {})
-        .await?
-        .expect("league_info struct missing");
-
-    let victim_event = match ctx.data().mongo
-        .collection::<StandingEvent>("events")
-        .find_one(doc! { "_id": available_event_number - 1 }).await? {
+    let victim_event = match league(ctx).store.events.event(available_event_number - 1).await? {
         None => {
             ctx.reply("latest event DNE; you have a major issue, fsck now").await?;
             return Ok(());
@@ -194,7 +183,7 @@ pub(crate) async fn pop_event(ctx: Context<'_>) -> Result<(), BotError> {
         .embed(base_embed(ctx)
             .description(format!(
                 "**you are permanently removing event ID {}:**\n> {}\n**from the record!** please confirm (5 seconds)",
-                victim_event._id, victim_event.short_summary(&ctx.data().mongo).await?)))
+                victim_event._id, victim_event.short_summary(&league(ctx).mongo).await?)))
         .components(vec![
             CreateActionRow::Buttons(vec![
                 CreateButton::new("pop_event_confirm")
@@ -215,35 +204,60 @@ pub(crate) async fn pop_event(ctx: Context<'_>) -> Result<(), BotError> {
         Some(ixn) => ixn.create_response(ctx.http(), CreateInteractionResponse::Acknowledge).await?
     };
 
-    // yes, this is declared twice but no big deal tbh
-    let evt = match ctx.data().mongo.collection::<StandingEvent>("events")
-        .find_one_and_delete(doc! { "_id": victim_event._id }).await? {
+    // the processor checks again that nothing was appended while we waited
+    let evt = match league(ctx).processor.pop_event(victim_event._id).await? {
         None => {
             ctx.reply("free event number bad?").await?;
             return Ok(());
         }
-        Some(evt) => {
-            let update_doc = if let GameEnd(_) = evt.inner {
-                doc! {
-                    "$inc": {"available_event_number": -1, "available_game_id": -1},
-                    "$min": { "first_unreviewed_event_number": evt._id }
-                }
-            } else {
-                doc! {
-                    "$inc": { "available_event_number": -1 },
-                    "$min": { "first_unreviewed_event_number": evt._id }
-                }
-            };
+        Some(evt) => evt
+    };
 
-            ctx.data().mongo
-                .collection::<LeagueInfo>("league_info")
-                .update_one(doc! {}, update_doc)
-                .await?;
+    ctx.reply(format!("ok, event {} is gone, need to reprocess to finish", evt._id)).await?;
+    Ok(())
+}
+
+/// league moderators: compare this league's indexes with the ones the bot expects
+#[poise::command(prefix_command, slash_command, check = is_league_moderator)]
+pub(crate) async fn indexes(ctx: Context<'_>) -> Result<(), BotError> {
+    ctx.defer().await?;
+
+    let IndexReport { missing, undeclared, unused } = index_report(&league(ctx).mongo).await?;
+
+    ctx.send(CreateReply::default()
+        .embed(base_embed(ctx)
+            .title("index report")
+            .field("missing (restart the bot to create)", list_indexes(&missing), false)
+            .field("not declared by the bot", list_indexes(&undeclared), false)
+            .field("unused since the database started", list_indexes(&unused), false))).await?;
 
-            evt
+    Ok(())
+}
+
+fn list_indexes(names: &[String]) -> String {
+    match names.is_empty() {
+        true => String::from("none"),
+        false => names.iter().map(|name| format!("`{name}`")).join("\n"),
+    }
+}
+
+/// show or change which logs are kept, like `info` or `info,ewar_bot=debug`
+#[poise::command(prefix_command, slash_command, owners_only, category = "Anywhere")]
+pub(crate) async fn log_level(ctx: Context<'_>, #[description = "new filter; leave out to see the current one"] filter: Option<String>) -> Result<(), BotError> {
+    let log_level = &ctx.data().log_level;
+    match filter {
+        None => {
+            ctx.reply(format!("logging `{}`", log_level.current()?)).await?;
         }
-    };
+        Some(filter) => {
+            if let Err(err) = log_level.set(&filter) {
+                ctx.reply(format!(":x: can't use that filter: {err}")).await?;
+                return Ok(());
+            }
+            info!(filter, "log level changed");
+            ctx.reply(format!("ok, now logging `{}`", log_level.current()?)).await?;
+        }
+    }
 
-    ctx.reply(format!("ok, event {} is gone, need to reprocess to finish", evt._id)).await?;
     Ok(())
 }
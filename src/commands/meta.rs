@@ -6,7 +6,7 @@ use itertools::Itertools;
 use poise::CreateReply;
 
 /// Check bot is alive, get numerical ping to Discord
-#[poise::command(prefix_command, slash_command)]
+#[poise::command(prefix_command, slash_command, category = "Anywhere")]
 pub(crate) async fn ping(ctx: Context<'_>) -> Result<(), BotError> {
     let ping_num = ctx.ping().await.as_millis();
     ctx.say(match ping_num {
@@ -17,7 +17,7 @@ pub(crate) async fn ping(ctx: Context<'_>) -> Result<(), BotError> {
 }
 
 /// See recent Git commits to the bot
-#[poise::command(prefix_command, slash_command)]
+#[poise::command(prefix_command, slash_command, category = "Anywhere")]
 pub(crate) async fn git(ctx: Context<'_>) -> Result<(), BotError> {
     let recents = {
         let repo = ThreadSafeRepository::open(".")?.to_thread_local();
@@ -59,3 +59,13 @@ pub(crate) async fn git(ctx: Context<'_>) -> Result<(), BotError> {
 
     Ok(())
 }
+
+/// See which league this channel plays in
+#[poise::command(prefix_command, slash_command, category = "Anywhere")]
+pub(crate) async fn league(ctx: Context<'_>) -> Result<(), BotError> {
+    match ctx.data().league_for(ctx.guild_id(), ctx.channel_id()) {
+        Some(league) => ctx.say(format!("this channel plays in the `{}` league", remove_markdown(&league.name))).await?,
+        None => ctx.say("no league is played in this channel").await?,
+    };
+    Ok(())
+}
@@ -0,0 +1,478 @@
+use serde::{Deserialize, Deserializer};
+use skillratings::trueskill::{TrueSkillConfig, TrueSkillRating};
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::net::SocketAddr;
+use std::num::NonZeroUsize;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use tracing_subscriber::EnvFilter;
+
+static INSTALLED: OnceLock<Config> = OnceLock::new();
+
+/// everything the bot is told at startup; see config.example.yaml
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    #[serde(default)]
+    pub(crate) token: String,
+    #[serde(default)]
+    pub(crate) register_commands: RegisterCommands,
+    #[serde(default)]
+    pub(crate) creds: Creds,
+    #[serde(default)]
+    pub(crate) league: LeagueConfig,
+    // more leagues besides the main one, each kept in its own database
+    #[serde(default, deserialize_with = "listed")]
+    pub(crate) leagues: Vec<ExtraLeague>,
+    #[serde(default)]
+    pub(crate) rating: RatingConfig,
+    #[serde(default)]
+    pub(crate) logging: LoggingConfig,
+    #[serde(default)]
+    pub(crate) metrics: MetricsConfig,
+}
+
+/// a league other than the main one, and where it is played
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ExtraLeague {
+    pub(crate) name: String,
+    // database on the same mongo server
+    pub(crate) db: String,
+    // every channel in these guilds uses this league
+    #[serde(default, deserialize_with = "snowflakes")]
+    pub(crate) guilds: Vec<u64>,
+    // these channels use this league no matter the guild
+    #[serde(default, deserialize_with = "snowflakes")]
+    pub(crate) channels: Vec<u64>,
+    #[serde(default)]
+    pub(crate) announce_channel: Option<u64>,
+    #[serde(default)]
+    pub(crate) digest_channel: Option<u64>,
+    // moderators of this league only; the main league's have no say here
+    #[serde(default, deserialize_with = "snowflakes")]
+    pub(crate) moderator_discords: Vec<u64>,
+    #[serde(default)]
+    pub(crate) review: ReviewConfig,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct RegisterCommands {
+    #[serde(default)]
+    pub(crate) global: bool,
+    #[serde(default)]
+    pub(crate) local: LocalRegistration,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct LocalRegistration {
+    #[serde(default)]
+    pub(crate) enabled: bool,
+    #[serde(default, deserialize_with = "snowflakes")]
+    pub(crate) guilds: Vec<u64>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Creds {
+    #[serde(default)]
+    pub(crate) mongo: MongoCreds,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct MongoCreds {
+    #[serde(default)]
+    pub(crate) uri: String,
+    #[serde(default)]
+    pub(crate) db: String,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct LeagueConfig {
+    // every channel in these guilds uses the main league
+    #[serde(deserialize_with = "snowflakes")]
+    pub(crate) guilds: Vec<u64>,
+    // these channels use the main league no matter the guild
+    #[serde(deserialize_with = "snowflakes")]
+    pub(crate) channels: Vec<u64>,
+    #[serde(deserialize_with = "snowflakes")]
+    pub(crate) moderator_discords: Vec<u64>,
+    pub(crate) review: ReviewConfig,
+    // where achievement unlocks are posted
+    pub(crate) announce_channel: Option<u64>,
+    // where the weekly digest is posted
+    pub(crate) digest_channel: Option<u64>,
+    pub(crate) rename_cooldown_days: i64,
+    // most events listed by the log commands
+    pub(crate) log_limit: i64,
+    pub(crate) live_leaderboard_size: usize,
+    pub(crate) live_leaderboard_movers: usize,
+}
+
+impl Default for LeagueConfig {
+    fn default() -> Self {
+        Self {
+            guilds: vec![],
+            channels: vec![],
+            moderator_discords: vec![],
+            review: Default::default(),
+            announce_channel: None,
+            digest_channel: None,
+            rename_cooldown_days: 30,
+            log_limit: 50,
+            live_leaderboard_size: 10,
+            live_leaderboard_movers: 5,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct ReviewConfig {
+    pub(crate) allow_self_review: bool,
+    pub(crate) allow_moderator_self_post: bool,
+    pub(crate) approval_quorum: NonZeroUsize,
+}
+
+impl Default for ReviewConfig {
+    fn default() -> Self {
+        Self {
+            allow_self_review: false,
+            allow_moderator_self_post: false,
+            approval_quorum: NonZeroUsize::MIN,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct RatingConfig {
+    pub(crate) trueskill: TrueSkillParams,
+    pub(crate) default_rating: f64,
+    pub(crate) default_deviation: f64,
+    pub(crate) provisional_deviation_threshold: f64,
+    // defaults for closing a season
+    pub(crate) soft_reset_pull: f64,
+    pub(crate) soft_reset_delta_deviation: f64,
+}
+
+impl Default for RatingConfig {
+    fn default() -> Self {
+        Self {
+            trueskill: Default::default(),
+            default_rating: 18.0,
+            default_deviation: 9.0,
+            provisional_deviation_threshold: 2.0,
+            soft_reset_pull: 0.5,
+            soft_reset_delta_deviation: 2.0,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct TrueSkillParams {
+    pub(crate) draw_probability: f64,
+    pub(crate) beta: f64,
+    // aka tau
+    pub(crate) default_dynamics: f64,
+}
+
+impl Default for TrueSkillParams {
+    fn default() -> Self {
+        Self {
+            draw_probability: 0f64,
+            beta: 2f64,
+            default_dynamics: 0.04,
+        }
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct LoggingConfig {
+    pub(crate) format: LogFormat,
+    // a tracing filter, like `info` or `info,ewar_bot=debug`; can be changed while running
+    pub(crate) level: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        Self {
+            format: LogFormat::Pretty,
+            level: String::from("info"),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub(crate) enum LogFormat {
+    // for people reading a terminal
+    Pretty,
+    // one object per line, for log collectors
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "pretty" => Ok(LogFormat::Pretty),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(format!("{s} is not a log format; use pretty or json")),
+        }
+    }
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields, default)]
+pub(crate) struct MetricsConfig {
+    // where to serve prometheus metrics at /metrics, like `127.0.0.1:9185`; off if unset
+    pub(crate) listen: Option<SocketAddr>,
+}
+
+/// a config value that is missing, malformed or out of range, and the key it was under
+#[derive(Debug)]
+pub(crate) struct ConfigError {
+    key: String,
+    problem: String,
+}
+
+impl ConfigError {
+    fn new(key: impl Into<String>, problem: impl Display) -> Self {
+        Self { key: key.into(), problem: problem.to_string() }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "bad config at `{}`: {}", self.key, self.problem)
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+// the example config leaves empty list items, which come through as nulls
+fn snowflakes<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u64>, D::Error> {
+    Ok(Option::<Vec<Option<u64>>>::deserialize(deserializer)?
+        .unwrap_or_default()
+        .into_iter()
+        .flatten()
+        .collect())
+}
+
+// a list key with nothing under it is null rather than empty
+fn listed<'de, D: Deserializer<'de>, T: Deserialize<'de>>(deserializer: D) -> Result<Vec<T>, D::Error> {
+    Ok(Option::<Vec<T>>::deserialize(deserializer)?.unwrap_or_default())
+}
+
+fn env_flag(name: &str, value: &mut bool) {
+    if env::var(name).is_ok() {
+        *value = true;
+    }
+}
+
+fn env_parse<T: FromStr>(name: &str, value: &mut T) -> Result<(), ConfigError>
+where
+    T::Err: Display,
+{
+    if let Ok(raw) = env::var(name) {
+        *value = raw.trim().parse().map_err(|err| ConfigError::new(name, err))?;
+    }
+    Ok(())
+}
+
+fn env_parse_opt<T: FromStr>(name: &str, value: &mut Option<T>) -> Result<(), ConfigError>
+where
+    T::Err: Display,
+{
+    if let Ok(raw) = env::var(name) {
+        *value = Some(raw.trim().parse().map_err(|err| ConfigError::new(name, err))?);
+    }
+    Ok(())
+}
+
+fn env_snowflake(name: &str, value: &mut Option<u64>) -> Result<(), ConfigError> {
+    if let Ok(raw) = env::var(name) {
+        *value = Some(raw.trim().parse::<u64>().map_err(|_| ConfigError::new(name, format!("{raw} is not a valid snowflake")))?);
+    }
+    Ok(())
+}
+
+fn env_snowflakes(name: &str, value: &mut Vec<u64>) -> Result<(), ConfigError> {
+    if let Ok(raw) = env::var(name) {
+        *value = raw.split(",")
+            .map(str::trim)
+            .filter(|id| !id.is_empty())
+            .map(|id| id.parse::<u64>().map_err(|_| ConfigError::new(name, format!("{id} is not a valid snowflake"))))
+            .collect::<Result<_, _>>()?;
+    }
+    Ok(())
+}
+
+impl Config {
+    /// read a YAML config, or a .env file for older setups, then apply any `EWAR_*` overrides from the environment
+    pub(crate) fn load(path: &Path) -> Result<Self, ConfigError> {
+        let mut config = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => {
+                let text = fs::read_to_string(path).map_err(|err| ConfigError::new(path.display().to_string(), err))?;
+                serde_path_to_error::deserialize(serde_yaml::Deserializer::from_str(&text))
+                    .map_err(|err| ConfigError::new(err.path().to_string(), err.inner()))?
+            }
+            _ => {
+                dotenv::from_filename(path).ok();
+                Config::default()
+            }
+        };
+
+        config.apply_env()?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    fn apply_env(&mut self) -> Result<(), ConfigError> {
+        if let Ok(token) = env::var("EWAR_DISCORD_TOKEN") {
+            self.token = token;
+        }
+        env_flag("EWAR_REGISTER_GLOBAL", &mut self.register_commands.global);
+        env_flag("EWAR_REGISTER_LOCAL", &mut self.register_commands.local.enabled);
+        env_snowflakes("EWAR_GUILDS", &mut self.register_commands.local.guilds)?;
+        env_parse("EWAR_MONGO_URI", &mut self.creds.mongo.uri)?;
+        env_parse("EWAR_MONGO_DB", &mut self.creds.mongo.db)?;
+        env_snowflakes("EWAR_LEAGUE_GUILDS", &mut self.league.guilds)?;
+        env_snowflakes("EWAR_LEAGUE_CHANNELS", &mut self.league.channels)?;
+        env_snowflakes("EWAR_LEAGUE_MODERATORS", &mut self.league.moderator_discords)?;
+        env_flag("EWAR_ALLOW_SELF_REVIEW", &mut self.league.review.allow_self_review);
+        env_flag("EWAR_ALLOW_MODERATOR_SELF_POST", &mut self.league.review.allow_moderator_self_post);
+        env_parse("EWAR_APPROVAL_QUORUM", &mut self.league.review.approval_quorum)?;
+        env_snowflake("EWAR_ANNOUNCE_CHANNEL", &mut self.league.announce_channel)?;
+        env_snowflake("EWAR_DIGEST_CHANNEL", &mut self.league.digest_channel)?;
+        env_parse("EWAR_LOG_FORMAT", &mut self.logging.format)?;
+        env_parse("EWAR_LOG_LEVEL", &mut self.logging.level)?;
+        env_parse_opt("EWAR_METRICS_LISTEN", &mut self.metrics.listen)?;
+
+        Ok(())
+    }
+
+    fn validate(&self) -> Result<(), ConfigError> {
+        let required = [
+            ("token", "EWAR_DISCORD_TOKEN", &self.token),
+            ("creds.mongo.uri", "EWAR_MONGO_URI", &self.creds.mongo.uri),
+            ("creds.mongo.db", "EWAR_MONGO_DB", &self.creds.mongo.db),
+        ];
+        for (key, env_name, value) in required {
+            if value.trim().is_empty() {
+                return Err(ConfigError::new(key, format!("must be set (or set {env_name})")));
+            }
+        }
+
+        let mut dbs = vec![&self.creds.mongo.db];
+        let mut names = vec![];
+        let mut guilds = self.league.guilds.iter().collect::<Vec<_>>();
+        let mut channels = self.league.channels.iter().collect::<Vec<_>>();
+        for (index, league) in self.leagues.iter().enumerate() {
+            if league.name.trim().is_empty() || names.contains(&&league.name) {
+                return Err(ConfigError::new(format!("leagues[{index}].name"), "must be set and different from every other league's"));
+            }
+            if league.db.trim().is_empty() || dbs.contains(&&league.db) {
+                return Err(ConfigError::new(format!("leagues[{index}].db"), "must be set and different from every other league's, including creds.mongo.db"));
+            }
+            if let Some(guild) = league.guilds.iter().find(|guild| guilds.contains(guild)) {
+                return Err(ConfigError::new(format!("leagues[{index}].guilds"), format!("guild {guild} already belongs to another league")));
+            }
+            if let Some(channel) = league.channels.iter().find(|channel| channels.contains(channel)) {
+                return Err(ConfigError::new(format!("leagues[{index}].channels"), format!("channel {channel} already belongs to another league")));
+            }
+            names.push(&league.name);
+            dbs.push(&league.db);
+            guilds.extend(league.guilds.iter());
+            channels.extend(league.channels.iter());
+        }
+        // commands are refused anywhere no league is played
+        if guilds.is_empty() && channels.is_empty() {
+            return Err(ConfigError::new("league.guilds", "no league lists a guild or channel to play in (set league.guilds, or EWAR_LEAGUE_GUILDS)"));
+        }
+
+        if self.register_commands.local.enabled && self.register_commands.local.guilds.is_empty() {
+            return Err(ConfigError::new("register_commands.local.guilds", "local registration is on but no guilds are listed"));
+        }
+
+        let positive = [
+            ("rating.trueskill.beta", self.rating.trueskill.beta),
+            ("rating.default_deviation", self.rating.default_deviation),
+            ("rating.provisional_deviation_threshold", self.rating.provisional_deviation_threshold),
+        ];
+        for (key, value) in positive {
+            if !(value.is_finite() && value > 0.0) {
+                return Err(ConfigError::new(key, "must be a positive number"));
+            }
+        }
+
+        let non_negative = [
+            ("rating.trueskill.default_dynamics", self.rating.trueskill.default_dynamics),
+            ("rating.soft_reset_delta_deviation", self.rating.soft_reset_delta_deviation),
+        ];
+        for (key, value) in non_negative {
+            if !(value.is_finite() && value >= 0.0) {
+                return Err(ConfigError::new(key, "can't be negative"));
+            }
+        }
+
+        if !self.rating.default_rating.is_finite() {
+            return Err(ConfigError::new("rating.default_rating", "must be a number"));
+        }
+        if !(0.0..1.0).contains(&self.rating.trueskill.draw_probability) {
+            return Err(ConfigError::new("rating.trueskill.draw_probability", "must be at least 0 and less than 1"));
+        }
+        if !(0.0..=1.0).contains(&self.rating.soft_reset_pull) {
+            return Err(ConfigError::new("rating.soft_reset_pull", "must be between 0 and 1"));
+        }
+        if self.league.rename_cooldown_days < 0 {
+            return Err(ConfigError::new("league.rename_cooldown_days", "can't be negative"));
+        }
+        if self.league.log_limit <= 0 {
+            return Err(ConfigError::new("league.log_limit", "must be positive"));
+        }
+        if let Err(err) = EnvFilter::try_new(&self.logging.level) {
+            return Err(ConfigError::new("logging.level", err));
+        }
+
+        Ok(())
+    }
+
+    /// make this the config everything else reads; only the first call has any effect
+    pub(crate) fn install(self) -> &'static Config {
+        INSTALLED.get_or_init(|| self)
+    }
+}
+
+/// the config in use, or defaults if none was installed
+pub(crate) fn installed() -> &'static Config {
+    INSTALLED.get_or_init(Config::default)
+}
+
+impl RatingConfig {
+    pub(crate) fn trueskill_config(&self) -> TrueSkillConfig {
+        TrueSkillConfig {
+            draw_probability: self.trueskill.draw_probability,
+            beta: self.trueskill.beta,
+            default_dynamics: self.trueskill.default_dynamics,
+        }
+    }
+
+    pub(crate) fn default_rating(&self) -> TrueSkillRating {
+        TrueSkillRating {
+            rating: self.default_rating,
+            uncertainty: self.default_deviation,
+        }
+    }
+}
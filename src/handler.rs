@@ -4,15 +4,16 @@ use serenity::all::{ActivityData, ActivityType, Context, EventHandler, OnlineSta
 use serenity::async_trait;
 use std::time::Duration;
 use tokio::time;
+use tracing::info;
 
 pub(crate) struct EWarBotHandler;
 
 #[async_trait]
 impl EventHandler for EWarBotHandler {
     async fn ready(&self, ctx: Context, ready_info: Ready) {
-        println!("ok, connected as {} (UID {})", ready_info.user.tag(), ready_info.user.id);
-        println!("using discord API version {}", ready_info.version);
-        println!("invite link: {}", bot_invite_url(ready_info.user.id, Permissions::empty(), true));
+        info!("ok, connected as {} (UID {})", ready_info.user.tag(), ready_info.user.id);
+        info!("using discord API version {}", ready_info.version);
+        info!("invite link: {}", bot_invite_url(ready_info.user.id, Permissions::empty(), true));
 
         tokio::spawn(async move {
             let mut interval = time::interval(Duration::from_secs(120));
@@ -42,6 +43,6 @@ impl EventHandler for EWarBotHandler {
                 interval.tick().await;
             }
         });
-        println!("status cycling active");
+        info!("status cycling active");
     }
 }
\ No newline at end of file
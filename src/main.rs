@@ -1,142 +1,312 @@
 mod commands;
+mod config;
 mod handler;
 mod model;
+mod store;
+#[cfg(test)]
+mod tests;
 mod util;
 
 use crate::commands::{ewar, maint, meta};
-use crate::model::StandingEventInner::InactivityDecay;
-use crate::model::{ApprovalStatus, LeagueInfo, Player, StandingEvent};
+use crate::config::Config;
+use crate::model::StandingEventInner::{InactivityDecay, Unsuspend};
+use crate::model::{ApprovalStatus, StandingEvent};
+use crate::util::checks;
+use crate::util::digest::weekly_digest;
+use crate::store::{Change, Store};
+use crate::util::indexes::ensure_indexes;
+use crate::util::league::{League, LeagueSettings};
+use crate::util::lease::new_holder_id;
+use crate::util::logging;
+use crate::util::logging::{LogLevel, TracedFramework};
+use crate::util::metrics;
+use crate::util::metrics::METRICS;
+use crate::util::migrations::{latest_schema_version, migrate};
+use crate::util::processor::RatingProcessor;
 use chrono::{TimeDelta, Utc};
+use clap::parser::ValueSource;
 use clap::ValueHint;
-use futures::TryStreamExt;
 use itertools::Itertools;
 use mongodb::bson::doc;
-use mongodb::Database;
+use mongodb::options::ClientOptions;
 use pluralizer::pluralize;
 use poise::{FrameworkOptions, PrefixFrameworkOptions};
-use serenity::all::GuildId;
-use serenity::all::{GatewayIntents, UserId};
+use serenity::all::{ChannelId, CreateMessage, GuildId, Http};
+use serenity::all::GatewayIntents;
 use serenity::Client;
-use std::collections::HashSet;
 use std::default::Default;
-use std::env;
+use std::future::Future;
+use std::iter;
 use std::path::PathBuf;
-use tokio_cron::{daily, Job, Scheduler};
+use std::process;
+use std::sync::Arc;
+use tokio_cron::{daily, hourly, weekly, Job, Scheduler};
+use tracing::{error, info, info_span, Instrument};
 
-async fn inactivity_decay_job(mongo_uri: String, mongo_db: String) -> Result<(), BotError> {
-    let mongo = mongodb::Client::with_uri_str(mongo_uri)
-        .await?
-        .database(&*mongo_db);
-
-    inactivity_decay_inner(&mongo).await
+/// run one league's share of a scheduled job in its own span, logging how it went
+async fn run_job(name: &'static str, league: &League, work: impl Future<Output = Result<(), BotError>>) {
+    async move {
+        let result = work.await;
+        METRICS.job(name, &league.name, result.is_ok());
+        match result {
+            Ok(()) => info!("job done"),
+            Err(err) => error!(error = %err, "job failed"),
+        }
+    }
+    .instrument(info_span!("job", name, league = %league.name))
+    .await
 }
 
-async fn inactivity_decay_inner(mongo: &Database) -> Result<(), BotError> {
-    let victims = mongo
-        .collection::<Player>("players")
-        .find(doc! {
-            "last_played": {
-                "$lt": bson::DateTime::from_chrono(Utc::now() - TimeDelta::days(7))
-            }
+async fn inactivity_decay_job(leagues: Vec<League>) {
+    for league in leagues {
+        run_job("inactivity_decay", &league, async {
+            inactivity_decay_inner(&league.store).await?;
+            // the decay event is approved already, so have it take effect now
+            league.processor.advance(None).await?;
+            Ok(())
         })
-        .await?
-        .try_filter_map(|p| async move { Ok(Some(p._id)) })
-        .try_collect::<Vec<_>>()
-        .await?;
-
-    let LeagueInfo {
-        available_event_number,
-        ..
-    } = mongo
-        .collection::<LeagueInfo>("league_info")
-        .find_one_and_update(doc! {}, doc! { "$inc": { "available_event_number": 1 } })
-        .await?
-        .expect("league_info struct missing");
-
-    mongo
-        .collection::<StandingEvent>("events")
-        .insert_one(StandingEvent {
-            _id: available_event_number,
+        .await;
+    }
+}
+
+async fn inactivity_decay_inner(store: &Store) -> Result<(), BotError> {
+    let victims = store.players.idle_since(Utc::now() - TimeDelta::days(7)).await?;
+
+    store.append_event(move |event_number| StandingEvent {
+        _id: event_number,
+        approval_status: Some(ApprovalStatus {
+            approved: true,
+            reviewer: None,
+        }),
+        inner: InactivityDecay {
+            victims: victims.clone(),
+            delta_deviation: 0.1,
+        },
+        approvals: vec![],
+        when: Utc::now(),
+    })
+    .await?;
+
+    Ok(())
+}
+
+async fn suspension_expiry_job(leagues: Vec<League>) {
+    for league in leagues {
+        run_job("suspension_expiry", &league, suspension_expiry_inner(&league.store, &league.processor)).await;
+    }
+}
+
+async fn suspension_expiry_inner(store: &Store, processor: &RatingProcessor) -> Result<(), BotError> {
+    let now = Utc::now();
+    let expired = store.players.suspensions_ended(now).await?;
+
+    if expired.is_empty() {
+        return Ok(());
+    }
+
+    processor.record(
+        vec![Change::Unsuspend { players: expired.clone() }],
+        move |event_number| StandingEvent {
+            _id: event_number,
             approval_status: Some(ApprovalStatus {
                 approved: true,
                 reviewer: None,
             }),
-            inner: InactivityDecay {
-                victims,
-                delta_deviation: 0.1,
+            approvals: vec![],
+            inner: Unsuspend {
+                victims: expired.clone(),
+                reason: String::from("suspension expired"),
             },
-            when: Utc::now(),
-        })
-        .await?;
+            when: now,
+        },
+    ).await?;
 
     Ok(())
 }
 
+async fn blacklist_expiry_job(leagues: Vec<League>) {
+    for league in leagues {
+        run_job("blacklist_expiry", &league, blacklist_expiry_inner(&league.store)).await;
+    }
+}
+
+async fn blacklist_expiry_inner(store: &Store) -> Result<(), BotError> {
+    store.league_info.expire_blacklist(Utc::now()).await
+}
+
+async fn weekly_digest_job(digests: Vec<(League, ChannelId)>, http: Arc<Http>) {
+    for (league, channel) in digests {
+        run_job("weekly_digest", &league, async {
+            let digest = weekly_digest(&league.store, Utc::now()).await?;
+            channel.send_message(&http, CreateMessage::new().embed(digest)).await?;
+            Ok(())
+        })
+        .await;
+    }
+}
+
 struct BotVars {
-    mongo: Database,
-    core_state_lock: async_std::sync::Arc<async_std::sync::Mutex<()>>,
-    league_moderators: HashSet<UserId>,
+    // the main league comes first
+    leagues: Vec<League>,
+    log_level: LogLevel,
 }
 
 #[tokio::main]
 async fn main() {
     let cmd = clap::command!("ewar-bot")
         .about("Discord bot for handling ranked Egyptian War backed by TrueSkill")
-        .arg(clap::arg!(<"config"> ".env file path")
+        .arg(clap::arg!(<"config"> "config file path; YAML, or a .env file for older setups")
             .value_parser(clap::value_parser!(PathBuf))
             .value_hint(ValueHint::FilePath)
             .required(false)
-            .default_value(".env"));
+            .default_value("config.yaml"))
+        .arg(clap::arg!(--"check-config" "check the config file and exit"))
+        .arg(clap::arg!(--"migrate" "bring every league database up to date and exit"));
 
     let args = cmd.get_matches();
-    dotenv::from_filename(args.get_one::<PathBuf>("config").expect("config file is bad path?")).ok();
-
-    let register_globally = env::var("EWAR_REGISTER_GLOBAL").is_ok();
-    let guilds_to_register_in = match env::var("EWAR_REGISTER_LOCAL").is_ok() {
-        true => env::var("EWAR_GUILDS")
-            .unwrap_or(String::from(""))
-            .split(",")
-            .map(String::from)
-            .map(|s| String::from(s.trim()))
-            .map(|id| GuildId::from(id.parse::<u64>().expect("guild id not valid snowflake")))
+    let mut config_path = args.get_one::<PathBuf>("config").expect("config file is bad path?").clone();
+    // older setups only have a .env next to the bot
+    if args.value_source("config") == Some(ValueSource::DefaultValue) && !config_path.exists() {
+        config_path = PathBuf::from(".env");
+    }
+
+    let config = match Config::load(&config_path) {
+        Ok(config) => config.install(),
+        Err(err) => {
+            eprintln!("{err}");
+            process::exit(1);
+        }
+    };
+
+    if args.get_flag("check-config") {
+        println!("config at {} ok", config_path.display());
+        return;
+    }
+
+    let log_level = logging::init(&config.logging);
+
+    let register_globally = config.register_commands.global;
+    let guilds_to_register_in = match config.register_commands.local.enabled {
+        true => config.register_commands.local.guilds.iter()
+            .map(|id| GuildId::from(*id))
             .collect_vec(),
         false => vec![],
     };
 
-    let mongo_uri = env::var("EWAR_MONGO_URI").expect("bad mongo uri");
-    let mongo_db = env::var("EWAR_MONGO_DB").expect("bad mongo db");
-
-    let moderator_discord_ids = env::var("EWAR_LEAGUE_MODERATORS")
-        .unwrap_or(String::from(""))
-        .split(",")
-        .map(String::from)
-        // dumb allocation but meh
-        .map(|s| String::from(s.trim()))
-        .map(|id| {
-            UserId::from(
-                id.parse::<u64>()
-                    .expect("league moderator discord id not valid snowflake"),
-            )
-        })
+    let token = config.token.clone();
+    // for work done outside of any command, like rating processors and scheduled jobs
+    let http = Arc::new(Http::new(&token));
+
+    let mongo_client = match ClientOptions::parse(&config.creds.mongo.uri).await.and_then(|mut options| {
+        options.command_event_handler = Some(metrics::mongo_event_handler());
+        mongodb::Client::with_options(options)
+    }) {
+        Ok(client) => client,
+        Err(err) => {
+            error!(error = %err, "couldn't connect to mongo");
+            process::exit(1);
+        }
+    };
+    let holder = new_holder_id();
+    // the main league's database also keeps every league's player identities
+    let directory = mongo_client.database(&config.creds.mongo.db);
+    let main_league = League::open(
+        String::from("main"),
+        directory.clone(),
+        &directory,
+        holder.clone(),
+        http.clone(),
+        LeagueSettings::main(&config.league),
+    );
+    let leagues = iter::once(main_league)
+        .chain(config.leagues.iter().map(|league| League::open(
+            league.name.clone(),
+            mongo_client.database(&league.db),
+            &directory,
+            holder.clone(),
+            http.clone(),
+            LeagueSettings::extra(league),
+        )))
+        .collect_vec();
+
+    // startup does this anyway, but it can be run ahead of a deploy
+    for league in leagues.iter() {
+        match migrate(&league.mongo, &directory, &holder).await {
+            Ok(applied) => {
+                for description in applied.iter() {
+                    info!(league = %league.name, description, "applied migration");
+                }
+                info!(league = %league.name, version = latest_schema_version(), "schema up to date");
+            }
+            Err(err) => {
+                error!(league = %league.name, error = %err, "couldn't migrate");
+                process::exit(1);
+            }
+        }
+    }
+    for league in leagues.iter() {
+        if let Err(err) = ensure_indexes(&league.mongo).await {
+            error!(league = %league.name, error = %err, "couldn't create indexes");
+            process::exit(1);
+        }
+    }
+    info!("indexes ok");
+    if args.get_flag("migrate") {
+        return;
+    }
+
+    let digests = iter::once(config.league.digest_channel)
+        .chain(config.leagues.iter().map(|league| league.digest_channel))
+        .zip(leagues.iter())
+        .filter_map(|(channel, league)| Some((league.clone(), ChannelId::from(channel?))))
         .collect_vec();
 
     let mut scheduler = Scheduler::local();
     {
-        let mongo_uri = mongo_uri.clone();
-        let mongo_db = mongo_db.clone();
+        let leagues = leagues.clone();
         scheduler.add(Job::named("inactivity_decay", daily("0"), move || {
-            let mongo_uri = mongo_uri.clone();
-            let mongo_db = mongo_db.clone();
-            async move {
-                match inactivity_decay_job(mongo_uri, mongo_db).await.err() {
-                    None => {}
-                    Some(err) => {
-                        eprintln!("{}", err)
-                    }
-                }
-            }
+            let leagues = leagues.clone();
+            async move { inactivity_decay_job(leagues).await }
+        }));
+        info!("cron job for decay ok")
+    }
+    {
+        let leagues = leagues.clone();
+        scheduler.add(Job::named("suspension_expiry", hourly("0"), move || {
+            let leagues = leagues.clone();
+            async move { suspension_expiry_job(leagues).await }
         }));
-        println!("cron job for decay ok")
+        info!("cron job for suspension expiry ok")
+    }
+    {
+        let leagues = leagues.clone();
+        scheduler.add(Job::named("blacklist_expiry", hourly("0"), move || {
+            let leagues = leagues.clone();
+            async move { blacklist_expiry_job(leagues).await }
+        }));
+        info!("cron job for blacklist expiry ok")
+    }
+    if digests.is_empty() {
+        info!("no digest channel, not posting weekly digest")
+    } else {
+        let http = http.clone();
+        scheduler.add(Job::named("weekly_digest", weekly("Mon", "12"), move || {
+            let digests = digests.clone();
+            let http = http.clone();
+            async move { weekly_digest_job(digests, http).await }
+        }));
+        info!("cron job for weekly digest ok")
+    }
+
+    if let Some(listen) = config.metrics.listen {
+        let leagues = leagues.clone();
+        tokio::spawn(async move {
+            if let Err(err) = metrics::serve(listen, leagues).await {
+                error!(error = %err, "metrics listener stopped");
+            }
+        });
+    } else {
+        info!("no metrics listen address, not serving metrics")
     }
 
     let framework = poise::Framework::<BotVars, BotError>::builder()
@@ -144,26 +314,43 @@ async fn main() {
             commands: vec![
                 meta::ping(),
                 meta::git(),
+                meta::league(),
                 maint::advance_pointer(),
                 maint::fsck(),
                 maint::force_reprocess(),
                 maint::do_decay(),
                 maint::pop_event(),
+                maint::indexes(),
+                maint::log_level(),
                 ewar::event::event(),
                 ewar::user::user(),
                 ewar::user::register(),
+                ewar::user::rename(),
                 ewar::game::game(),
                 ewar::moderation::review(),
                 ewar::moderation::unreviewed(),
                 ewar::moderation::penalize(),
                 ewar::moderation::force_register(),
                 ewar::moderation::lb_blacklist(),
+                ewar::moderation::suspend(),
+                ewar::moderation::unsuspend(),
+                ewar::moderation::merge_players(),
+                ewar::moderation::review_rename(),
+                ewar::moderation::link_discord(),
+                ewar::moderation::unlink_discord(),
                 ewar::leaderboard::leaderboard(),
+                ewar::leaderboard::live_leaderboard(),
+                ewar::records::records(),
+                ewar::season::close_season(),
             ],
             prefix_options: PrefixFrameworkOptions {
                 mention_as_prefix: true,
                 ..Default::default()
             },
+            command_check: Some(|ctx| Box::pin(checks::plays_in_a_league(ctx))),
+            pre_command: logging::pre_command,
+            post_command: logging::post_command,
+            on_error: logging::on_error,
             ..Default::default()
         })
         .setup(move |ctx, _ready, framework| {
@@ -173,9 +360,9 @@ async fn main() {
 
                 if register_globally {
                     poise::builtins::register_globally(ctx, &framework.options().commands).await?;
-                    println!("registered {commands_count} globally");
+                    info!("registered {commands_count} globally");
                 } else {
-                    println!("not registering {commands_count} globally");
+                    info!("not registering {commands_count} globally");
                 }
 
                 if !guilds_to_register_in.is_empty() {
@@ -183,36 +370,33 @@ async fn main() {
                         poise::builtins::register_in_guild(ctx, &framework.options().commands, *id)
                             .await?;
                     }
-                    println!(
+                    info!(
                         "registered {commands_count} locally in {}",
                         pluralize("guild", guilds_to_register_in.len() as isize, true)
                     );
                 }
 
-                let mongo = mongodb::Client::with_uri_str(mongo_uri)
-                    .await?
-                    .database(&*mongo_db);
-                mongo.run_command(doc! { "ping": 1 }).await?;
-                println!("mongo ok");
+                for league in leagues.iter() {
+                    league.mongo.run_command(doc! { "ping": 1 }).await?;
+                }
+                info!("mongo ok for {}", pluralize("league", leagues.len() as isize, true));
 
                 Ok(BotVars {
-                    mongo,
-                    core_state_lock: Default::default(),
-                    league_moderators: moderator_discord_ids.into_iter().collect(),
+                    leagues,
+                    log_level,
                 })
             })
         })
         .build();
 
-    let token = env::var("EWAR_DISCORD_TOKEN").expect("no discord token set");
     let mut client = Client::builder(&token, GatewayIntents::all())
         .event_handler(handler::EWarBotHandler)
-        .framework(framework)
+        .framework(TracedFramework::new(framework))
         .await
         .expect("couldn't make client");
 
     if let Err(why) = client.start().await {
-        println!("Client error: {why:?}");
+        error!(error = ?why, "client error");
     }
 }
 
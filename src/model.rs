@@ -1,4 +1,4 @@
-use bson::doc;
+use bson::{doc, Bson};
 use chrono::Utc;
 use serde::{Deserialize, Serialize};
 use skillratings::trueskill::TrueSkillRating;
@@ -6,17 +6,94 @@ use std::collections::HashMap;
 pub(crate) type EventNumber = u32;
 pub(crate) type GameID = i64;
 pub(crate) type PlayerID = i32;
+pub(crate) type SeasonID = u32;
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct LeagueInfo {
     pub(crate) first_unreviewed_event_number: EventNumber,
     pub(crate) available_game_id: GameID,
     pub(crate) available_event_number: EventNumber,
-    pub(crate) available_player_id: PlayerID,
-    pub(crate) leaderboard_blacklist: Vec<PlayerID>,
+    pub(crate) leaderboard_blacklist: Vec<BlacklistEntry>,
+    // achievements earned by events before this one have been announced; survives replays so nothing is announced twice
+    #[serde(default)]
+    pub(crate) achievements_announced_before: EventNumber,
+    // leaderboard message the bot keeps editing, if moderators have posted one
+    #[serde(default)]
+    pub(crate) live_leaderboard: Option<LiveLeaderboard>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct LiveLeaderboard {
+    pub(crate) channel: u64,
+    pub(crate) message: u64,
+    // the whole board as of the last edit, in order, to tell who moved since
+    pub(crate) standings: Vec<LiveStanding>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct LiveStanding {
+    pub(crate) player: PlayerID,
+    pub(crate) rating: f64,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(try_from = "Bson")]
+pub(crate) struct BlacklistEntry {
+    pub(crate) player: PlayerID,
+    // no ID means the entry predates keeping track
+    pub(crate) added_by: Option<PlayerID>,
+    pub(crate) reason: Option<String>,
+    #[serde(with = "crate::util::serialization::chrono_datetime_option_as_bson_datetime_option")]
+    pub(crate) when: Option<chrono::DateTime<Utc>>,
+    // no expiry is forever
+    #[serde(with = "crate::util::serialization::chrono_datetime_option_as_bson_datetime_option")]
+    pub(crate) expires: Option<chrono::DateTime<Utc>>,
+}
+
+// same shape as BlacklistEntry; needed so the fallback below doesn't recurse
+#[derive(Deserialize)]
+struct BlacklistEntryFields {
+    player: PlayerID,
+    added_by: Option<PlayerID>,
+    reason: Option<String>,
+    #[serde(default, with = "crate::util::serialization::chrono_datetime_option_as_bson_datetime_option")]
+    when: Option<chrono::DateTime<Utc>>,
+    #[serde(default, with = "crate::util::serialization::chrono_datetime_option_as_bson_datetime_option")]
+    expires: Option<chrono::DateTime<Utc>>,
+}
+
+impl TryFrom<Bson> for BlacklistEntry {
+    type Error = bson::de::Error;
+
+    // entries used to be bare player IDs
+    fn try_from(value: Bson) -> Result<Self, Self::Error> {
+        let legacy = |player| BlacklistEntry { player, added_by: None, reason: None, when: None, expires: None };
+
+        match value {
+            Bson::Int32(player) => Ok(legacy(player)),
+            Bson::Int64(player) => Ok(legacy(player as PlayerID)),
+            other => {
+                let BlacklistEntryFields { player, added_by, reason, when, expires } = bson::from_bson(other)?;
+                Ok(BlacklistEntry { player, added_by, reason, when, expires })
+            }
+        }
+    }
+}
+
+impl BlacklistEntry {
+    /// whether this entry still hides the player; expired ones may linger until the scheduler clears them
+    pub(crate) fn is_active(&self) -> bool {
+        self.expires.is_none_or(|expires| expires > Utc::now())
+    }
+}
+
+impl LeagueInfo {
+    pub(crate) fn blacklist_entry(&self, player: PlayerID) -> Option<&BlacklistEntry> {
+        self.leaderboard_blacklist.iter().find(|entry| entry.player == player && entry.is_active())
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) struct ApprovalStatus {
     pub(crate) approved: bool,
     // no ID is a system job
@@ -31,6 +108,12 @@ pub(crate) struct Game {
     // seconds long
     pub(crate) length: u32,
     // time submitted to system
+    // each player's chance of winning going in, in placement order; filled in as the game is processed
+    #[serde(default)]
+    pub(crate) expected: Vec<f64>,
+    // each player's leaderboard rating change, in placement order; filled in as the game is processed
+    #[serde(default)]
+    pub(crate) deltas: Vec<f64>,
 }
 
 #[derive(Serialize, Deserialize, Clone, Debug)]
@@ -45,32 +128,167 @@ pub(crate) enum StandingEventInner {
     SetStanding { victims: Vec<PlayerID>, new_rating: Option<f64>, new_deviation: Option<f64>, reason: String },
     ChangeStanding { victims: Vec<PlayerID>, delta_rating: Option<f64>, delta_deviation: Option<f64>, reason: String },
     JoinLeague { victims: Vec<PlayerID>, initial_rating: f64, initial_deviation: f64 },
+    // barred from posting, playing in, or signing off on games for a while; does not touch ratings
+    Suspend {
+        victims: Vec<PlayerID>,
+        #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+        until: chrono::DateTime<Utc>,
+        reason: String,
+    },
+    // suspension ended, either by expiry or by a moderator
+    Unsuspend { victims: Vec<PlayerID>, reason: String },
+    // duplicate account folded into the victim; every reference to it was re-pointed at the time
+    MergePlayers { victims: Vec<PlayerID>, merged: PlayerID, merged_username: String },
+    // moderator-approved change of username
+    Rename { victims: Vec<PlayerID>, old_username: String, new_username: String },
+    // discord account bound to or unbound from the victim
+    LinkDiscord { victims: Vec<PlayerID>, discord_id: u64 },
+    UnlinkDiscord { victims: Vec<PlayerID>, discord_id: u64 },
+    // a season closed: ratings pulled part of the way back to the default and made less certain
+    SoftReset { victims: Vec<PlayerID>, season: SeasonID, pull: f64, delta_deviation: f64 },
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+// every event variant that keeps the players it affects in a `victims` list
+pub(crate) static VICTIM_VARIANTS: [&str; 12] = [
+    "Penalty", "InactivityDecay", "SetStanding", "ChangeStanding", "JoinLeague", "Suspend", "Unsuspend",
+    "MergePlayers", "Rename", "LinkDiscord", "UnlinkDiscord", "SoftReset",
+];
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
 pub(crate) struct StandingEvent {
     pub(crate) _id: EventNumber,
     pub(crate) approval_status: Option<ApprovalStatus>,
+    // moderators who have voted to approve, counted toward the approval quorum
+    #[serde(default)]
+    pub(crate) approvals: Vec<PlayerID>,
     pub(crate) inner: StandingEventInner,
     #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
     pub(crate) when: chrono::DateTime<Utc>,
 }
 
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct Player {
     pub(crate) _id: PlayerID,
     pub(crate) username: String,
+    // for case-insensitive prefix searches; always username.to_lowercase()
+    #[serde(default)]
+    pub(crate) username_lower: String,
     pub(crate) rating: f64,
     pub(crate) deviation: f64,
     #[serde(with = "crate::util::serialization::chrono_datetime_option_as_bson_datetime_option")]
     pub(crate) last_played: Option<chrono::DateTime<Utc>>,
     pub(crate) discord_ids: Vec<u64>,
+    #[serde(default)]
+    pub(crate) suspension: Option<Suspension>,
+    // highest leaderboard rating reached
+    #[serde(default)]
+    pub(crate) peak: Option<RatingMark>,
+    // lowest leaderboard rating reached after leaving provisional status
+    #[serde(default)]
+    pub(crate) trough: Option<RatingMark>,
+    #[serde(default)]
+    pub(crate) achievements: Vec<Unlock>,
+}
+
+// who a player is in every league: one ID, name and set of discord accounts, shared by the player
+// documents of each league they have joined
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Identity {
+    pub(crate) _id: PlayerID,
+    pub(crate) username: String,
+    pub(crate) username_lower: String,
+    pub(crate) discord_ids: Vec<u64>,
+    // databases of the leagues joined
+    pub(crate) leagues: Vec<String>,
+}
+
+// a player's name without the rest of them, for suggestions and listings
+#[derive(Deserialize, Clone)]
+pub(crate) struct PlayerName {
+    pub(crate) _id: PlayerID,
+    pub(crate) username: String,
+}
+
+// a leaderboard rating at one point in the record; rebuilt whenever the record is replayed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct RatingMark {
+    pub(crate) rating: f64,
+    pub(crate) event: EventNumber,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub(crate) when: chrono::DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum Achievement {
+    FirstWin,
+    // finished above someone in the top 3 going in
+    GiantSlayer,
+    // won a game of 8 or more
+    BigTableWin,
+    FiftyGames,
+    // rating stopped being provisional
+    Established,
+}
+
+// an achievement and the event that earned it; rebuilt whenever the record is replayed
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Unlock {
+    pub(crate) kind: Achievement,
+    pub(crate) event: EventNumber,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub(crate) when: chrono::DateTime<Utc>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct Suspension {
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub(crate) until: chrono::DateTime<Utc>,
+    pub(crate) reason: String,
+    // the Suspend event that put this in place
+    pub(crate) event: EventNumber,
 }
 
 impl Player {
     pub(crate) fn rating_struct(&self) -> TrueSkillRating {
         TrueSkillRating { rating: self.rating, uncertainty: self.deviation }
     }
+
+    /// the suspension in force right now, if any; expired ones may linger until the scheduler clears them
+    pub(crate) fn active_suspension(&self) -> Option<&Suspension> {
+        self.suspension.as_ref().filter(|suspension| suspension.until > Utc::now())
+    }
+}
+
+// the final leaderboard of a closed season
+#[derive(Serialize, Deserialize, Clone)]
+pub(crate) struct Season {
+    pub(crate) _id: SeasonID,
+    // the SoftReset event that closed it
+    pub(crate) closed_by: EventNumber,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub(crate) when: chrono::DateTime<Utc>,
+    // in leaderboard order; players blacklisted at the time are left out
+    pub(crate) standings: Vec<SeasonStanding>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub(crate) struct SeasonStanding {
+    pub(crate) player: PlayerID,
+    // as of the close
+    pub(crate) username: String,
+    pub(crate) rating: f64,
+    pub(crate) deviation: f64,
+    // none if the rating was still provisional
+    pub(crate) place: Option<u32>,
+}
+
+// a player's pending request to change their username, at most one each
+#[derive(Serialize, Deserialize)]
+pub(crate) struct RenameRequest {
+    pub(crate) _id: PlayerID,
+    pub(crate) new_username: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    pub(crate) when: chrono::DateTime<Utc>,
 }
 
 // precompute rating at certain points in the timeline
@@ -0,0 +1,633 @@
+use crate::model::StandingEventInner::{ChangeStanding, GameEnd, InactivityDecay, JoinLeague, LinkDiscord, MergePlayers, Penalty, Rename, SetStanding, SoftReset, Suspend, UnlinkDiscord, Unsuspend};
+use crate::model::{Achievement, ApprovalStatus, EventNumber, GameID, Identity, LeagueInfo, Player, PlayerID, PlayerName, Season, SeasonID, StandingEvent, StandingEventInner, Suspension};
+use crate::store::{BuildEvent, BuildRegistration, Change, Counter, EventRepo, Fence, IdentityRepo, LeagueInfoRepo, Outcome, PlayerRepo, Processed, FENCED_OUT};
+use crate::BotError;
+use async_trait::async_trait;
+use bson::Document;
+use chrono::{DateTime, Utc};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::ops::{Deref, DerefMut};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// a league that lives in memory, for tests. every call takes the one lock over every league and identity,
+/// so each is atomic the same way the mongo version's transactions are
+pub(crate) struct MemoryStore {
+    // stands in for the league's database name
+    league: String,
+    world: Arc<Mutex<World>>,
+}
+
+// what the mongo version keeps across every league's database
+#[derive(Clone)]
+struct World {
+    available_player_id: PlayerID,
+    identities: BTreeMap<PlayerID, Identity>,
+    leagues: BTreeMap<String, State>,
+}
+
+// one league's part of the world, locked along with the rest
+struct LeagueGuard<'a> {
+    world: MutexGuard<'a, World>,
+    league: &'a str,
+}
+
+impl Deref for LeagueGuard<'_> {
+    type Target = State;
+
+    fn deref(&self) -> &State {
+        &self.world.leagues[self.league]
+    }
+}
+
+impl DerefMut for LeagueGuard<'_> {
+    fn deref_mut(&mut self) -> &mut State {
+        self.world.league_mut(self.league)
+    }
+}
+
+#[derive(Clone)]
+struct State {
+    league_info: LeagueInfo,
+    // the highest fencing token that has written league_info
+    fencing_token: i64,
+    players: BTreeMap<PlayerID, Player>,
+    events: BTreeMap<EventNumber, StandingEvent>,
+    seasons: BTreeMap<SeasonID, Season>,
+}
+
+impl State {
+    /// refuse a write from a lease holder older than one that already wrote
+    fn fence(&mut self, fence: &dyn Fence) -> Result<(), BotError> {
+        if fence.fencing_token() < self.fencing_token {
+            return Err(FENCED_OUT.into());
+        }
+        self.fencing_token = fence.fencing_token();
+        Ok(())
+    }
+
+    fn player_mut(&mut self, id: PlayerID) -> Result<&mut Player, BotError> {
+        self.players.get_mut(&id).ok_or_else(|| format!("player {id} DNE").into())
+    }
+
+    /// what the unique indexes enforce in mongo, checked after each write the way they are
+    fn check_unique(&self) -> Result<(), BotError> {
+        let mut usernames = HashSet::new();
+        let mut discord_ids = HashSet::new();
+        for player in self.players.values() {
+            if !usernames.insert(&player.username) {
+                return Err(format!("username {} is taken", player.username).into());
+            }
+            if !player.discord_ids.iter().all(|id| discord_ids.insert(*id)) {
+                return Err("discord account is already bound to a player".into());
+            }
+        }
+        Ok(())
+    }
+
+    /// rewrite every reference to one player in the record so it refers to another
+    fn repoint_player(&mut self, from: PlayerID, into: PlayerID) {
+        // both accounts joined the league, but only the earlier join may set a rating on replay
+        let first_join = |player: PlayerID| self.events.values()
+            .find(|event| matches!(&event.inner, JoinLeague { victims, .. } if victims.contains(&player)))
+            .map(|event| event._id);
+        if let (Some(from_join), Some(into_join)) = (first_join(from), first_join(into)) {
+            let (later_join, later_joiner) = if from_join > into_join { (from_join, from) } else { (into_join, into) };
+            if from_join != into_join {
+                if let Some(JoinLeague { victims, .. }) = self.events.get_mut(&later_join).map(|event| &mut event.inner) {
+                    victims.retain(|victim| *victim != later_joiner);
+                }
+            }
+        }
+
+        for event in self.events.values_mut() {
+            if let Some(victims) = victims_mut(&mut event.inner) {
+                repoint(victims, from, into);
+            }
+            if let GameEnd(game) = &mut event.inner {
+                repoint(&mut game.ranking, from, into);
+            }
+            if let Some(status) = &mut event.approval_status {
+                if status.reviewer == Some(from) {
+                    status.reviewer = Some(into);
+                }
+            }
+            repoint(&mut event.approvals, from, into);
+        }
+
+        for standing in self.seasons.values_mut().flat_map(|season| season.standings.iter_mut()) {
+            if standing.player == from {
+                standing.player = into;
+            }
+        }
+
+        for entry in self.league_info.leaderboard_blacklist.iter_mut() {
+            if entry.player == from { entry.player = into }
+            if entry.added_by == Some(from) { entry.added_by = Some(into) }
+        }
+    }
+}
+
+impl World {
+    fn league_mut(&mut self, league: &str) -> &mut State {
+        self.leagues.get_mut(league).expect("no such memory league")
+    }
+
+    fn identity_mut(&mut self, id: PlayerID) -> Result<&mut Identity, BotError> {
+        self.identities.get_mut(&id).ok_or_else(|| format!("identity {id} DNE").into())
+    }
+
+    /// bring the player documents of every league the identity plays in up to date with it
+    fn copy_identity(&mut self, id: PlayerID) -> Result<(), BotError> {
+        let identity = self.identity_mut(id)?.clone();
+        for league in &identity.leagues {
+            if let Some(player) = self.league_mut(league).players.get_mut(&id) {
+                player.username = identity.username.clone();
+                player.username_lower = identity.username_lower.clone();
+                player.discord_ids = identity.discord_ids.clone();
+            }
+        }
+        Ok(())
+    }
+
+    /// what the unique indexes enforce in mongo, checked after each write the way they are
+    fn check_unique(&self) -> Result<(), BotError> {
+        let mut usernames = HashSet::new();
+        let mut discord_ids = HashSet::new();
+        for identity in self.identities.values() {
+            if !usernames.insert(&identity.username_lower) {
+                return Err(format!("username {} is taken", identity.username).into());
+            }
+            if !identity.discord_ids.iter().all(|id| discord_ids.insert(*id)) {
+                return Err("discord account is already bound to a player".into());
+            }
+        }
+        self.leagues.values().try_for_each(State::check_unique)
+    }
+
+    /// make one change in `league` alongside appending `event`
+    fn apply(&mut self, league: &str, event: &StandingEvent, change: Change) -> Result<(), BotError> {
+        match change {
+            Change::Suspend { player, until, reason } => {
+                self.league_mut(league).player_mut(player)?.suspension = Some(Suspension { until, reason, event: event._id });
+            }
+            Change::Unsuspend { players } => {
+                let state = self.league_mut(league);
+                for player in players {
+                    if let Some(player) = state.players.get_mut(&player) {
+                        player.suspension = None;
+                    }
+                }
+            }
+            Change::Rename { player, username } => {
+                let identity = self.identity_mut(player)?;
+                identity.username_lower = username.to_lowercase();
+                identity.username = username;
+                self.copy_identity(player)?;
+            }
+            Change::LinkDiscord { player, discord_id } => {
+                let identity = self.identity_mut(player)?;
+                if !identity.discord_ids.contains(&discord_id) {
+                    identity.discord_ids.push(discord_id);
+                }
+                self.copy_identity(player)?;
+            }
+            Change::UnlinkDiscord { player, discord_id } => {
+                self.identity_mut(player)?.discord_ids.retain(|id| *id != discord_id);
+                self.copy_identity(player)?;
+            }
+            Change::Merge { from, into, suspension } => {
+                let state = self.league_mut(league);
+                if !state.players.contains_key(&from) {
+                    return Err(format!("player {from} DNE").into());
+                }
+                state.repoint_player(from, into);
+                state.players.remove(&from);
+                state.player_mut(into)?.suspension = suspension;
+
+                // the discord IDs are unique across identities, so they can only move once `from` has let go of them
+                let merged = self.identity_mut(from)?;
+                merged.leagues.retain(|joined| joined != league);
+                if merged.leagues.is_empty() {
+                    let merged = self.identities.remove(&from).expect("merged identity vanished");
+                    let kept = self.identity_mut(into)?;
+                    for id in merged.discord_ids {
+                        if !kept.discord_ids.contains(&id) {
+                            kept.discord_ids.push(id);
+                        }
+                    }
+                    self.copy_identity(into)?;
+                }
+            }
+            Change::ArchiveSeason { season, standings } => {
+                let state = self.league_mut(league);
+                if state.seasons.contains_key(&season) {
+                    return Err(format!("season {season} is already archived").into());
+                }
+                state.seasons.insert(season, Season { _id: season, closed_by: event._id, when: event.when, standings });
+            }
+        }
+
+        self.check_unique()
+    }
+}
+
+// swap one player for another in a list, without listing anyone twice
+fn repoint(players: &mut Vec<PlayerID>, from: PlayerID, into: PlayerID) {
+    if players.contains(&into) {
+        players.retain(|player| *player != from);
+    } else {
+        for player in players.iter_mut().filter(|player| **player == from) {
+            *player = into;
+        }
+    }
+}
+
+fn name(player: &Player) -> PlayerName {
+    PlayerName { _id: player._id, username: player.username.clone() }
+}
+
+fn victims_mut(inner: &mut StandingEventInner) -> Option<&mut Vec<PlayerID>> {
+    match inner {
+        Penalty { victims, .. } | InactivityDecay { victims, .. } | SetStanding { victims, .. }
+        | ChangeStanding { victims, .. } | JoinLeague { victims, .. } | Suspend { victims, .. }
+        | Unsuspend { victims, .. } | MergePlayers { victims, .. } | Rename { victims, .. }
+        | LinkDiscord { victims, .. } | UnlinkDiscord { victims, .. } | SoftReset { victims, .. } => Some(victims),
+        GameEnd(_) => None,
+    }
+}
+
+/// stands in for the ratings lease when only one task ever touches the store
+pub(crate) struct SoleWriter;
+
+#[async_trait]
+impl Fence for SoleWriter {
+    async fn check(&mut self) -> Result<(), BotError> {
+        Ok(())
+    }
+
+    fn fencing_token(&self) -> i64 {
+        0
+    }
+}
+
+impl State {
+    /// the same league_info a new mongo league is bootstrapped with
+    fn new() -> Self {
+        State {
+            league_info: LeagueInfo {
+                first_unreviewed_event_number: 0,
+                available_game_id: 0,
+                available_event_number: 0,
+                leaderboard_blacklist: vec![],
+                achievements_announced_before: 0,
+                live_leaderboard: None,
+            },
+            fencing_token: 0,
+            players: BTreeMap::new(),
+            events: BTreeMap::new(),
+            seasons: BTreeMap::new(),
+        }
+    }
+}
+
+impl MemoryStore {
+    /// fresh, empty leagues sharing one set of identities
+    pub(crate) fn leagues(count: usize) -> Vec<Self> {
+        let names = (0..count).map(|index| format!("league{index}")).collect::<Vec<_>>();
+        let world = Arc::new(Mutex::new(World {
+            available_player_id: 1,
+            identities: BTreeMap::new(),
+            leagues: names.iter().map(|name| (name.clone(), State::new())).collect(),
+        }));
+        names.into_iter().map(|league| MemoryStore { league, world: world.clone() }).collect()
+    }
+
+    fn world(&self) -> MutexGuard<'_, World> {
+        self.world.lock().expect("memory store poisoned")
+    }
+
+    fn state(&self) -> LeagueGuard<'_> {
+        LeagueGuard { world: self.world(), league: &self.league }
+    }
+}
+
+#[async_trait]
+impl PlayerRepo for MemoryStore {
+    async fn player(&self, id: PlayerID) -> Result<Option<Player>, BotError> {
+        Ok(self.state().players.get(&id).cloned())
+    }
+
+    async fn players(&self) -> Result<Vec<Player>, BotError> {
+        Ok(self.state().players.values().cloned().collect())
+    }
+
+    async fn idle_since(&self, cutoff: DateTime<Utc>) -> Result<Vec<PlayerID>, BotError> {
+        Ok(self.state().players.values()
+            .filter(|player| player.last_played.is_some_and(|last_played| last_played < cutoff))
+            .map(|player| player._id)
+            .collect())
+    }
+
+    async fn suspensions_ended(&self, now: DateTime<Utc>) -> Result<Vec<PlayerID>, BotError> {
+        Ok(self.state().players.values()
+            .filter(|player| player.suspension.as_ref().is_some_and(|suspension| suspension.until <= now))
+            .map(|player| player._id)
+            .collect())
+    }
+
+    async fn unlocked_between(&self, kind: Achievement, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<Player>, BotError> {
+        Ok(self.state().players.values()
+            .filter(|player| player.achievements.iter()
+                .any(|unlock| unlock.kind == kind && since <= unlock.when && unlock.when < until))
+            .cloned()
+            .collect())
+    }
+
+    async fn names(&self) -> Result<Vec<PlayerName>, BotError> {
+        Ok(self.state().players.values().map(name).collect())
+    }
+
+    async fn names_of(&self, ids: &[PlayerID]) -> Result<Vec<PlayerName>, BotError> {
+        let state = self.state();
+        Ok(ids.iter().filter_map(|id| state.players.get(id)).map(name).collect())
+    }
+
+    async fn names_starting_with(&self, prefix: &str, limit: usize) -> Result<Vec<PlayerName>, BotError> {
+        let prefix = prefix.to_lowercase();
+        let mut found = self.state().players.values()
+            .filter(|player| player.username_lower.starts_with(&prefix))
+            .map(name)
+            .collect::<Vec<_>>();
+        found.sort_by_key(|player| player.username.to_lowercase());
+        found.truncate(limit);
+        Ok(found)
+    }
+
+    async fn register(&self, identity: Option<PlayerID>, build: BuildRegistration) -> Result<Player, BotError> {
+        let mut world = self.world();
+        // work on a copy so a refused registration draws nothing, as an aborted transaction would
+        let mut changed = world.clone();
+        let id = identity.unwrap_or_else(|| {
+            changed.available_player_id += 1;
+            changed.available_player_id - 1
+        });
+        if changed.league_mut(&self.league).players.contains_key(&id) {
+            return Err(format!("player {id} already plays in this league").into());
+        }
+        let (mut player, event) = build(id, changed.league_mut(&self.league).league_info.available_event_number);
+
+        match identity {
+            Some(id) => {
+                let identity = changed.identity_mut(id)?;
+                identity.leagues.push(self.league.clone());
+                player.username = identity.username.clone();
+                player.username_lower = identity.username_lower.clone();
+                player.discord_ids = identity.discord_ids.clone();
+            }
+            None => {
+                changed.identities.insert(id, Identity {
+                    _id: id,
+                    username: player.username.clone(),
+                    username_lower: player.username_lower.clone(),
+                    discord_ids: player.discord_ids.clone(),
+                    leagues: vec![self.league.clone()],
+                });
+            }
+        }
+
+        let state = changed.league_mut(&self.league);
+        state.league_info.available_event_number += 1;
+        state.players.insert(player._id, player.clone());
+        state.events.insert(event._id, event);
+        changed.check_unique()?;
+        *world = changed;
+        Ok(player)
+    }
+}
+
+#[async_trait]
+impl IdentityRepo for MemoryStore {
+    async fn by_discord(&self, discord_id: u64) -> Result<Option<Identity>, BotError> {
+        Ok(self.world().identities.values().find(|identity| identity.discord_ids.contains(&discord_id)).cloned())
+    }
+
+    async fn by_username(&self, username: &str) -> Result<Option<Identity>, BotError> {
+        let username_lower = username.to_lowercase();
+        Ok(self.world().identities.values().find(|identity| identity.username_lower == username_lower).cloned())
+    }
+}
+
+#[async_trait]
+impl EventRepo for MemoryStore {
+    async fn event(&self, id: EventNumber) -> Result<Option<StandingEvent>, BotError> {
+        Ok(self.state().events.get(&id).cloned())
+    }
+
+    async fn game(&self, game_id: GameID) -> Result<Option<StandingEvent>, BotError> {
+        Ok(self.state().events.values()
+            .find(|event| matches!(&event.inner, GameEnd(game) if game.game_id == game_id))
+            .cloned())
+    }
+
+    async fn events_from(&self, first: EventNumber) -> Result<Vec<StandingEvent>, BotError> {
+        Ok(self.state().events.range(first..).map(|(_, event)| event.clone()).collect())
+    }
+
+    async fn raw_events(&self) -> Result<Vec<Document>, BotError> {
+        let state = self.state();
+        let mut raw = Vec::with_capacity(state.events.len());
+        for event in state.events.values() {
+            raw.push(bson::to_document(event)?);
+        }
+        Ok(raw)
+    }
+
+    async fn append(&self, also: &[Counter], build: BuildEvent) -> Result<StandingEvent, BotError> {
+        let mut state = self.state();
+        let event = build(&state.league_info);
+        assert_eq!(event._id, state.league_info.available_event_number, "event appended under a number it did not reserve");
+
+        state.league_info.available_event_number += 1;
+        for counter in also {
+            match counter {
+                Counter::GameId => state.league_info.available_game_id += 1,
+            }
+        }
+        state.events.insert(event._id, event.clone());
+        Ok(event)
+    }
+
+    async fn record(&self, changes: Vec<Change>, build: BuildEvent, fence: &mut dyn Fence) -> Result<StandingEvent, BotError> {
+        fence.check().await?;
+
+        let mut world = self.world();
+        // work on a copy so a failed change leaves nothing behind, as an aborted transaction would
+        let mut changed = world.clone();
+        let state = changed.league_mut(&self.league);
+        state.fence(fence)?;
+        let event = build(&state.league_info);
+        assert_eq!(event._id, state.league_info.available_event_number, "event appended under a number it did not reserve");
+
+        for change in changes {
+            changed.apply(&self.league, &event, change)?;
+        }
+        let state = changed.league_mut(&self.league);
+        state.league_info.available_event_number += 1;
+        state.events.insert(event._id, event.clone());
+        *world = changed;
+        Ok(event)
+    }
+
+    async fn add_approval(&self, id: EventNumber, reviewer: PlayerID) -> Result<Option<StandingEvent>, BotError> {
+        let mut state = self.state();
+        let Some(event) = state.events.get_mut(&id).filter(|event| event.approval_status.is_none()) else {
+            return Ok(None);
+        };
+        if !event.approvals.contains(&reviewer) {
+            event.approvals.push(reviewer);
+        }
+        Ok(Some(event.clone()))
+    }
+
+    async fn decide(&self, id: EventNumber, status: ApprovalStatus) -> Result<bool, BotError> {
+        let mut state = self.state();
+        match state.events.get_mut(&id).filter(|event| event.approval_status.is_none()) {
+            None => Ok(false),
+            Some(event) => {
+                event.approval_status = Some(status);
+                Ok(true)
+            }
+        }
+    }
+
+    async fn approved_game_counts(&self, before: EventNumber) -> Result<HashMap<PlayerID, u64>, BotError> {
+        let mut counts = HashMap::new();
+        for event in self.state().events.range(..before).map(|(_, event)| event)
+            .filter(|event| event.approval_status.as_ref().is_some_and(|status| status.approved)) {
+            if let GameEnd(game) = &event.inner {
+                for player in &game.ranking {
+                    *counts.entry(*player).or_default() += 1;
+                }
+            }
+        }
+        Ok(counts)
+    }
+
+    async fn count_unreviewed(&self) -> Result<u64, BotError> {
+        Ok(self.state().events.values()
+            .filter(|event| event.approval_status.is_none() && matches!(event.inner, GameEnd(_)))
+            .count() as u64)
+    }
+
+    async fn unreviewed_games(&self) -> Result<Vec<StandingEvent>, BotError> {
+        Ok(self.state().events.values()
+            .filter(|event| event.approval_status.is_none() && matches!(event.inner, GameEnd(_)))
+            .cloned()
+            .collect())
+    }
+
+    async fn approved_between(&self, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<StandingEvent>, BotError> {
+        Ok(self.state().events.values()
+            .filter(|event| event.approval_status.as_ref().is_some_and(|status| status.approved))
+            .filter(|event| since <= event.when && event.when < until)
+            .cloned()
+            .collect())
+    }
+
+    async fn pop(&self, id: EventNumber, fence: &mut dyn Fence) -> Result<Option<StandingEvent>, BotError> {
+        fence.check().await?;
+
+        let mut state = self.state();
+        if state.league_info.available_event_number != id + 1 {
+            return Ok(None);
+        }
+        state.fence(fence)?;
+        let Some(evt) = state.events.remove(&id) else { return Ok(None) };
+
+        state.league_info.available_event_number -= 1;
+        if let GameEnd(_) = evt.inner {
+            state.league_info.available_game_id -= 1;
+        }
+        state.league_info.first_unreviewed_event_number = state.league_info.first_unreviewed_event_number.min(evt._id);
+        Ok(Some(evt))
+    }
+}
+
+#[async_trait]
+impl LeagueInfoRepo for MemoryStore {
+    async fn league_info(&self) -> Result<LeagueInfo, BotError> {
+        Ok(self.state().league_info.clone())
+    }
+
+    async fn current_season(&self) -> Result<SeasonID, BotError> {
+        Ok(self.state().seasons.len() as SeasonID + 1)
+    }
+
+    async fn advance_pointer(&self, to: EventNumber, processed: &Processed, fence: &mut dyn Fence) -> Result<(), BotError> {
+        let mut state = self.state();
+        // work on a copy so a failed write leaves nothing behind, as an aborted transaction would
+        let mut changed = state.clone();
+        changed.fence(fence)?;
+        let league_info = &mut changed.league_info;
+        league_info.first_unreviewed_event_number = league_info.first_unreviewed_event_number.max(to);
+        league_info.achievements_announced_before = league_info.achievements_announced_before.max(to);
+
+        for (id, rating) in &processed.ratings {
+            let player = changed.player_mut(*id)?;
+            player.rating = rating.rating;
+            player.deviation = rating.uncertainty;
+        }
+        if let Some((ids, when)) = &processed.played {
+            for id in ids {
+                let player = changed.player_mut(*id)?;
+                player.last_played = player.last_played.max(Some(*when));
+            }
+        }
+        if let Some(Outcome { event, expected, deltas }) = &processed.outcome {
+            if let Some(StandingEvent { inner: GameEnd(game), .. }) = changed.events.get_mut(event) {
+                game.expected = expected.clone();
+                game.deltas = deltas.clone();
+            }
+        }
+        for (id, mark) in &processed.peaks {
+            changed.player_mut(*id)?.peak = Some(mark.clone());
+        }
+        for (id, mark) in &processed.troughs {
+            changed.player_mut(*id)?.trough = Some(mark.clone());
+        }
+        for (id, unlock) in &processed.unlocks {
+            let player = changed.player_mut(*id)?;
+            if !player.achievements.iter().any(|had| had.kind == unlock.kind) {
+                player.achievements.push(unlock.clone());
+            }
+        }
+
+        *state = changed;
+        Ok(())
+    }
+
+    async fn reset(&self, fence: &mut dyn Fence) -> Result<(), BotError> {
+        let mut state = self.state();
+        state.fence(fence)?;
+        state.league_info.first_unreviewed_event_number = 0;
+        for player in state.players.values_mut() {
+            player.rating = 0.0;
+            player.deviation = 0.0;
+            player.last_played = None;
+            player.peak = None;
+            player.trough = None;
+            player.achievements.clear();
+        }
+        Ok(())
+    }
+
+    async fn expire_blacklist(&self, now: DateTime<Utc>) -> Result<(), BotError> {
+        self.state().league_info.leaderboard_blacklist.retain(|entry| entry.expires.is_none_or(|expires| expires > now));
+        Ok(())
+    }
+
+    async fn replace(&self, info: LeagueInfo) -> Result<(), BotError> {
+        self.state().league_info = info;
+        Ok(())
+    }
+}
@@ -0,0 +1,211 @@
+//! where a league's players, events and league_info live. the rating, review and maintenance flows
+//! go through these traits, so they run the same against mongo or against memory in tests
+
+#[cfg(test)]
+pub(crate) mod memory;
+pub(crate) mod mongo;
+
+use crate::model::{Achievement, ApprovalStatus, EventNumber, GameID, Identity, LeagueInfo, Player, PlayerID, PlayerName, RatingMark, SeasonID, SeasonStanding, StandingEvent, Suspension, Unlock};
+#[cfg(test)]
+use crate::store::memory::MemoryStore;
+use crate::store::mongo::MongoStore;
+use crate::BotError;
+use async_trait::async_trait;
+use bson::Document;
+use chrono::{DateTime, Utc};
+use mongodb::Database;
+use skillratings::trueskill::TrueSkillRating;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+/// builds the event for a freshly drawn number; the league_info passed in is as it was before the draw,
+/// so its counters are the ones reserved. called again if the append has to be retried
+pub(crate) type BuildEvent = Box<dyn Fn(&LeagueInfo) -> StandingEvent + Send + Sync>;
+
+/// builds a new player and the event that admits them, given their player ID and the event number drawn for them.
+/// called again if the registration has to be retried
+pub(crate) type BuildRegistration = Box<dyn Fn(PlayerID, EventNumber) -> (Player, StandingEvent) + Send + Sync>;
+
+/// league_info counters that can be drawn along with an event number
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Counter {
+    GameId,
+}
+
+/// a write to players, or to what refers to them, that lands together with the event recording it
+#[derive(Clone, Debug)]
+pub(crate) enum Change {
+    /// the suspension points back at the event
+    Suspend { player: PlayerID, until: DateTime<Utc>, reason: String },
+    Unsuspend { players: Vec<PlayerID> },
+    /// settles the player's pending rename request too. renames the player in every league they play in
+    Rename { player: PlayerID, username: String },
+    /// in every league the player plays in
+    LinkDiscord { player: PlayerID, discord_id: u64 },
+    /// in every league the player plays in
+    UnlinkDiscord { player: PlayerID, discord_id: u64 },
+    /// re-point every reference to `from` at `into`, give `into` `suspension`, then take `from` out of the league.
+    /// `from`'s discord accounts move to `into` once `from` plays in no league at all
+    Merge { from: PlayerID, into: PlayerID, suspension: Option<Suspension> },
+    /// the final standings of a season the event closes
+    ArchiveSeason { season: SeasonID, standings: Vec<SeasonStanding> },
+}
+
+/// what processing one event did to present-day standings. none of it is written until the pointer
+/// moves past the event, and then all of it is, under the same fence
+#[derive(Default)]
+pub(crate) struct Processed {
+    pub(crate) ratings: Vec<(PlayerID, TrueSkillRating)>,
+    /// who played, and when; `last_played` only ever moves forward
+    pub(crate) played: Option<(Vec<PlayerID>, DateTime<Utc>)>,
+    pub(crate) outcome: Option<Outcome>,
+    pub(crate) peaks: Vec<(PlayerID, RatingMark)>,
+    pub(crate) troughs: Vec<(PlayerID, RatingMark)>,
+    /// only achievements the player didn't have yet
+    pub(crate) unlocks: Vec<(PlayerID, Unlock)>,
+}
+
+/// how likely each placement in a game was and what it did to each player, for stats and records
+pub(crate) struct Outcome {
+    pub(crate) event: EventNumber,
+    pub(crate) expected: Vec<f64>,
+    pub(crate) deltas: Vec<f64>,
+}
+
+/// proof that the ratings lease is held. pointer moves, with the rating writes that go along, and event pops
+/// are refused once someone holding a later fencing token has written
+#[async_trait]
+pub(crate) trait Fence: Send {
+    /// stop before writing if the claim may have lapsed
+    async fn check(&mut self) -> Result<(), BotError>;
+    fn fencing_token(&self) -> i64;
+}
+
+#[async_trait]
+pub(crate) trait PlayerRepo: Send + Sync {
+    async fn player(&self, id: PlayerID) -> Result<Option<Player>, BotError>;
+    async fn players(&self) -> Result<Vec<Player>, BotError>;
+    /// players who have played, but not since `cutoff`
+    async fn idle_since(&self, cutoff: DateTime<Utc>) -> Result<Vec<PlayerID>, BotError>;
+    /// players whose suspension ran out by `now` but hasn't been lifted
+    async fn suspensions_ended(&self, now: DateTime<Utc>) -> Result<Vec<PlayerID>, BotError>;
+    /// players who unlocked `kind` at or after `since` and before `until`
+    async fn unlocked_between(&self, kind: Achievement, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<Player>, BotError>;
+    /// everyone's name
+    async fn names(&self) -> Result<Vec<PlayerName>, BotError>;
+    /// the names of these players, in no particular order; IDs nobody has are skipped
+    async fn names_of(&self, ids: &[PlayerID]) -> Result<Vec<PlayerName>, BotError>;
+    /// up to `limit` names starting with `prefix`, ignoring case, in username order
+    async fn names_starting_with(&self, prefix: &str, limit: usize) -> Result<Vec<PlayerName>, BotError>;
+    /// join the league as an existing identity, or as a new one with a freshly drawn player ID, writing the
+    /// player and their join event together. joining as an identity keeps its name and discord accounts
+    async fn register(&self, identity: Option<PlayerID>, build: BuildRegistration) -> Result<Player, BotError>;
+}
+
+/// the identities shared by every league
+#[async_trait]
+pub(crate) trait IdentityRepo: Send + Sync {
+    async fn by_discord(&self, discord_id: u64) -> Result<Option<Identity>, BotError>;
+    /// ignoring case
+    async fn by_username(&self, username: &str) -> Result<Option<Identity>, BotError>;
+}
+
+#[async_trait]
+pub(crate) trait EventRepo: Send + Sync {
+    async fn event(&self, id: EventNumber) -> Result<Option<StandingEvent>, BotError>;
+    async fn game(&self, game_id: GameID) -> Result<Option<StandingEvent>, BotError>;
+    /// in record order
+    async fn events_from(&self, first: EventNumber) -> Result<Vec<StandingEvent>, BotError>;
+    /// every event as stored, in record order, whether or not it still parses
+    async fn raw_events(&self) -> Result<Vec<Document>, BotError>;
+    /// draw the next event number, plus one of each counter in `also`, and append the event built for it
+    async fn append(&self, also: &[Counter], build: BuildEvent) -> Result<StandingEvent, BotError>;
+    /// append the event built for the next number and make `changes`, in order, all or nothing
+    async fn record(&self, changes: Vec<Change>, build: BuildEvent, fence: &mut dyn Fence) -> Result<StandingEvent, BotError>;
+    /// add a moderator's approval to a game still waiting on review; `None` if it has been decided already
+    async fn add_approval(&self, id: EventNumber, reviewer: PlayerID) -> Result<Option<StandingEvent>, BotError>;
+    /// settle review of an event; false if it was already settled
+    async fn decide(&self, id: EventNumber, status: ApprovalStatus) -> Result<bool, BotError>;
+    /// how many approved games each player was in, among the events before `before`
+    async fn approved_game_counts(&self, before: EventNumber) -> Result<HashMap<PlayerID, u64>, BotError>;
+    /// games still waiting on review
+    async fn count_unreviewed(&self) -> Result<u64, BotError>;
+    /// the games still waiting on review, in record order
+    async fn unreviewed_games(&self) -> Result<Vec<StandingEvent>, BotError>;
+    /// approved events from at or after `since` and before `until`, in record order
+    async fn approved_between(&self, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<StandingEvent>, BotError>;
+    /// delete the latest event and give its number back, if it is still the latest
+    async fn pop(&self, id: EventNumber, fence: &mut dyn Fence) -> Result<Option<StandingEvent>, BotError>;
+}
+
+#[async_trait]
+pub(crate) trait LeagueInfoRepo: Send + Sync {
+    async fn league_info(&self) -> Result<LeagueInfo, BotError>;
+    /// the season in progress; closed seasons are numbered from 1
+    async fn current_season(&self) -> Result<SeasonID, BotError>;
+    /// move the approve pointer (and the announced-achievements mark with it) forward to `to`, and write
+    /// what processing the event just before it did; all or nothing
+    async fn advance_pointer(&self, to: EventNumber, processed: &Processed, fence: &mut dyn Fence) -> Result<(), BotError>;
+    /// forget every present-day rating, mark and achievement and move the approve pointer back to the start
+    /// of the record; all or nothing
+    async fn reset(&self, fence: &mut dyn Fence) -> Result<(), BotError>;
+    /// take players off the leaderboard blacklist once their entry has expired by `now`
+    async fn expire_blacklist(&self, now: DateTime<Utc>) -> Result<(), BotError>;
+    /// overwrite league_info wholesale; only for repairs
+    async fn replace(&self, info: LeagueInfo) -> Result<(), BotError>;
+}
+
+/// the repositories of one league, and the identities it shares with the rest
+#[derive(Clone)]
+pub(crate) struct Store {
+    pub(crate) players: Arc<dyn PlayerRepo>,
+    pub(crate) events: Arc<dyn EventRepo>,
+    pub(crate) league_info: Arc<dyn LeagueInfoRepo>,
+    pub(crate) identities: Arc<dyn IdentityRepo>,
+}
+
+impl Store {
+    /// `directory` is the database holding the identities, the same for every league
+    pub(crate) fn mongo(mongo: &Database, directory: &Database) -> Self {
+        let store = Arc::new(MongoStore::new(mongo, directory));
+        Store { players: store.clone(), events: store.clone(), league_info: store.clone(), identities: store }
+    }
+
+    /// a fresh, empty league that lives only as long as this value
+    #[cfg(test)]
+    pub(crate) fn memory() -> Self {
+        Self::memory_leagues(1).remove(0)
+    }
+
+    /// fresh, empty leagues sharing one set of identities
+    #[cfg(test)]
+    pub(crate) fn memory_leagues(count: usize) -> Vec<Self> {
+        MemoryStore::leagues(count).into_iter()
+            .map(|store| {
+                let store = Arc::new(store);
+                Store { players: store.clone(), events: store.clone(), league_info: store.clone(), identities: store }
+            })
+            .collect()
+    }
+
+    /// append an event that needs no counters besides its own number
+    pub(crate) async fn append_event(
+        &self,
+        build: impl Fn(EventNumber) -> StandingEvent + Send + Sync + 'static,
+    ) -> Result<StandingEvent, BotError> {
+        self.events.append(&[], Box::new(move |reserved| build(reserved.available_event_number))).await
+    }
+
+    /// append an event along with the changes to players it records
+    pub(crate) async fn record(
+        &self,
+        changes: Vec<Change>,
+        build: impl Fn(EventNumber) -> StandingEvent + Send + Sync + 'static,
+        fence: &mut dyn Fence,
+    ) -> Result<StandingEvent, BotError> {
+        self.events.record(changes, Box::new(move |reserved| build(reserved.available_event_number)), fence).await
+    }
+}
+
+// a pointer move that matched nothing lost out to a newer lease holder
+pub(crate) static FENCED_OUT: &str = "approve pointer was moved by a newer ratings lease holder";
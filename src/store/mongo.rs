@@ -0,0 +1,689 @@
+use crate::model::StandingEventInner::GameEnd;
+use crate::model::{Achievement, ApprovalStatus, EventNumber, GameID, Identity, LeagueInfo, Player, PlayerID, PlayerName, RenameRequest, Season, SeasonID, StandingEvent, Suspension, VICTIM_VARIANTS};
+use crate::store::{BuildEvent, BuildRegistration, Change, Counter, EventRepo, Fence, IdentityRepo, LeagueInfoRepo, Outcome, PlayerRepo, Processed, FENCED_OUT};
+use crate::util::events::{with_retries, EventAppend};
+use crate::BotError;
+use async_trait::async_trait;
+use bson::Bson::{Int64, Null};
+use bson::{doc, Bson, Document};
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use mongodb::options::ReturnDocument;
+use mongodb::{ClientSession, Collection, Database};
+use serde::Deserialize;
+use std::collections::HashMap;
+
+/// a league kept in its own mongo database, with its players' identities kept in the shared directory database
+pub(crate) struct MongoStore {
+    mongo: Database,
+    directory: Database,
+}
+
+impl MongoStore {
+    pub(crate) fn new(mongo: &Database, directory: &Database) -> Self {
+        MongoStore { mongo: mongo.clone(), directory: directory.clone() }
+    }
+
+    fn identities(&self) -> Collection<Identity> {
+        self.directory.collection("identities")
+    }
+
+    /// change one identity, failing if it DNE
+    async fn update_identity(&self, session: &mut ClientSession, id: PlayerID, update: Document) -> Result<(), BotError> {
+        let updated = self.identities().update_one(doc! { "_id": id }, update).session(session).await?;
+        if updated.matched_count == 0 {
+            return Err(format!("identity {id} DNE").into());
+        }
+
+        Ok(())
+    }
+
+    /// bring the player documents of every league the identity plays in up to date with it
+    async fn copy_identity(&self, session: &mut ClientSession, id: PlayerID) -> Result<(), BotError> {
+        let identity = self.identities().find_one(doc! { "_id": id }).session(&mut *session).await?
+            .ok_or_else(|| format!("identity {id} DNE"))?;
+        let copied = doc! {
+            "username": &identity.username,
+            "username_lower": &identity.username_lower,
+            "discord_ids": identity.discord_ids.iter().map(|id| *id as i64).collect::<Vec<_>>(),
+        };
+        for league in &identity.leagues {
+            self.mongo.client().database(league).collection::<Player>("players")
+                .update_one(doc! { "_id": id }, doc! { "$set": copied.clone() })
+                .session(&mut *session)
+                .await?;
+        }
+
+        Ok(())
+    }
+
+    fn players(&self) -> Collection<Player> {
+        self.mongo.collection("players")
+    }
+
+    // players with only their names read back
+    fn player_names(&self) -> Collection<PlayerName> {
+        self.mongo.collection("players")
+    }
+
+    fn events(&self) -> Collection<StandingEvent> {
+        self.mongo.collection("events")
+    }
+
+    fn league_info_collection(&self) -> Collection<LeagueInfo> {
+        self.mongo.collection("league_info")
+    }
+
+    /// a fenced, stamped write to league_info; fails if a later lease holder got there first
+    async fn fenced_league_info_update(&self, session: &mut ClientSession, update: Document, token: i64) -> Result<(), BotError> {
+        let moved = self.league_info_collection()
+            .update_one(fenced(doc! {}, token), stamped(update, token))
+            .session(session)
+            .await?;
+        if moved.matched_count == 0 {
+            return Err(FENCED_OUT.into());
+        }
+
+        Ok(())
+    }
+
+    /// make one change inside the transaction appending `event`
+    async fn apply(&self, session: &mut ClientSession, event: &StandingEvent, change: Change) -> Result<(), BotError> {
+        match change {
+            Change::Suspend { player, until, reason } => {
+                self.players().update_one(
+                    doc! { "_id": player },
+                    doc! { "$set": { "suspension": bson::to_bson(&Suspension { until, reason, event: event._id })? } },
+                ).session(session).await?;
+            }
+            Change::Unsuspend { players } => {
+                self.players().update_many(
+                    doc! { "_id": { "$in": players } },
+                    doc! { "$set": { "suspension": null } },
+                ).session(session).await?;
+            }
+            Change::Rename { player, username } => {
+                // a moderator who settled the same request first wins
+                let settled = self.mongo.collection::<RenameRequest>("rename_requests")
+                    .delete_one(doc! { "_id": player, "new_username": &username })
+                    .session(&mut *session)
+                    .await?;
+                if settled.deleted_count == 0 {
+                    return Err(format!("player {player} has no pending rename to {username}").into());
+                }
+
+                self.update_identity(
+                    session,
+                    player,
+                    doc! { "$set": { "username_lower": username.to_lowercase(), "username": username } },
+                ).await?;
+                self.copy_identity(session, player).await?;
+            }
+            Change::LinkDiscord { player, discord_id } => {
+                self.update_identity(session, player, doc! { "$addToSet": { "discord_ids": discord_id as i64 } }).await?;
+                self.copy_identity(session, player).await?;
+            }
+            Change::UnlinkDiscord { player, discord_id } => {
+                self.update_identity(session, player, doc! { "$pull": { "discord_ids": discord_id as i64 } }).await?;
+                self.copy_identity(session, player).await?;
+            }
+            Change::Merge { from, into, suspension } => {
+                let deleted = self.players().delete_one(doc! { "_id": from }).session(&mut *session).await?;
+                if deleted.deleted_count == 0 {
+                    return Err(format!("player {from} DNE").into());
+                }
+                self.repoint_player(session, from, into).await?;
+                self.players().update_one(
+                    doc! { "_id": into },
+                    doc! { "$set": { "suspension": bson::to_bson(&suspension)? } },
+                ).session(&mut *session).await?;
+
+                // `from` keeps its identity while it still plays in another league
+                let league = self.mongo.name();
+                let merged = self.identities()
+                    .find_one_and_update(doc! { "_id": from }, doc! { "$pull": { "leagues": league } })
+                    .return_document(ReturnDocument::After)
+                    .session(&mut *session)
+                    .await?
+                    .ok_or_else(|| format!("identity {from} DNE"))?;
+                if merged.leagues.is_empty() {
+                    // the discord IDs are unique across identities, so they can only move once `from` has let go of them
+                    self.identities().delete_one(doc! { "_id": from }).session(&mut *session).await?;
+                    self.update_identity(
+                        session,
+                        into,
+                        doc! { "$addToSet": { "discord_ids": { "$each": merged.discord_ids.iter().map(|id| *id as i64).collect::<Vec<_>>() } } },
+                    ).await?;
+                    self.copy_identity(session, into).await?;
+                }
+            }
+            Change::ArchiveSeason { season, standings } => {
+                self.mongo.collection::<Season>("seasons").insert_one(Season {
+                    _id: season,
+                    closed_by: event._id,
+                    when: event.when,
+                    standings,
+                }).session(session).await?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// rewrite every reference to one player in the record so it refers to another
+    async fn repoint_player(&self, session: &mut ClientSession, from: PlayerID, into: PlayerID) -> Result<(), BotError> {
+        let events = self.events();
+
+        // both accounts joined the league, but only the earlier join may set a rating on replay
+        let from_join = events.find_one(doc! { "inner.JoinLeague.victims": from }).sort(doc! { "_id": 1 }).session(&mut *session).await?;
+        let into_join = events.find_one(doc! { "inner.JoinLeague.victims": into }).sort(doc! { "_id": 1 }).session(&mut *session).await?;
+        if let (Some(from_join), Some(into_join)) = (from_join, into_join) {
+            let (later_join, later_joiner) = if from_join._id > into_join._id { (from_join._id, from) } else { (into_join._id, into) };
+            if from_join._id != into_join._id {
+                events.update_one(
+                    doc! { "_id": later_join },
+                    doc! { "$pull": { "inner.JoinLeague.victims": later_joiner } },
+                ).session(&mut *session).await?;
+            }
+        }
+
+        for variant in VICTIM_VARIANTS {
+            let path = format!("inner.{variant}.victims");
+            // don't list the same player twice in one event
+            events.update_many(
+                doc! { &path: { "$all": [from, into] } },
+                doc! { "$pull": { &path: from } },
+            ).session(&mut *session).await?;
+            events.update_many(
+                doc! { &path: from },
+                doc! { "$set": { format!("{path}.$[merged]"): into } },
+            ).array_filters(vec![doc! { "merged": from }]).session(&mut *session).await?;
+        }
+
+        events.update_many(
+            doc! { "inner.GameEnd.ranking": from },
+            doc! { "$set": { "inner.GameEnd.ranking.$[merged]": into } },
+        ).array_filters(vec![doc! { "merged": from }]).session(&mut *session).await?;
+
+        events.update_many(
+            doc! { "approval_status.reviewer": from },
+            doc! { "$set": { "approval_status.reviewer": into } },
+        ).session(&mut *session).await?;
+
+        events.update_many(
+            doc! { "approvals": { "$all": [from, into] } },
+            doc! { "$pull": { "approvals": from } },
+        ).session(&mut *session).await?;
+        events.update_many(
+            doc! { "approvals": from },
+            doc! { "$set": { "approvals.$[merged]": into } },
+        ).array_filters(vec![doc! { "merged": from }]).session(&mut *session).await?;
+
+        self.mongo.collection::<Season>("seasons").update_many(
+            doc! { "standings.player": from },
+            doc! { "$set": { "standings.$[merged].player": into } },
+        ).array_filters(vec![doc! { "merged.player": from }]).session(&mut *session).await?;
+
+        // a pending rename for an account that is going away means nothing
+        self.mongo.collection::<RenameRequest>("rename_requests").delete_one(doc! { "_id": from }).session(&mut *session).await?;
+
+        let LeagueInfo { mut leaderboard_blacklist, .. } = self.league_info_collection()
+            .find_one(doc! {}).session(&mut *session).await?
+            .expect("league_info struct missing");
+        if leaderboard_blacklist.iter().any(|entry| entry.player == from || entry.added_by == Some(from)) {
+            for entry in leaderboard_blacklist.iter_mut() {
+                if entry.player == from { entry.player = into }
+                if entry.added_by == Some(from) { entry.added_by = Some(into) }
+            }
+            self.league_info_collection()
+                .update_one(doc! {}, doc! { "$set": { "leaderboard_blacklist": bson::to_bson(&leaderboard_blacklist)? } })
+                .session(&mut *session)
+                .await?;
+        }
+
+        Ok(())
+    }
+}
+
+// the directory's counter, drawn from by every league
+#[derive(Deserialize)]
+struct IdentityInfo {
+    available_player_id: PlayerID,
+}
+
+impl Counter {
+    fn field(&self) -> &'static str {
+        match self {
+            Counter::GameId => "available_game_id",
+        }
+    }
+}
+
+/// add to a filter so it only matches documents no later lease holder has written
+fn fenced(mut filter: Document, token: i64) -> Document {
+    filter.insert("$or", vec![
+        doc! { "fencing_token": { "$lte": token } },
+        doc! { "fencing_token": { "$exists": false } },
+    ]);
+    filter
+}
+
+// regex metacharacters, escaped so a typed name only ever matches itself
+fn escape_regex(text: &str) -> String {
+    text.chars()
+        .flat_map(|c| match "\\^$.|?*+()[]{}".contains(c) {
+            true => vec!['\\', c],
+            false => vec![c],
+        })
+        .collect()
+}
+
+/// add to an update so later writes fenced with an older token are refused
+fn stamped(mut update: Document, token: i64) -> Document {
+    match update.get_document_mut("$max") {
+        Ok(max) => {
+            max.insert("fencing_token", token);
+        }
+        Err(_) => {
+            update.insert("$max", doc! { "fencing_token": token });
+        }
+    }
+    update
+}
+
+#[async_trait]
+impl PlayerRepo for MongoStore {
+    async fn player(&self, id: PlayerID) -> Result<Option<Player>, BotError> {
+        Ok(self.players().find_one(doc! { "_id": id }).await?)
+    }
+
+    async fn players(&self) -> Result<Vec<Player>, BotError> {
+        Ok(self.players().find(doc! {}).await?.try_collect().await?)
+    }
+
+    async fn idle_since(&self, cutoff: DateTime<Utc>) -> Result<Vec<PlayerID>, BotError> {
+        Ok(self.players()
+            .find(doc! { "last_played": { "$lt": bson::DateTime::from_chrono(cutoff) } })
+            .await?
+            .try_filter_map(|p| async move { Ok(Some(p._id)) })
+            .try_collect()
+            .await?)
+    }
+
+    async fn suspensions_ended(&self, now: DateTime<Utc>) -> Result<Vec<PlayerID>, BotError> {
+        Ok(self.players()
+            .find(doc! { "suspension.until": { "$lte": bson::DateTime::from_chrono(now) } })
+            .await?
+            .try_filter_map(|p| async move { Ok(Some(p._id)) })
+            .try_collect()
+            .await?)
+    }
+
+    async fn unlocked_between(&self, kind: Achievement, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<Player>, BotError> {
+        Ok(self.players()
+            .find(doc! { "achievements": { "$elemMatch": {
+                "kind": bson::to_bson(&kind)?,
+                "when": { "$gte": bson::DateTime::from_chrono(since), "$lt": bson::DateTime::from_chrono(until) },
+            } } })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn names(&self) -> Result<Vec<PlayerName>, BotError> {
+        Ok(self.player_names()
+            .find(doc! {})
+            .projection(doc! { "username": 1 })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn names_of(&self, ids: &[PlayerID]) -> Result<Vec<PlayerName>, BotError> {
+        Ok(self.player_names()
+            .find(doc! { "_id": { "$in": ids } })
+            .projection(doc! { "username": 1 })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn names_starting_with(&self, prefix: &str, limit: usize) -> Result<Vec<PlayerName>, BotError> {
+        // anchored on the lowercased name, so it comes off the username index
+        Ok(self.player_names()
+            .find(doc! { "username_lower": { "$regex": format!("^{}", escape_regex(&prefix.to_lowercase())) } })
+            .sort(doc! { "username_lower": 1 })
+            .limit(limit as i64)
+            .projection(doc! { "username": 1 })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn register(&self, identity: Option<PlayerID>, build: BuildRegistration) -> Result<Player, BotError> {
+        let build = &build;
+        with_retries(|deadline| async move {
+            let mut append = EventAppend::begin(&self.mongo, &[], deadline).await?;
+            let league = self.mongo.name();
+            let (player, event) = match identity {
+                Some(id) => {
+                    // joining this league under an identity made in another
+                    let joined = self.identities()
+                        .find_one_and_update(
+                            doc! { "_id": id, "leagues": { "$ne": league } },
+                            doc! { "$push": { "leagues": league } },
+                        )
+                        .return_document(ReturnDocument::After)
+                        .session(append.session())
+                        .await?
+                        .ok_or_else(|| format!("identity {id} DNE or already plays in this league"))?;
+                    let (mut player, event) = build(id, append.event_number());
+                    player.username = joined.username;
+                    player.username_lower = joined.username_lower;
+                    player.discord_ids = joined.discord_ids;
+                    (player, event)
+                }
+                None => {
+                    let drawn = self.directory.collection::<IdentityInfo>("identity_info")
+                        .find_one_and_update(doc! {}, doc! { "$inc": { "available_player_id": 1 } })
+                        .session(append.session())
+                        .await?
+                        .ok_or("identity_info struct missing")?;
+                    let (player, event) = build(drawn.available_player_id, append.event_number());
+                    self.identities().insert_one(Identity {
+                        _id: player._id,
+                        username: player.username.clone(),
+                        username_lower: player.username_lower.clone(),
+                        discord_ids: player.discord_ids.clone(),
+                        leagues: vec![league.to_string()],
+                    }).session(append.session()).await?;
+                    (player, event)
+                }
+            };
+
+            self.players().insert_one(&player).session(append.session()).await?;
+            append.append(event).await?;
+
+            Ok(player)
+        }).await
+    }
+}
+
+#[async_trait]
+impl IdentityRepo for MongoStore {
+    async fn by_discord(&self, discord_id: u64) -> Result<Option<Identity>, BotError> {
+        Ok(self.identities().find_one(doc! { "discord_ids": discord_id as i64 }).await?)
+    }
+
+    async fn by_username(&self, username: &str) -> Result<Option<Identity>, BotError> {
+        Ok(self.identities().find_one(doc! { "username_lower": username.to_lowercase() }).await?)
+    }
+}
+
+#[async_trait]
+impl EventRepo for MongoStore {
+    async fn event(&self, id: EventNumber) -> Result<Option<StandingEvent>, BotError> {
+        Ok(self.events().find_one(doc! { "_id": id }).await?)
+    }
+
+    async fn game(&self, game_id: GameID) -> Result<Option<StandingEvent>, BotError> {
+        Ok(self.events().find_one(doc! { "inner.GameEnd.game_id": game_id }).await?)
+    }
+
+    async fn events_from(&self, first: EventNumber) -> Result<Vec<StandingEvent>, BotError> {
+        Ok(self.events()
+            .find(doc! { "_id": { "$gte": first } })
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn raw_events(&self) -> Result<Vec<Document>, BotError> {
+        Ok(self.mongo.collection::<Document>("events")
+            .find(doc! {})
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect()
+            .await?)
+    }
+
+    async fn append(&self, also: &[Counter], build: BuildEvent) -> Result<StandingEvent, BotError> {
+        let counters = also.iter().map(Counter::field).collect::<Vec<_>>();
+        let (counters, build) = (&counters, &build);
+        with_retries(|deadline| async move {
+            let append = EventAppend::begin(&self.mongo, counters, deadline).await?;
+            let event = build(append.reserved());
+            append.append(event.clone()).await?;
+
+            Ok(event)
+        }).await
+    }
+
+    async fn record(&self, changes: Vec<Change>, build: BuildEvent, fence: &mut dyn Fence) -> Result<StandingEvent, BotError> {
+        fence.check().await?;
+
+        let (changes, build, token) = (&changes, &build, fence.fencing_token());
+        with_retries(|deadline| async move {
+            let mut append = EventAppend::begin(&self.mongo, &[], deadline).await?;
+            // the fence goes before the changes, so a stale lease holder gets nothing written
+            self.fenced_league_info_update(append.session(), doc! {}, token).await?;
+            let event = build(append.reserved());
+            for change in changes.iter().cloned() {
+                self.apply(append.session(), &event, change).await?;
+            }
+            append.append(event.clone()).await?;
+
+            Ok(event)
+        }).await
+    }
+
+    async fn add_approval(&self, id: EventNumber, reviewer: PlayerID) -> Result<Option<StandingEvent>, BotError> {
+        Ok(self.events().find_one_and_update(
+            doc! { "_id": id, "approval_status": Bson::Null },
+            doc! { "$addToSet": { "approvals": reviewer } })
+            .return_document(ReturnDocument::After)
+            .await?)
+    }
+
+    async fn decide(&self, id: EventNumber, status: ApprovalStatus) -> Result<bool, BotError> {
+        let decided = self.events().update_one(
+            doc! { "_id": id, "approval_status": Bson::Null },
+            doc! { "$set": { "approval_status": bson::to_bson(&status)? } },
+        ).await?;
+        Ok(decided.modified_count > 0)
+    }
+
+    async fn approved_game_counts(&self, before: EventNumber) -> Result<HashMap<PlayerID, u64>, BotError> {
+        #[derive(Deserialize)]
+        struct GameCount {
+            _id: PlayerID,
+            games: u64,
+        }
+
+        Ok(self.events().aggregate(vec![
+            doc! { "$match": {
+                "_id": { "$lt": before },
+                "inner.GameEnd": { "$exists": true },
+                "approval_status.approved": true,
+            } },
+            doc! { "$unwind": "$inner.GameEnd.ranking" },
+            doc! { "$group": { "_id": "$inner.GameEnd.ranking", "games": { "$sum": 1 } } },
+        ])
+            .with_type::<GameCount>()
+            .await?
+            .map_ok(|count| (count._id, count.games))
+            .try_collect()
+            .await?)
+    }
+
+    async fn count_unreviewed(&self) -> Result<u64, BotError> {
+        Ok(self.events().count_documents(doc! {
+            "inner.GameEnd": { "$exists": true },
+            "approval_status": Bson::Null,
+        }).await?)
+    }
+
+    async fn unreviewed_games(&self) -> Result<Vec<StandingEvent>, BotError> {
+        Ok(self.events()
+            .find(doc! {
+                "inner.GameEnd": { "$exists": true },
+                "approval_status": Bson::Null,
+            })
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect().await?)
+    }
+
+    async fn approved_between(&self, since: DateTime<Utc>, until: DateTime<Utc>) -> Result<Vec<StandingEvent>, BotError> {
+        Ok(self.events()
+            .find(doc! {
+                "approval_status.approved": true,
+                "when": { "$gte": bson::DateTime::from_chrono(since), "$lt": bson::DateTime::from_chrono(until) },
+            })
+            .sort(doc! { "_id": 1 })
+            .await?
+            .try_collect().await?)
+    }
+
+    async fn pop(&self, id: EventNumber, fence: &mut dyn Fence) -> Result<Option<StandingEvent>, BotError> {
+        fence.check().await?;
+
+        // the event and its number go back together
+        let mut session = self.mongo.client().start_session().await?;
+        session.start_transaction().await?;
+
+        let LeagueInfo { available_event_number, .. } = self.league_info_collection()
+            .find_one(doc! {})
+            .session(&mut session)
+            .await?
+            .expect("league_info struct missing");
+        if available_event_number != id + 1 {
+            return Ok(None);
+        }
+
+        let Some(evt) = self.events()
+            .find_one_and_delete(doc! { "_id": id })
+            .session(&mut session)
+            .await? else { return Ok(None) };
+
+        let update_doc = if let GameEnd(_) = evt.inner {
+            doc! {
+                "$inc": {"available_event_number": -1, "available_game_id": -1},
+                "$min": { "first_unreviewed_event_number": evt._id }
+            }
+        } else {
+            doc! {
+                "$inc": { "available_event_number": -1 },
+                "$min": { "first_unreviewed_event_number": evt._id }
+            }
+        };
+
+        let moved = self.league_info_collection()
+            .update_one(fenced(doc! {}, fence.fencing_token()), stamped(update_doc, fence.fencing_token()))
+            .session(&mut session)
+            .await?;
+        if moved.matched_count == 0 {
+            return Err(FENCED_OUT.into());
+        }
+        session.commit_transaction().await?;
+
+        Ok(Some(evt))
+    }
+}
+
+#[async_trait]
+impl LeagueInfoRepo for MongoStore {
+    async fn league_info(&self) -> Result<LeagueInfo, BotError> {
+        Ok(self.league_info_collection().find_one(doc! {}).await?.ok_or("league_info DNE")?)
+    }
+
+    async fn current_season(&self) -> Result<SeasonID, BotError> {
+        Ok(self.mongo.collection::<Season>("seasons").count_documents(doc! {}).await? as SeasonID + 1)
+    }
+
+    async fn advance_pointer(&self, to: EventNumber, processed: &Processed, fence: &mut dyn Fence) -> Result<(), BotError> {
+        let mut session = self.mongo.client().start_session().await?;
+        session.start_transaction().await?;
+
+        // the fence goes first, so a stale lease holder gets nothing written
+        self.fenced_league_info_update(&mut session, doc! {
+            "$max": {
+                "first_unreviewed_event_number": to as i64,
+                "achievements_announced_before": to as i64,
+            },
+        }, fence.fencing_token()).await?;
+
+        for (id, rating) in &processed.ratings {
+            self.players().update_one(
+                doc! { "_id": id },
+                doc! { "$set": { "rating": rating.rating, "deviation": rating.uncertainty } },
+            ).session(&mut session).await?;
+        }
+        if let Some((ids, when)) = &processed.played {
+            self.players().update_many(
+                doc! { "_id": { "$in": ids } },
+                doc! { "$max": { "last_played": bson::DateTime::from_chrono(*when) } },
+            ).session(&mut session).await?;
+        }
+        if let Some(Outcome { event, expected, deltas }) = &processed.outcome {
+            self.events().update_one(
+                doc! { "_id": event },
+                doc! { "$set": {
+                    "inner.GameEnd.expected": expected,
+                    "inner.GameEnd.deltas": deltas,
+                } },
+            ).session(&mut session).await?;
+        }
+        for (id, mark) in &processed.peaks {
+            self.players().update_one(doc! { "_id": id }, doc! { "$set": { "peak": bson::to_bson(mark)? } })
+                .session(&mut session).await?;
+        }
+        for (id, mark) in &processed.troughs {
+            self.players().update_one(doc! { "_id": id }, doc! { "$set": { "trough": bson::to_bson(mark)? } })
+                .session(&mut session).await?;
+        }
+        for (id, unlock) in &processed.unlocks {
+            self.players().update_one(
+                doc! { "_id": id, "achievements.kind": { "$ne": bson::to_bson(&unlock.kind)? } },
+                doc! { "$push": { "achievements": bson::to_bson(unlock)? } },
+            ).session(&mut session).await?;
+        }
+
+        session.commit_transaction().await?;
+        Ok(())
+    }
+
+    async fn reset(&self, fence: &mut dyn Fence) -> Result<(), BotError> {
+        let mut session = self.mongo.client().start_session().await?;
+        session.start_transaction().await?;
+
+        self.fenced_league_info_update(&mut session, doc! { "$set": { "first_unreviewed_event_number": Int64(0) } }, fence.fencing_token()).await?;
+        self.players().update_many(doc! {}, doc! {"$set": {
+            "rating": 0,
+            "deviation": 0,
+            "last_played": Null,
+            "peak": Null,
+            "trough": Null,
+            "achievements": [],
+        }}).session(&mut session).await?;
+
+        session.commit_transaction().await?;
+        Ok(())
+    }
+
+    async fn expire_blacklist(&self, now: DateTime<Utc>) -> Result<(), BotError> {
+        self.league_info_collection().update_one(
+            doc! {},
+            doc! { "$pull": { "leaderboard_blacklist": {
+                "expires": { "$lte": bson::DateTime::from_chrono(now) }
+            } } },
+        ).await?;
+        Ok(())
+    }
+
+    async fn replace(&self, info: LeagueInfo) -> Result<(), BotError> {
+        self.league_info_collection().find_one_and_replace(doc! {}, info).await?;
+        Ok(())
+    }
+}
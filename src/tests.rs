@@ -0,0 +1,468 @@
+//! end-to-end flows against the in-memory store: no discord, no database server
+
+use crate::commands::ewar::game::record_game;
+use crate::commands::ewar::moderation::{cast_review, ReviewOutcome};
+use crate::commands::ewar::season::{close_season_inner, SeasonClose};
+use crate::commands::ewar::user::registration;
+use crate::commands::maint::{check_event_log, FsckReport};
+use crate::inactivity_decay_inner;
+use crate::model::StandingEventInner::{GameEnd, LinkDiscord, MergePlayers, Penalty, Rename};
+use crate::model::{Achievement, ApprovalStatus, EventNumber, GameID, Player, PlayerID, PlayerName, StandingEvent};
+use crate::store::memory::SoleWriter;
+use crate::store::{Change, Fence, Processed, Store};
+use crate::util::autocomplete::search_players;
+use crate::util::constants::DEFAULT_RATING;
+use crate::util::rating::{advance_approve_pointer, reset_standings, Advanced};
+use crate::util::review::{ReviewConflict, ReviewPolicy};
+use crate::BotError;
+use async_trait::async_trait;
+use chrono::{TimeDelta, Utc};
+use std::num::NonZeroUsize;
+
+// a lease holder with a particular fencing token
+struct Token(i64);
+
+#[async_trait]
+impl Fence for Token {
+    async fn check(&mut self) -> Result<(), BotError> {
+        Ok(())
+    }
+
+    fn fencing_token(&self) -> i64 {
+        self.0
+    }
+}
+
+// a lease holder whose lease passes to a newer holder just after a given check succeeds
+struct LosesLease {
+    store: Store,
+    token: i64,
+    checks_until_lost: u32,
+    // everyone as they stood when the lease was lost
+    lost: Option<Vec<Player>>,
+}
+
+#[async_trait]
+impl Fence for LosesLease {
+    async fn check(&mut self) -> Result<(), BotError> {
+        if self.checks_until_lost > 0 {
+            self.checks_until_lost -= 1;
+        } else if self.lost.is_none() {
+            let pointer = self.store.league_info.league_info().await?.first_unreviewed_event_number;
+            self.store.league_info.advance_pointer(pointer, &Processed::default(), &mut Token(self.token + 1)).await?;
+            self.lost = Some(self.store.players.players().await?);
+        }
+        Ok(())
+    }
+
+    fn fencing_token(&self) -> i64 {
+        self.token
+    }
+}
+
+async fn register(store: &Store, name: &str) -> Player {
+    store.players.register(None, registration(None, String::from(name))).await.expect("registration failed")
+}
+
+async fn advance(store: &Store) -> Advanced {
+    advance_approve_pointer(store, &mut SoleWriter, None).await.expect("advance failed")
+}
+
+async fn player(store: &Store, id: PlayerID) -> Player {
+    store.players.player(id).await.unwrap().expect("player DNE")
+}
+
+/// bind another discord account to a player, as a moderator would
+async fn link(store: &Store, player: PlayerID, discord_id: u64) {
+    store.record(vec![Change::LinkDiscord { player, discord_id }], move |event_number| StandingEvent {
+        _id: event_number,
+        approval_status: Some(ApprovalStatus { approved: true, reviewer: None }),
+        approvals: vec![],
+        inner: LinkDiscord { victims: vec![player], discord_id },
+        when: Utc::now(),
+    }, &mut SoleWriter).await.expect("link failed");
+}
+
+/// a game posted by a player, waiting on review
+async fn post_game(store: &Store, ranking: &[PlayerID]) -> (GameID, EventNumber) {
+    record_game(store, ranking.to_vec(), 600, Utc::now(), None, vec![]).await.unwrap()
+}
+
+/// a game that went through review and into the record
+async fn play_game(store: &Store, moderator: PlayerID, ranking: &[PlayerID]) -> EventNumber {
+    let (_, event_number) = post_game(store, ranking).await;
+    match cast_review(store, &ReviewPolicy::default(), event_number, moderator, true).await.unwrap() {
+        ReviewOutcome::Decided(decided) => assert_eq!(decided, event_number),
+        _ => panic!("a single approval should meet the default quorum"),
+    }
+    advance(store).await;
+    event_number
+}
+
+#[tokio::test]
+async fn registration_joins_at_default_rating() {
+    let store = Store::memory();
+    let alice = register(&store, "alice").await;
+    let bob = register(&store, "bob").await;
+    assert_eq!((alice._id, bob._id), (1, 2));
+
+    let league_info = store.league_info.league_info().await.unwrap();
+    assert_eq!(league_info.available_event_number, 2);
+
+    // joins are approved on the spot
+    assert_eq!(advance(&store).await.pointer, 2);
+    let alice = player(&store, alice._id).await;
+    assert_eq!(alice.rating_struct().rating, DEFAULT_RATING.rating);
+    assert_eq!(alice.rating_struct().uncertainty, DEFAULT_RATING.uncertainty);
+}
+
+#[tokio::test]
+async fn taken_username_draws_nothing() {
+    let store = Store::memory();
+    register(&store, "alice").await;
+    assert!(store.players.register(None, registration(None, String::from("alice"))).await.is_err());
+
+    let league_info = store.league_info.league_info().await.unwrap();
+    assert_eq!(league_info.available_event_number, 1);
+    assert_eq!(register(&store, "bob").await._id, 2);
+}
+
+#[tokio::test]
+async fn unreviewed_game_holds_the_pointer() {
+    let store = Store::memory();
+    let moderator = register(&store, "moderator").await._id;
+    let winner = register(&store, "winner").await._id;
+    let loser = register(&store, "loser").await._id;
+    advance(&store).await;
+
+    let (game_id, event_number) = post_game(&store, &[winner, loser]).await;
+    assert_eq!((game_id, event_number), (0, 3));
+    assert_eq!(advance(&store).await.pointer, 3);
+    assert_eq!(player(&store, winner).await.rating, DEFAULT_RATING.rating);
+    assert_eq!(store.events.count_unreviewed().await.unwrap(), 1);
+
+    match cast_review(&store, &ReviewPolicy::default(), event_number, moderator, true).await.unwrap() {
+        ReviewOutcome::Decided(decided) => assert_eq!(decided, event_number),
+        _ => panic!("a single approval should meet the default quorum"),
+    }
+    assert_eq!(store.events.count_unreviewed().await.unwrap(), 0);
+    let Advanced { pointer, unlocks, ratings_changed } = advance(&store).await;
+    assert_eq!(pointer, 4);
+    assert!(ratings_changed);
+    assert!(unlocks.iter().any(|(id, unlock)| *id == winner && unlock.kind == Achievement::FirstWin));
+
+    let (winner, loser) = (player(&store, winner).await, player(&store, loser).await);
+    assert!(winner.rating > loser.rating);
+    assert!(winner.last_played.is_some());
+    assert_eq!(winner.peak.expect("winner has no peak").event, event_number);
+
+    let StandingEvent { inner: GameEnd(game), .. } = store.events.game(game_id).await.unwrap().unwrap() else {
+        panic!("game is not a game");
+    };
+    assert_eq!(game.expected.len(), 2);
+    assert!(game.deltas[0] > 0.0 && game.deltas[1] < 0.0);
+}
+
+#[tokio::test]
+async fn quorum_needs_distinct_moderators() {
+    let store = Store::memory();
+    let first = register(&store, "first").await._id;
+    let second = register(&store, "second").await._id;
+    let a = register(&store, "a").await._id;
+    let b = register(&store, "b").await._id;
+    let policy = ReviewPolicy { approval_quorum: NonZeroUsize::new(2).unwrap(), ..ReviewPolicy::default() };
+
+    let (game_id, event_number) = post_game(&store, &[a, b]).await;
+    match cast_review(&store, &policy, event_number, first, true).await.unwrap() {
+        ReviewOutcome::Waiting(approvals) => assert_eq!(approvals, vec![first]),
+        _ => panic!("one approval shouldn't meet a quorum of two"),
+    }
+
+    let event = store.events.game(game_id).await.unwrap().unwrap();
+    let StandingEvent { inner: GameEnd(ref game), .. } = event else { panic!("game is not a game") };
+    assert!(matches!(policy.check_review(first, &event, game, true), Err(ReviewConflict::AlreadyApproved { have: 1, need: 2 })));
+    assert!(matches!(policy.check_review(a, &event, game, true), Err(ReviewConflict::SelfReview { .. })));
+    assert!(policy.check_review(second, &event, game, true).is_ok());
+
+    assert!(matches!(cast_review(&store, &policy, event_number, second, true).await.unwrap(), ReviewOutcome::Decided(_)));
+    // someone racing to review it too gets told it's settled
+    assert!(matches!(cast_review(&store, &policy, event_number, second, false).await.unwrap(), ReviewOutcome::AlreadyDecided));
+}
+
+#[tokio::test]
+async fn rejected_game_leaves_ratings_alone() {
+    let store = Store::memory();
+    let moderator = register(&store, "moderator").await._id;
+    let a = register(&store, "a").await._id;
+    let b = register(&store, "b").await._id;
+    advance(&store).await;
+
+    let (_, event_number) = post_game(&store, &[a, b]).await;
+    assert!(matches!(cast_review(&store, &ReviewPolicy::default(), event_number, moderator, false).await.unwrap(),
+                     ReviewOutcome::Decided(_)));
+    let Advanced { pointer, ratings_changed, .. } = advance(&store).await;
+
+    // rejected games still move the pointer, they just don't count
+    assert_eq!(pointer, event_number + 1);
+    assert!(!ratings_changed);
+    assert_eq!(player(&store, a).await.rating, DEFAULT_RATING.rating);
+    assert!(player(&store, a).await.last_played.is_none());
+}
+
+#[tokio::test]
+async fn penalty_takes_true_rating() {
+    let store = Store::memory();
+    let moderator = register(&store, "moderator").await._id;
+    let victim = register(&store, "victim").await._id;
+    advance(&store).await;
+
+    store.append_event(move |event_number| StandingEvent {
+        _id: event_number,
+        approval_status: Some(ApprovalStatus { approved: true, reviewer: Some(moderator) }),
+        approvals: vec![],
+        inner: Penalty { victims: vec![victim], delta_rating: -3.0, reason: String::from("test") },
+        when: Utc::now(),
+    }).await.unwrap();
+    advance(&store).await;
+
+    let victim = player(&store, victim).await;
+    assert_eq!(victim.rating, DEFAULT_RATING.rating - 3.0);
+    assert_eq!(victim.deviation, DEFAULT_RATING.uncertainty);
+}
+
+#[tokio::test]
+async fn decay_only_touches_idle_players() {
+    let store = Store::memory();
+    let moderator = register(&store, "moderator").await._id;
+    let a = register(&store, "a").await._id;
+    let b = register(&store, "b").await._id;
+    let (_, game) = record_game(&store, vec![a, b], 600, Utc::now() - TimeDelta::days(8),
+                                Some(ApprovalStatus { approved: true, reviewer: Some(moderator) }), vec![moderator]).await.unwrap();
+    advance(&store).await;
+    let before = player(&store, a).await.deviation;
+
+    inactivity_decay_inner(&store).await.unwrap();
+    let Advanced { pointer, .. } = advance(&store).await;
+    assert_eq!(pointer, game + 2);
+
+    // never capped below where a new player starts
+    let expected = (before + 0.1).min(DEFAULT_RATING.uncertainty);
+    assert!((player(&store, a).await.deviation - expected).abs() < 1e-9);
+    // the moderator never played, so there's nothing to decay from
+    assert_eq!(player(&store, moderator).await.deviation, DEFAULT_RATING.uncertainty);
+}
+
+#[tokio::test]
+async fn fsck_finds_and_repairs_a_bad_pointer() {
+    let store = Store::memory();
+    let moderator = register(&store, "moderator").await._id;
+    let a = register(&store, "a").await._id;
+    let b = register(&store, "b").await._id;
+    play_game(&store, moderator, &[a, b]).await;
+    post_game(&store, &[b, a]).await;
+
+    let FsckReport { problems, .. } = check_event_log(&store).await.unwrap();
+    assert!(problems.is_empty(), "{problems:?}");
+
+    let mut broken = store.league_info.league_info().await.unwrap();
+    broken.first_unreviewed_event_number = 1;
+    store.league_info.replace(broken).await.unwrap();
+
+    let FsckReport { problems, repaired } = check_event_log(&store).await.unwrap();
+    assert_eq!(problems.len(), 1);
+    assert_eq!(repaired.first_unreviewed_event_number, 4);
+    store.league_info.replace(repaired).await.unwrap();
+    assert!(check_event_log(&store).await.unwrap().problems.is_empty());
+}
+
+#[tokio::test]
+async fn reprocess_rebuilds_the_same_standings() {
+    let store = Store::memory();
+    let moderator = register(&store, "moderator").await._id;
+    let a = register(&store, "a").await._id;
+    let b = register(&store, "b").await._id;
+    let c = register(&store, "c").await._id;
+    play_game(&store, moderator, &[a, b, c]).await;
+    play_game(&store, moderator, &[c, a]).await;
+    let before = store.players.players().await.unwrap();
+
+    reset_standings(&store, &mut SoleWriter).await.unwrap();
+    assert_eq!(player(&store, a).await.rating, 0.0);
+    let Advanced { pointer, unlocks, .. } = advance(&store).await;
+
+    assert_eq!(pointer, store.league_info.league_info().await.unwrap().available_event_number);
+    // everything was earned over again, but it was all announced the first time
+    assert!(unlocks.is_empty());
+    for (old, new) in before.iter().zip(store.players.players().await.unwrap()) {
+        assert_eq!((old.rating, old.deviation), (new.rating, new.deviation));
+        assert_eq!(old.achievements.len(), new.achievements.len());
+    }
+}
+
+#[tokio::test]
+async fn stale_lease_holder_is_fenced_out() {
+    let store = Store::memory();
+    register(&store, "a").await;
+
+    advance_approve_pointer(&store, &mut Token(2), None).await.unwrap();
+    let stale = reset_standings(&store, &mut Token(1)).await;
+    assert!(stale.is_err());
+    assert_eq!(store.league_info.league_info().await.unwrap().first_unreviewed_event_number, 1);
+
+    // the lease is lost after the joins are processed, just as the game is about to be
+    let moderator = player(&store, 1).await._id;
+    let b = register(&store, "b").await._id;
+    let c = register(&store, "c").await._id;
+    let (game_id, event_number) = post_game(&store, &[b, c]).await;
+    cast_review(&store, &ReviewPolicy::default(), event_number, moderator, true).await.unwrap();
+
+    let mut fence = LosesLease { store: store.clone(), token: 3, checks_until_lost: 2, lost: None };
+    assert!(advance_approve_pointer(&store, &mut fence, None).await.is_err());
+    assert_eq!(store.league_info.league_info().await.unwrap().first_unreviewed_event_number, event_number);
+
+    let lost = fence.lost.expect("lease was never lost");
+    for (then, now) in lost.iter().zip(store.players.players().await.unwrap()) {
+        assert_eq!((then.rating, then.deviation, then.last_played), (now.rating, now.deviation, now.last_played));
+        assert!(now.peak.is_none_or(|peak| peak.event < event_number));
+        assert!(now.achievements.is_empty());
+    }
+    let StandingEvent { inner: GameEnd(game), .. } = store.events.game(game_id).await.unwrap().unwrap() else {
+        panic!("game {game_id} isn't a game");
+    };
+    assert!(game.deltas.is_empty());
+
+    // nor can it write to players through an event
+    let linked = store.record(vec![Change::LinkDiscord { player: b, discord_id: 100 }], move |event_number| StandingEvent {
+        _id: event_number,
+        approval_status: Some(ApprovalStatus { approved: true, reviewer: Some(moderator) }),
+        approvals: vec![],
+        inner: LinkDiscord { victims: vec![b], discord_id: 100 },
+        when: Utc::now(),
+    }, &mut Token(3)).await;
+    assert!(linked.is_err());
+    assert!(player(&store, b).await.discord_ids.is_empty());
+}
+
+#[tokio::test]
+async fn popping_a_game_gives_its_numbers_back() {
+    let store = Store::memory();
+    let a = register(&store, "a").await._id;
+    let b = register(&store, "b").await._id;
+    advance(&store).await;
+    let (_, event_number) = post_game(&store, &[a, b]).await;
+
+    // only the latest event can go
+    assert!(store.events.pop(event_number - 1, &mut SoleWriter).await.unwrap().is_none());
+    let popped = store.events.pop(event_number, &mut SoleWriter).await.unwrap().expect("nothing popped");
+    assert_eq!(popped._id, event_number);
+
+    let league_info = store.league_info.league_info().await.unwrap();
+    assert_eq!((league_info.available_event_number, league_info.available_game_id), (event_number, 0));
+    assert!(check_event_log(&store).await.unwrap().problems.is_empty());
+}
+
+#[tokio::test]
+async fn merge_carries_discord_accounts_over() {
+    let store = Store::memory();
+    let moderator = register(&store, "moderator").await._id;
+    let kept = register(&store, "kept").await._id;
+    let duplicate = register(&store, "duplicate").await._id;
+    link(&store, kept, 100).await;
+    link(&store, duplicate, 200).await;
+    play_game(&store, moderator, &[duplicate, moderator]).await;
+
+    // the duplicate has to be gone before its discord account can move, or the binding would be held twice
+    store.record(vec![Change::Merge { from: duplicate, into: kept, suspension: None }], move |event_number| StandingEvent {
+        _id: event_number,
+        approval_status: Some(ApprovalStatus { approved: true, reviewer: Some(moderator) }),
+        approvals: vec![],
+        inner: MergePlayers { victims: vec![kept], merged: duplicate, merged_username: String::from("duplicate") },
+        when: Utc::now(),
+    }, &mut SoleWriter).await.expect("merge failed");
+
+    assert!(store.players.player(duplicate).await.unwrap().is_none());
+    let mut discord_ids = player(&store, kept).await.discord_ids;
+    discord_ids.sort();
+    assert_eq!(discord_ids, [100, 200]);
+
+    // the duplicate's game replays as the kept player's
+    reset_standings(&store, &mut SoleWriter).await.unwrap();
+    advance(&store).await;
+    assert!(player(&store, kept).await.last_played.is_some());
+    assert!(check_event_log(&store).await.unwrap().problems.is_empty());
+}
+
+#[tokio::test]
+async fn one_identity_plays_in_every_league() {
+    let mut leagues = Store::memory_leagues(2);
+    let (main, side) = (leagues.remove(0), leagues.remove(0));
+    let alice = register(&main, "alice").await._id;
+    link(&main, alice, 100).await;
+
+    // joining keeps the ID, name and discord accounts from the other league, and draws no new ID
+    let joined = side.players.register(Some(alice), registration(None, String::from("ignored"))).await.unwrap();
+    assert_eq!((joined._id, joined.username.as_str(), joined.discord_ids.as_slice()), (alice, "alice", &[100][..]));
+    assert!(side.players.register(Some(alice), registration(None, String::from("alice"))).await.is_err());
+    assert!(side.players.register(None, registration(None, String::from("alice"))).await.is_err());
+    let bob = register(&side, "bob").await._id;
+    assert_ne!(bob, alice);
+    assert_eq!(side.identities.by_discord(100).await.unwrap().map(|identity| identity.leagues.len()), Some(2));
+
+    // renaming in one league renames everywhere
+    side.record(vec![Change::Rename { player: alice, username: String::from("alicia") }], move |event_number| StandingEvent {
+        _id: event_number,
+        approval_status: Some(ApprovalStatus { approved: true, reviewer: None }),
+        approvals: vec![],
+        inner: Rename { victims: vec![alice], old_username: String::from("alice"), new_username: String::from("alicia") },
+        when: Utc::now(),
+    }, &mut SoleWriter).await.expect("rename failed");
+    assert_eq!(player(&main, alice).await.username, "alicia");
+    assert!(main.players.register(None, registration(None, String::from("alicia"))).await.is_err());
+
+    // merging away alice's side account leaves her main one and its discord account alone
+    side.record(vec![Change::Merge { from: alice, into: bob, suspension: None }], move |event_number| StandingEvent {
+        _id: event_number,
+        approval_status: Some(ApprovalStatus { approved: true, reviewer: None }),
+        approvals: vec![],
+        inner: MergePlayers { victims: vec![bob], merged: alice, merged_username: String::from("alicia") },
+        when: Utc::now(),
+    }, &mut SoleWriter).await.expect("merge failed");
+    assert!(side.players.player(alice).await.unwrap().is_none());
+    assert!(player(&side, bob).await.discord_ids.is_empty());
+    assert_eq!(player(&main, alice).await.discord_ids, [100]);
+}
+
+#[tokio::test]
+async fn season_closes_only_once_every_game_is_in() {
+    let store = Store::memory();
+    let moderator = register(&store, "moderator").await._id;
+    let a = register(&store, "a").await._id;
+    let b = register(&store, "b").await._id;
+    play_game(&store, moderator, &[a, b]).await;
+    let (_, waiting) = post_game(&store, &[b, a]).await;
+
+    let closed = close_season_inner(&store, &mut SoleWriter, moderator, 0.5, 1.0).await.unwrap();
+    assert!(matches!(closed, SeasonClose::Unreviewed(event_number) if event_number == waiting));
+    assert_eq!(store.league_info.current_season().await.unwrap(), 1);
+
+    cast_review(&store, &ReviewPolicy::default(), waiting, moderator, true).await.unwrap();
+    advance(&store).await;
+    let SeasonClose::Closed { season, .. } = close_season_inner(&store, &mut SoleWriter, moderator, 0.5, 1.0).await.unwrap() else {
+        panic!("season didn't close with nothing left to review");
+    };
+    assert_eq!(season, 1);
+    assert_eq!(store.league_info.current_season().await.unwrap(), 2);
+}
+
+#[tokio::test]
+async fn player_search_prefers_ids_then_prefixes_then_typos() {
+    let store = Store::memory();
+    for name in ["alice", "alicia", "bob", "robert"] {
+        register(&store, name).await;
+    }
+
+    let names = |found: Vec<PlayerName>| found.into_iter().map(|player| player.username).collect::<Vec<_>>();
+    assert_eq!(names(search_players(&store, "ALI", 25).await.unwrap()), ["alice", "alicia"]);
+    assert_eq!(names(search_players(&store, "3", 25).await.unwrap())[0], "bob");
+    assert_eq!(names(search_players(&store, "robrt", 25).await.unwrap()), ["robert"]);
+}
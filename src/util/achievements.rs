@@ -0,0 +1,125 @@
+use crate::model::StandingEventInner::GameEnd;
+use crate::model::{Achievement, PlayerID, StandingEvent, Unlock};
+use crate::store::{Processed, Store};
+use crate::util::rating::{RatingExtra, Standings};
+use crate::BotError;
+use itertools::Itertools;
+use serenity::all::{ChannelId, Http};
+use skillratings::trueskill::TrueSkillRating;
+use std::collections::HashMap;
+
+static BIG_TABLE_SIZE: usize = 8;
+static MANY_GAMES: u64 = 50;
+
+impl Achievement {
+    pub(crate) fn name(&self) -> &'static str {
+        match self {
+            Achievement::FirstWin => "first win",
+            Achievement::GiantSlayer => "giant slayer",
+            Achievement::BigTableWin => "king of the table",
+            Achievement::FiftyGames => "regular",
+            Achievement::Established => "established",
+        }
+    }
+
+    pub(crate) fn description(&self) -> &'static str {
+        match self {
+            Achievement::FirstWin => "won a game",
+            Achievement::GiantSlayer => "finished above a top 3 player",
+            Achievement::BigTableWin => "won a game of 8 or more",
+            Achievement::FiftyGames => "played 50 games",
+            Achievement::Established => "left provisional status",
+        }
+    }
+}
+
+impl Unlock {
+    pub(crate) fn describe(&self) -> String {
+        format!("**{}** ({}) on <t:{}:d> (event {})", self.kind.name(), self.kind.description(), self.when.timestamp(), self.event)
+    }
+}
+
+/// the parts of the standings achievements are judged against, taken just before an event is processed
+pub(crate) struct BeforeEvent {
+    ratings: HashMap<PlayerID, TrueSkillRating>,
+    top_three: Vec<PlayerID>,
+}
+
+impl BeforeEvent {
+    pub(crate) fn snapshot(event: &StandingEvent, standings: &mut Standings) -> Self {
+        let ratings = event.inner.rated_players().iter()
+            .filter_map(|player_id| standings.player(*player_id).map(|player| (*player_id, player.rating_struct())))
+            .collect();
+
+        // only games can award anything relative to the leaderboard
+        let top_three = match event.inner {
+            GameEnd(_) => standings.top_three().to_vec(),
+            _ => vec![],
+        };
+
+        Self { ratings, top_three }
+    }
+}
+
+impl StandingEvent {
+    /// award what this event earned, given what processing it did; only achievements the player didn't have yet are added
+    pub(crate) fn award_achievements(&self, standings: &Standings, before: &BeforeEvent, processed: &mut Processed) -> Result<(), BotError> {
+        let mut earned = Vec::new();
+
+        if let GameEnd(game) = &self.inner {
+            if let Some(winner) = game.ranking.first() {
+                earned.push((*winner, Achievement::FirstWin));
+                if game.ranking.len() >= BIG_TABLE_SIZE {
+                    earned.push((*winner, Achievement::BigTableWin));
+                }
+            }
+
+            for (place, player_id) in game.ranking.iter().enumerate() {
+                if game.ranking[place + 1..].iter().any(|beaten| before.top_three.contains(beaten)) {
+                    earned.push((*player_id, Achievement::GiantSlayer));
+                }
+
+                // counting this one
+                if standings.games_played(*player_id) + 1 >= MANY_GAMES {
+                    earned.push((*player_id, Achievement::FiftyGames));
+                }
+            }
+        }
+
+        for (player_id, new_rating) in processed.ratings.iter() {
+            let Some(old_rating) = before.ratings.get(player_id) else { continue };
+            if old_rating.is_provisional() && !new_rating.is_provisional() {
+                earned.push((*player_id, Achievement::Established));
+            }
+        }
+
+        for (player_id, kind) in earned {
+            let player = standings.player(player_id)
+                .ok_or_else(|| format!("player {player_id} earning an achievement at event {} DNE", self._id))?;
+            let had = player.achievements.iter().map(|had| &had.kind)
+                .chain(processed.unlocks.iter().filter(|(id, _)| *id == player_id).map(|(_, unlock)| &unlock.kind))
+                .contains(&kind);
+            if !had {
+                processed.unlocks.push((player_id, Unlock { kind, event: self._id, when: self.when }));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// post newly unlocked achievements to the announcement channel, if there is one
+pub(crate) async fn announce_unlocks(http: &Http, store: &Store, announce_channel: Option<ChannelId>, unlocks: &[(PlayerID, Unlock)]) -> Result<(), BotError> {
+    let Some(channel) = announce_channel else { return Ok(()) };
+
+    for (player_id, unlock) in unlocks {
+        let player = store.players.player(*player_id).await?
+            .ok_or_else(|| format!("unlocking player {player_id} DNE"))?;
+        channel.say(http, format!(
+            ":trophy: {} unlocked **{}** ({}) in event {}",
+            player.short_summary(), unlock.kind.name(), unlock.kind.description(), unlock.event,
+        )).await?;
+    }
+
+    Ok(())
+}
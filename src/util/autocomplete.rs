@@ -0,0 +1,136 @@
+use crate::model::StandingEventInner::GameEnd;
+use crate::model::{PlayerID, PlayerName};
+use crate::store::Store;
+use crate::util::league::league;
+use crate::{BotError, Context};
+use itertools::Itertools;
+use serenity::all::AutocompleteChoice;
+use std::collections::HashMap;
+
+// discord shows at most this many suggestions
+static MAX_CHOICES: usize = 25;
+
+// fewer prefix matches than this and typos are probably to blame, so look further
+static MIN_PREFIX_MATCHES: usize = 5;
+
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect_vec();
+    let mut row = (0..=b.len()).collect_vec();
+
+    for (i, a_char) in a.chars().enumerate() {
+        let mut diagonal = row[0];
+        row[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let above = row[j + 1];
+            row[j + 1] = (above + 1)
+                .min(row[j] + 1)
+                .min(diagonal + if a_char == *b_char { 0 } else { 1 });
+            diagonal = above;
+        }
+    }
+
+    row[b.len()]
+}
+
+/// lower is a better match; none is no match at all
+fn match_score(username: &str, partial: &str) -> Option<usize> {
+    let username = username.to_lowercase();
+    let partial = partial.trim().to_lowercase();
+
+    if username.starts_with(&partial) {
+        Some(0)
+    } else if username.contains(&partial) {
+        Some(1)
+    } else {
+        // compare against the part of the name that has been typed so far
+        let typed_so_far = username.chars().take(partial.chars().count()).collect::<String>();
+        let distance = edit_distance(&typed_so_far, &partial).min(edit_distance(&username, &partial));
+        (distance <= (partial.chars().count() / 3).max(1)).then_some(2 + distance)
+    }
+}
+
+/// players whose usernames look like what was typed, best first. usernames starting with it
+/// come off the index; the whole collection is only searched fuzzily when few do
+pub(crate) async fn search_players(store: &Store, partial: &str, limit: usize) -> Result<Vec<PlayerName>, BotError> {
+    let partial = partial.trim();
+
+    let mut found = store.players.names_starting_with(partial, limit).await?;
+
+    // an ID typed outright goes first
+    if let Ok(id) = partial.parse::<PlayerID>() {
+        if let Some(player) = store.players.names_of(&[id]).await?.pop() {
+            found.retain(|other| other._id != player._id);
+            found.insert(0, player);
+        }
+    }
+
+    if found.len() >= MIN_PREFIX_MATCHES.min(limit) {
+        found.truncate(limit);
+        return Ok(found);
+    }
+
+    let everyone = store.players.names().await?;
+
+    Ok(everyone.into_iter()
+        .filter_map(|player| (player._id.to_string() == partial).then_some(0)
+            .or_else(|| match_score(&player.username, partial))
+            .map(|score| (score, player)))
+        .sorted_by(|(score_a, player_a), (score_b, player_b)| score_a.cmp(score_b)
+            .then_with(|| player_a.username.cmp(&player_b.username)))
+        .map(|(_, player)| player)
+        .take(limit)
+        .collect_vec())
+}
+
+/// suggest players by username for a player ID argument
+pub(crate) async fn autocomplete_player(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    search_players(&league(ctx).store, partial, MAX_CHOICES).await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|player| AutocompleteChoice::new(format!("{} (ID {})", player.username, player._id), player._id))
+        .collect_vec()
+}
+
+/// suggest usernames for a username argument
+pub(crate) async fn autocomplete_username(ctx: Context<'_>, partial: &str) -> Vec<String> {
+    search_players(&league(ctx).store, partial, MAX_CHOICES).await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|player| player.username)
+        .collect_vec()
+}
+
+async fn unreviewed_game_choices(store: &Store, partial: &str) -> Result<Vec<AutocompleteChoice>, BotError> {
+    let games = store.events.unreviewed_games().await?.into_iter()
+        .filter_map(|event| match event.inner {
+            GameEnd(game) => Some(game),
+            _ => None,
+        })
+        .filter(|game| game.game_id.to_string().starts_with(partial.trim()))
+        .take(MAX_CHOICES)
+        .collect_vec();
+
+    let names = store.players.names_of(&games.iter().flat_map(|game| game.ranking.iter().copied()).unique().collect_vec())
+        .await?
+        .into_iter()
+        .map(|player| (player._id, player.username))
+        .collect::<HashMap<_, _>>();
+
+    Ok(games.into_iter()
+        .map(|game| {
+            let mut label = format!("game {}: {}", game.game_id, game.ranking.iter()
+                .map(|player_id| names.get(player_id).map(String::as_str).unwrap_or("?"))
+                .join(", "));
+            // discord caps choice labels at 100 characters
+            if label.chars().count() > 100 {
+                label = label.chars().take(97).collect::<String>() + "...";
+            }
+            AutocompleteChoice::new(label, game.game_id)
+        })
+        .collect_vec())
+}
+
+/// suggest games still waiting on review
+pub(crate) async fn autocomplete_unreviewed_game(ctx: Context<'_>, partial: &str) -> Vec<AutocompleteChoice> {
+    unreviewed_game_choices(&league(ctx).store, partial).await.unwrap_or_default()
+}
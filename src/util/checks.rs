@@ -1,9 +1,27 @@
 use crate::commands::ewar::user::{try_lookup_player, UserLookupType};
+use crate::util::league::league;
 use crate::{BotError, Context};
-use poise::CreateReply;
+use poise::{CommandInteractionType, CreateReply};
+
+/// every command, autocomplete included, except those in the "Anywhere" category needs a league to play in
+pub(crate) async fn plays_in_a_league(ctx: Context<'_>) -> Result<bool, BotError> {
+    if ctx.command().category.as_deref() == Some("Anywhere")
+        || ctx.data().league_for(ctx.guild_id(), ctx.channel_id()).is_some() {
+        return Ok(true);
+    }
+
+    // autocomplete has nowhere to reply
+    if !matches!(ctx, poise::Context::Application(app) if app.interaction_type == CommandInteractionType::Autocomplete) {
+        ctx.send(CreateReply::default()
+            .content(":x: no league is played here")
+            .ephemeral(true)).await?;
+    }
+
+    Ok(false)
+}
 
 pub(crate) async fn _is_league_moderator(ctx: Context<'_>) -> Result<bool, BotError> {
-    Ok(ctx.data().league_moderators.contains(&ctx.author().id))
+    Ok(league(ctx).moderators.contains(&ctx.author().id))
 }
 
 pub(crate) async fn is_league_moderator(ctx: Context<'_>) -> Result<bool, BotError> {
@@ -19,7 +37,7 @@ pub(crate) async fn is_league_moderator(ctx: Context<'_>) -> Result<bool, BotErr
 }
 
 pub(crate) async fn has_system_account(ctx: Context<'_>) -> Result<bool, BotError> {
-    let cond = try_lookup_player(&ctx.data().mongo, UserLookupType::DiscordID(ctx.author().id.get())).await?.is_some();
+    let cond = try_lookup_player(&league(ctx).mongo, UserLookupType::DiscordID(ctx.author().id.get())).await?.is_some();
 
     if !cond {
         ctx.send(CreateReply::default()
@@ -1,17 +1,25 @@
+use crate::config::installed;
 use skillratings::trueskill::{TrueSkillConfig, TrueSkillRating};
+use std::sync::LazyLock;
 
-pub(crate) static TRUESKILL_CONFIG: TrueSkillConfig = TrueSkillConfig {
-    draw_probability: 0f64,
-    beta: 2f64,
-    // aka tau
-    default_dynamics: 0.04,
-};
+// all of these come from the installed config, so it has to be installed before any is read
 
-pub(crate) static DEFAULT_RATING: TrueSkillRating = TrueSkillRating {
-    rating: 18.0,
-    uncertainty: 9.0,
-};
+pub(crate) static TRUESKILL_CONFIG: LazyLock<TrueSkillConfig> = LazyLock::new(|| installed().rating.trueskill_config());
 
-pub(crate) static LOG_LIMIT: i64 = 50;
+pub(crate) static DEFAULT_RATING: LazyLock<TrueSkillRating> = LazyLock::new(|| installed().rating.default_rating());
 
-pub(crate) static PROVISIONAL_DEVIATION_THRESHOLD: f64 = 2.0;
+pub(crate) static LOG_LIMIT: LazyLock<i64> = LazyLock::new(|| installed().league.log_limit);
+
+pub(crate) static PROVISIONAL_DEVIATION_THRESHOLD: LazyLock<f64> = LazyLock::new(|| installed().rating.provisional_deviation_threshold);
+
+pub(crate) static RENAME_COOLDOWN_DAYS: LazyLock<i64> = LazyLock::new(|| installed().league.rename_cooldown_days);
+
+// defaults for closing a season: how much of the way back to DEFAULT_RATING ratings go, and deviation added
+pub(crate) static SOFT_RESET_PULL: LazyLock<f64> = LazyLock::new(|| installed().rating.soft_reset_pull);
+
+pub(crate) static SOFT_RESET_DELTA_DEVIATION: LazyLock<f64> = LazyLock::new(|| installed().rating.soft_reset_delta_deviation);
+
+// players shown on the live leaderboard message, and how many biggest movers are listed under it
+pub(crate) static LIVE_LEADERBOARD_SIZE: LazyLock<usize> = LazyLock::new(|| installed().league.live_leaderboard_size);
+
+pub(crate) static LIVE_LEADERBOARD_MOVERS: LazyLock<usize> = LazyLock::new(|| installed().league.live_leaderboard_movers);
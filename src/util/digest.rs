@@ -0,0 +1,128 @@
+use crate::model::StandingEventInner::{GameEnd, JoinLeague};
+use crate::model::{Achievement, PlayerID};
+use crate::store::Store;
+use crate::util::remove_markdown;
+use crate::BotError;
+use chrono::{DateTime, TimeDelta, Utc};
+use itertools::Itertools;
+use pluralizer::pluralize;
+use serenity::all::{CreateEmbed, CreateEmbedFooter};
+use std::collections::HashMap;
+
+// how many players or games each section lists
+static DIGEST_TOP: usize = 3;
+
+async fn name_of(store: &Store, player_id: PlayerID) -> Result<String, BotError> {
+    Ok(remove_markdown(&store.players.player(player_id).await?
+        .expect("player in digest DNE")
+        .username))
+}
+
+/// summary of the approved events in the week before `until`
+pub(crate) async fn weekly_digest(store: &Store, until: DateTime<Utc>) -> Result<CreateEmbed, BotError> {
+    let since = until - TimeDelta::weeks(1);
+
+    let events = store.events.approved_between(since, until).await?;
+
+    let games = events.iter()
+        .filter_map(|event| match &event.inner {
+            GameEnd(game) => Some(game),
+            _ => None,
+        })
+        .collect_vec();
+
+    let mut games_played = HashMap::<PlayerID, usize>::new();
+    let mut net_change = HashMap::<PlayerID, f64>::new();
+    for game in games.iter() {
+        for player_id in game.ranking.iter() {
+            *games_played.entry(*player_id).or_default() += 1;
+        }
+        // deltas are missing until the game is processed
+        for (player_id, delta) in game.ranking.iter().zip(game.deltas.iter()) {
+            *net_change.entry(*player_id).or_default() += delta;
+        }
+    }
+
+    let mut embed = CreateEmbed::default()
+        .color(0xfcc11b)
+        .title("weekly digest")
+        .description(format!("<t:{}:d> to <t:{}:d>: {} played", since.timestamp(), until.timestamp(),
+                             pluralize("game", games.len() as isize, true)))
+        .footer(CreateEmbedFooter::new("only approved games count"));
+
+    // names are looked up after collecting; iterators holding closures can't be kept across an await here
+    let busiest = games_played.into_iter()
+        .sorted_by(|(id_a, a), (id_b, b)| b.cmp(a).then(id_a.cmp(id_b)))
+        .take(DIGEST_TOP)
+        .collect_vec();
+    let mut most_active = Vec::new();
+    for (player_id, played) in busiest {
+        most_active.push(format!("{}: {}", name_of(store, player_id).await?, pluralize("game", played as isize, true)));
+    }
+    if !most_active.is_empty() {
+        embed = embed.field("most active", most_active.join("\n"), true);
+    }
+
+    let by_change = net_change.into_iter()
+        .sorted_by(|(id_a, a), (id_b, b)| b.total_cmp(a).then(id_a.cmp(id_b)))
+        .collect_vec();
+    let top_gainers = by_change.iter().copied().filter(|(_, change)| *change > 0.0).take(DIGEST_TOP).collect_vec();
+    let top_losers = by_change.iter().copied().rev().filter(|(_, change)| *change < 0.0).take(DIGEST_TOP).collect_vec();
+    let mut gainers = Vec::new();
+    for (player_id, change) in top_gainers {
+        gainers.push(format!("{}: {:+.2}", name_of(store, player_id).await?, change));
+    }
+    if !gainers.is_empty() {
+        embed = embed.field("biggest gainers", gainers.join("\n"), true);
+    }
+    let mut losers = Vec::new();
+    for (player_id, change) in top_losers {
+        losers.push(format!("{}: {:+.2}", name_of(store, player_id).await?, change));
+    }
+    if !losers.is_empty() {
+        embed = embed.field("biggest losers", losers.join("\n"), true);
+    }
+
+    // a winner who wasn't the favorite, by how far off the favorite they were
+    let upsets = games.iter()
+        .filter_map(|game| {
+            let favorite = game.expected.iter().copied().reduce(f64::max)?;
+            let winner = *game.expected.first()?;
+            (winner < favorite).then_some((favorite - winner, winner, game.game_id, game.ranking[0]))
+        })
+        .sorted_by(|(a, ..), (b, ..)| b.total_cmp(a))
+        .take(DIGEST_TOP)
+        .collect_vec();
+    let mut upset_lines = Vec::new();
+    for (_, winner_chance, game_id, winner_id) in upsets {
+        upset_lines.push(format!("game {game_id}: {} won at {:.0}% odds",
+                                 name_of(store, winner_id).await?, winner_chance * 100.0));
+    }
+    if !upset_lines.is_empty() {
+        embed = embed.field("upsets", upset_lines.join("\n"), false);
+    }
+
+    let joiners = events.iter()
+        .filter_map(|event| match &event.inner {
+            JoinLeague { victims, .. } => Some(victims),
+            _ => None,
+        })
+        .flatten()
+        .copied()
+        .collect_vec();
+    let mut joined = Vec::new();
+    for player_id in joiners {
+        joined.push(name_of(store, player_id).await?);
+    }
+    if !joined.is_empty() {
+        embed = embed.field("new players", joined.join(", "), false);
+    }
+
+    let established = store.players.unlocked_between(Achievement::Established, since, until).await?;
+    if !established.is_empty() {
+        embed = embed.field("left provisional status",
+                            established.iter().map(|player| remove_markdown(&player.username)).join(", "), false);
+    }
+
+    Ok(embed)
+}
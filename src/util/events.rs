@@ -0,0 +1,122 @@
+use crate::model::{EventNumber, LeagueInfo, StandingEvent};
+use crate::BotError;
+use bson::{doc, Document};
+use mongodb::error::{Error, TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT};
+use mongodb::{ClientSession, Database};
+use std::future::Future;
+use std::iter;
+use std::time::Duration;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+// how long one append may keep retrying; commands are waiting on it, so well short of the driver's two minutes
+static TRANSACTION_DEADLINE: Duration = Duration::from_secs(30);
+
+/// an event number read inside a transaction; nothing is visible to anyone else until `append`.
+/// dropping this without appending aborts the transaction
+pub(crate) struct EventAppend {
+    mongo: Database,
+    session: ClientSession,
+    deadline: Instant,
+    // the league_info counters to draw: the event number, plus any others asked for
+    counters: Vec<String>,
+    // league_info as it was when the transaction started, so its counters are the ones to draw
+    reserved: LeagueInfo,
+}
+
+impl EventAppend {
+    /// start a transaction and read which event number, and which of each counter named in `also`, come next.
+    /// nothing is drawn until `append`, so league_info is written only at the very end of the transaction
+    pub(crate) async fn begin(mongo: &Database, also: &[&str], deadline: Instant) -> Result<Self, BotError> {
+        let mut session = mongo.client().start_session().await?;
+        session.start_transaction().await?;
+        let reserved = mongo.collection::<LeagueInfo>("league_info")
+            .find_one(doc! {})
+            .session(&mut session)
+            .await?
+            .ok_or("league_info struct missing")?;
+
+        Ok(EventAppend {
+            mongo: mongo.clone(),
+            session,
+            deadline,
+            counters: iter::once("available_event_number").chain(also.iter().copied()).map(String::from).collect(),
+            reserved,
+        })
+    }
+
+    pub(crate) fn event_number(&self) -> EventNumber {
+        self.reserved.available_event_number
+    }
+
+    pub(crate) fn reserved(&self) -> &LeagueInfo {
+        &self.reserved
+    }
+
+    /// for any other writes that must land together with the event
+    pub(crate) fn session(&mut self) -> &mut ClientSession {
+        &mut self.session
+    }
+
+    /// draw the numbers read at the start, write the event under them, and commit everything done in this transaction.
+    /// anyone who drew in between makes this a write conflict, which `with_retries` runs again from the top
+    pub(crate) async fn append(mut self, event: StandingEvent) -> Result<EventNumber, BotError> {
+        assert_eq!(event._id, self.event_number(), "event appended under a number it did not reserve");
+
+        let reserved = bson::to_document(&self.reserved)?;
+        let mut expected = Document::new();
+        let mut increments = Document::new();
+        for counter in self.counters.iter().map(String::as_str) {
+            expected.insert(counter, reserved.get(counter).cloned().ok_or_else(|| format!("no counter {counter} in league_info"))?);
+            increments.insert(counter, 1);
+        }
+
+        self.mongo.collection::<LeagueInfo>("league_info")
+            .find_one_and_update(expected, doc! { "$inc": increments })
+            .session(&mut self.session)
+            .await?
+            .ok_or("league_info moved under an open transaction")?;
+        self.mongo.collection::<StandingEvent>("events")
+            .insert_one(&event)
+            .session(&mut self.session)
+            .await?;
+
+        // the commit may have gone through even if we didn't hear back; committing again is safe
+        loop {
+            match self.session.commit_transaction().await {
+                Ok(()) => break,
+                Err(err) if err.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) && Instant::now() < self.deadline => {
+                    warn!(error = %err, "unknown commit result, committing again");
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+        info!(event_number = event._id, "appended event");
+
+        Ok(event._id)
+    }
+}
+
+/// run `attempt` until it commits, like the driver's `with_transaction`: the whole of it again on a transient
+/// transaction error, until the deadline it is handed. each attempt has to begin its own `EventAppend`
+pub(crate) async fn with_retries<T, F>(mut attempt: impl FnMut(Instant) -> F) -> Result<T, BotError>
+where
+    F: Future<Output = Result<T, BotError>>,
+{
+    let deadline = Instant::now() + TRANSACTION_DEADLINE;
+    let mut tries = 1;
+    loop {
+        match attempt(deadline).await {
+            Err(err) if is_transient(&err) && Instant::now() < deadline => {
+                // two writers racing for league_info is the usual conflict; the loser just draws again
+                tokio::time::sleep(Duration::from_millis(20 * tries.min(10))).await;
+                tries += 1;
+            }
+            result => return result,
+        }
+    }
+}
+
+fn is_transient(err: &BotError) -> bool {
+    err.downcast_ref::<Error>().is_some_and(|err| err.contains_label(TRANSIENT_TRANSACTION_ERROR))
+}
@@ -0,0 +1,150 @@
+use crate::model::VICTIM_VARIANTS;
+use crate::BotError;
+use bson::{doc, Document};
+use futures::TryStreamExt;
+use itertools::Itertools;
+use mongodb::options::IndexOptions;
+use mongodb::{Database, IndexModel};
+use std::collections::HashSet;
+
+/// an index the bot's queries rely on
+pub(crate) struct RequiredIndex {
+    pub(crate) collection: &'static str,
+    pub(crate) name: String,
+    keys: Document,
+    unique: bool,
+    // only documents matching this are indexed
+    partial: Option<Document>,
+}
+
+impl RequiredIndex {
+    fn new(collection: &'static str, name: &str, keys: Document) -> Self {
+        RequiredIndex { collection, name: String::from(name), keys, unique: false, partial: None }
+    }
+
+    fn unique(mut self) -> Self {
+        self.unique = true;
+        self
+    }
+
+    fn partial(mut self, filter: Document) -> Self {
+        self.partial = Some(filter);
+        self
+    }
+
+    fn model(&self) -> IndexModel {
+        IndexModel::builder()
+            .keys(self.keys.clone())
+            .options(IndexOptions::builder()
+                .name(self.name.clone())
+                .unique(self.unique.then_some(true))
+                .partial_filter_expression(self.partial.clone())
+                .build())
+            .build()
+    }
+}
+
+/// every index the bot expects in a league database
+pub(crate) fn required_indexes() -> Vec<RequiredIndex> {
+    let mut indexes = vec![
+        // these two date from migration 3 and must keep its names and options
+        RequiredIndex::new("players", "username_unique", doc! { "username": 1 }).unique(),
+        // players made by moderators may have no discord account at all
+        RequiredIndex::new("players", "discord_ids_unique", doc! { "discord_ids": 1 })
+            .unique()
+            .partial(doc! { "discord_ids.0": { "$exists": true } }),
+        // player autocomplete
+        RequiredIndex::new("players", "username_lower", doc! { "username_lower": 1 }),
+        // inactivity decay
+        RequiredIndex::new("players", "last_played", doc! { "last_played": 1 }),
+        // suspension expiry
+        RequiredIndex::new("players", "suspension_until", doc! { "suspension.until": 1 }),
+        // /review, /game query
+        RequiredIndex::new("events", "game_id_unique", doc! { "inner.GameEnd.game_id": 1 })
+            .unique()
+            .partial(doc! { "inner.GameEnd.game_id": { "$exists": true } }),
+        RequiredIndex::new("events", "game_ranking", doc! { "inner.GameEnd.ranking": 1 }),
+        // the unreviewed queue
+        RequiredIndex::new("events", "approval_status", doc! { "approval_status": 1, "_id": 1 }),
+        // weekly digest
+        RequiredIndex::new("events", "when", doc! { "when": 1 }),
+        RequiredIndex::new("seasons", "standings_player", doc! { "standings.player": 1 }),
+        RequiredIndex::new("rename_requests", "new_username", doc! { "new_username": 1 }),
+    ];
+
+    // a player's record is an $or over every kind of event that names them
+    indexes.extend(VICTIM_VARIANTS.iter().map(|variant| RequiredIndex::new(
+        "events",
+        &format!("{}_victims", variant.to_lowercase()),
+        doc! { format!("inner.{variant}.victims"): 1 })));
+
+    indexes
+}
+
+/// create any required index that is missing. existing indexes are left alone,
+/// but one with a required name and different keys or options is an error
+pub(crate) async fn ensure_indexes(mongo: &Database) -> Result<(), BotError> {
+    let required = required_indexes();
+    let collections = required.iter().map(|index| index.collection).unique().collect_vec();
+    for collection in collections {
+        let models = required.iter()
+            .filter(|index| index.collection == collection)
+            .map(RequiredIndex::model)
+            .collect_vec();
+        mongo.collection::<Document>(collection).create_indexes(models).await
+            .map_err(|err| format!("couldn't create indexes on {collection}: {err}"))?;
+    }
+
+    Ok(())
+}
+
+/// how the indexes in a database compare to what the bot expects
+pub(crate) struct IndexReport {
+    pub(crate) missing: Vec<String>,
+    // present but not declared by the bot
+    pub(crate) undeclared: Vec<String>,
+    // declared and present, but not used since the server last started
+    pub(crate) unused: Vec<String>,
+}
+
+pub(crate) async fn index_report(mongo: &Database) -> Result<IndexReport, BotError> {
+    let required = required_indexes();
+    let mut report = IndexReport { missing: vec![], undeclared: vec![], unused: vec![] };
+
+    // collected up front; iterators holding closures can't be kept across an await here
+    let collections = required.iter().map(|index| index.collection).unique().collect_vec();
+    for collection in collections {
+        let coll = mongo.collection::<Document>(collection);
+        let declared = required.iter()
+            .filter(|index| index.collection == collection)
+            .map(|index| index.name.clone())
+            .collect::<HashSet<_>>();
+        let present = coll.list_index_names().await?.into_iter().collect::<HashSet<_>>();
+
+        for name in declared.difference(&present) {
+            report.missing.push(format!("{collection}.{name}"));
+        }
+        for name in present.difference(&declared) {
+            if name != "_id_" {
+                report.undeclared.push(format!("{collection}.{name}"));
+            }
+        }
+
+        let stats = coll.aggregate(vec![doc! { "$indexStats": {} }]).await?
+            .try_collect::<Vec<_>>().await?;
+        for stat in stats {
+            let name = stat.get_str("name")?;
+            let ops = stat.get_document("accesses")?.get("ops")
+                .and_then(|ops| ops.as_i64().or(ops.as_i32().map(i64::from)))
+                .unwrap_or(0);
+            if ops == 0 && declared.contains(name) {
+                report.unused.push(format!("{collection}.{name}"));
+            }
+        }
+    }
+
+    report.missing.sort();
+    report.undeclared.sort();
+    report.unused.sort();
+    Ok(report)
+}
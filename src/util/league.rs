@@ -0,0 +1,87 @@
+use crate::config::{ExtraLeague, LeagueConfig};
+use crate::store::Store;
+use crate::util::processor::RatingProcessor;
+use crate::util::review::ReviewPolicy;
+use crate::{BotVars, Context};
+use mongodb::Database;
+use serenity::all::{ChannelId, GuildId, Http, UserId};
+use std::collections::HashSet;
+use std::sync::Arc;
+
+/// one set of players, events and ratings, and where it is played
+#[derive(Clone)]
+pub(crate) struct League {
+    pub(crate) name: String,
+    pub(crate) mongo: Database,
+    pub(crate) store: Store,
+    pub(crate) processor: RatingProcessor,
+    pub(crate) guilds: Vec<GuildId>,
+    pub(crate) channels: Vec<ChannelId>,
+    pub(crate) moderators: HashSet<UserId>,
+    pub(crate) review_policy: ReviewPolicy,
+}
+
+/// who runs a league and where it is played
+pub(crate) struct LeagueSettings {
+    pub(crate) guilds: Vec<GuildId>,
+    pub(crate) channels: Vec<ChannelId>,
+    pub(crate) moderators: HashSet<UserId>,
+    pub(crate) review_policy: ReviewPolicy,
+    // where achievement unlocks are posted
+    pub(crate) announce_channel: Option<ChannelId>,
+}
+
+impl LeagueSettings {
+    pub(crate) fn main(config: &LeagueConfig) -> Self {
+        LeagueSettings {
+            guilds: config.guilds.iter().copied().map(GuildId::from).collect(),
+            channels: config.channels.iter().copied().map(ChannelId::from).collect(),
+            moderators: config.moderator_discords.iter().copied().map(UserId::from).collect(),
+            review_policy: ReviewPolicy::from_config(&config.review),
+            announce_channel: config.announce_channel.map(ChannelId::from),
+        }
+    }
+
+    pub(crate) fn extra(config: &ExtraLeague) -> Self {
+        LeagueSettings {
+            guilds: config.guilds.iter().copied().map(GuildId::from).collect(),
+            channels: config.channels.iter().copied().map(ChannelId::from).collect(),
+            moderators: config.moderator_discords.iter().copied().map(UserId::from).collect(),
+            review_policy: ReviewPolicy::from_config(&config.review),
+            announce_channel: config.announce_channel.map(ChannelId::from),
+        }
+    }
+}
+
+impl League {
+    /// also starts the league's rating processor
+    pub(crate) fn open(
+        name: String,
+        mongo: Database,
+        // where the players' identities shared by every league are kept
+        directory: &Database,
+        // which process this is, for leases
+        holder: String,
+        http: Arc<Http>,
+        settings: LeagueSettings,
+    ) -> Self {
+        let LeagueSettings { guilds, channels, moderators, review_policy, announce_channel } = settings;
+        let store = Store::mongo(&mongo, directory);
+        let processor = RatingProcessor::spawn(name.clone(), store.clone(), mongo.clone(), holder, http, announce_channel);
+        League { name, mongo, store, processor, guilds, channels, moderators, review_policy }
+    }
+}
+
+impl BotVars {
+    /// a channel listed by a league wins over its guild; nothing is played anywhere no league lists
+    pub(crate) fn league_for(&self, guild: Option<GuildId>, channel: ChannelId) -> Option<&League> {
+        self.leagues.iter()
+            .find(|league| league.channels.contains(&channel))
+            .or_else(|| guild.and_then(|guild| self.leagues.iter().find(|league| league.guilds.contains(&guild))))
+    }
+}
+
+/// the league a command was run in; `plays_in_a_league` keeps commands from running anywhere else
+pub(crate) fn league(ctx: Context<'_>) -> &League {
+    ctx.data().league_for(ctx.guild_id(), ctx.channel_id()).expect("command ran outside of any league")
+}
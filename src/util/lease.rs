@@ -0,0 +1,134 @@
+use crate::store::Fence;
+use crate::BotError;
+use async_trait::async_trait;
+use bson::oid::ObjectId;
+use bson::{doc, Document};
+use chrono::{DateTime, TimeDelta, Utc};
+use mongodb::error::{ErrorKind, WriteFailure};
+use mongodb::options::ReturnDocument;
+use mongodb::Database;
+use serde::Deserialize;
+use std::time::Duration;
+
+// a replica that dies holding a lease blocks everyone else for at most this long
+static LEASE_TTL: TimeDelta = TimeDelta::seconds(30);
+// how long to wait on someone else's lease before giving up
+static ACQUIRE_WAIT: Duration = Duration::from_secs(45);
+static ACQUIRE_POLL: Duration = Duration::from_millis(500);
+
+/// an expiring, exclusive claim on some shared state, held by one bot process at a time.
+/// every acquisition gets a higher fencing token; writes fenced with it are refused once anyone acquires after us
+pub(crate) struct Lease {
+    mongo: Database,
+    name: &'static str,
+    holder: String,
+    token: i64,
+    expires: DateTime<Utc>,
+}
+
+#[derive(Deserialize)]
+struct LeaseDoc {
+    token: i64,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    expires: DateTime<Utc>,
+}
+
+/// identifies this process as a lease holder
+pub(crate) fn new_holder_id() -> String {
+    ObjectId::new().to_hex()
+}
+
+impl Lease {
+    /// take the lease if it is free, expired or already ours; `None` if someone else holds it
+    pub(crate) async fn try_acquire(mongo: &Database, name: &'static str, holder: &str) -> Result<Option<Lease>, BotError> {
+        let now = Utc::now();
+        let acquired = mongo.collection::<Document>("leases")
+            .find_one_and_update(
+                doc! {
+                    "_id": name,
+                    "$or": [
+                        { "holder": holder },
+                        { "expires": { "$lte": bson::DateTime::from_chrono(now) } },
+                    ],
+                },
+                doc! {
+                    "$set": { "holder": holder, "expires": bson::DateTime::from_chrono(now + LEASE_TTL) },
+                    "$inc": { "token": 1_i64 },
+                })
+            .upsert(true)
+            .return_document(ReturnDocument::After)
+            .await;
+
+        let acquired = match acquired {
+            Ok(acquired) => acquired.expect("upserted lease missing"),
+            // no match means a live lease exists, so the upsert collides with it
+            Err(err) if matches!(*err.kind, ErrorKind::Command(ref failure) if failure.code == 11000)
+                || matches!(*err.kind, ErrorKind::Write(WriteFailure::WriteError(ref failure)) if failure.code == 11000) => {
+                return Ok(None);
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let LeaseDoc { token, expires } = bson::from_document(acquired)?;
+        Ok(Some(Lease { mongo: mongo.clone(), name, holder: holder.to_string(), token, expires }))
+    }
+
+    /// wait a while for the lease to come free
+    pub(crate) async fn acquire(mongo: &Database, name: &'static str, holder: &str) -> Result<Lease, BotError> {
+        let give_up = tokio::time::Instant::now() + ACQUIRE_WAIT;
+        loop {
+            if let Some(lease) = Lease::try_acquire(mongo, name, holder).await? {
+                return Ok(lease);
+            }
+            if tokio::time::Instant::now() >= give_up {
+                return Err(format!("another bot process is holding the {name} lease; try again shortly").into());
+            }
+            tokio::time::sleep(ACQUIRE_POLL).await;
+        }
+    }
+
+    /// make sure the lease is still ours before writing more, extending it if it is getting old.
+    /// once this fails, stop writing: someone else may already be
+    pub(crate) async fn check(&mut self) -> Result<(), BotError> {
+        let now = Utc::now();
+        if now >= self.expires {
+            return Err(format!("lost the {} lease (expired)", self.name).into());
+        }
+        if self.expires - now > LEASE_TTL / 2 {
+            return Ok(());
+        }
+
+        let renewed = self.mongo.collection::<Document>("leases")
+            .update_one(
+                doc! { "_id": self.name, "holder": &self.holder, "token": self.token },
+                doc! { "$set": { "expires": bson::DateTime::from_chrono(now + LEASE_TTL) } })
+            .await?;
+        if renewed.matched_count == 0 {
+            return Err(format!("lost the {} lease (taken over)", self.name).into());
+        }
+
+        self.expires = now + LEASE_TTL;
+        Ok(())
+    }
+
+    /// let someone else have the lease right away rather than when it expires
+    pub(crate) async fn release(self) -> Result<(), BotError> {
+        self.mongo.collection::<Document>("leases")
+            .update_one(
+                doc! { "_id": self.name, "holder": &self.holder, "token": self.token },
+                doc! { "$set": { "expires": bson::DateTime::from_chrono(Utc::now()) } })
+            .await?;
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl Fence for Lease {
+    async fn check(&mut self) -> Result<(), BotError> {
+        Lease::check(self).await
+    }
+
+    fn fencing_token(&self) -> i64 {
+        self.token
+    }
+}
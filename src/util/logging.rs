@@ -0,0 +1,129 @@
+use crate::config::{LogFormat, LoggingConfig};
+use crate::util::metrics::METRICS;
+use crate::{BotError, BotVars, Context};
+use futures::future::BoxFuture;
+use poise::FrameworkError;
+use serenity::all::{FullEvent, Interaction};
+use serenity::async_trait;
+use serenity::framework::Framework;
+use serenity::Client;
+use std::time::Instant;
+use tracing::field::Empty;
+use tracing::{error, info, info_span, Instrument, Span};
+use tracing_subscriber::layer::SubscriberExt;
+use tracing_subscriber::util::SubscriberInitExt;
+use tracing_subscriber::{fmt, reload, EnvFilter, Registry};
+
+/// changes which logs are kept while the bot runs
+#[derive(Clone)]
+pub(crate) struct LogLevel {
+    handle: reload::Handle<EnvFilter, Registry>,
+}
+
+impl LogLevel {
+    /// the filter in use, like `info,ewar_bot=debug`
+    pub(crate) fn current(&self) -> Result<String, BotError> {
+        Ok(self.handle.with_current(|filter| filter.to_string())?)
+    }
+
+    pub(crate) fn set(&self, directives: &str) -> Result<(), BotError> {
+        let filter = EnvFilter::try_new(directives)?;
+        self.handle.reload(filter)?;
+        Ok(())
+    }
+}
+
+/// start logging as the config says; everything logged before this is lost
+pub(crate) fn init(config: &LoggingConfig) -> LogLevel {
+    let filter = EnvFilter::try_new(&config.level).expect("log level was validated with the config");
+    let (filter, handle) = reload::Layer::new(filter);
+
+    let (pretty, json) = match config.format {
+        LogFormat::Pretty => (Some(fmt::layer()), None),
+        LogFormat::Json => (None, Some(fmt::layer().json().flatten_event(true).with_current_span(true).with_span_list(true))),
+    };
+    tracing_subscriber::registry()
+        .with(filter)
+        .with(pretty)
+        .with(json)
+        .init();
+
+    LogLevel { handle }
+}
+
+/// runs whatever each incoming command does inside a `command` span, so every log line it causes
+/// (including from the rating processor on its behalf) carries who ran what where
+pub(crate) struct TracedFramework<F> {
+    inner: F,
+}
+
+impl<F> TracedFramework<F> {
+    pub(crate) fn new(inner: F) -> Self {
+        TracedFramework { inner }
+    }
+}
+
+#[async_trait]
+impl<F: Framework> Framework for TracedFramework<F> {
+    async fn init(&mut self, client: &Client) {
+        self.inner.init(client).await;
+    }
+
+    async fn dispatch(&self, ctx: serenity::all::Context, event: FullEvent) {
+        // anything else can't be a command; autocomplete is too frequent to be worth a span
+        let span = match &event {
+            FullEvent::InteractionCreate { interaction: Interaction::Command(_) } | FullEvent::Message { .. } =>
+                info_span!("command", name = Empty, invoker = Empty, guild = Empty, league = Empty),
+            _ => Span::none(),
+        };
+        self.inner.dispatch(ctx, event).instrument(span).await;
+    }
+}
+
+// when the command started, for its latency
+struct Started(Instant);
+
+/// fill in the command span and note the start
+pub(crate) fn pre_command(ctx: Context<'_>) -> BoxFuture<'_, ()> {
+    Box::pin(async move {
+        let span = Span::current();
+        span.record("name", ctx.command().qualified_name.as_str());
+        span.record("invoker", ctx.author().id.get());
+        if let Some(guild) = ctx.guild_id() {
+            span.record("guild", guild.get());
+        }
+        if let Some(league) = ctx.data().league_for(ctx.guild_id(), ctx.channel_id()) {
+            span.record("league", league.name.as_str());
+        }
+
+        ctx.set_invocation_data(Started(Instant::now())).await;
+        info!("command started");
+    })
+}
+
+pub(crate) fn post_command(ctx: Context<'_>) -> BoxFuture<'_, ()> {
+    Box::pin(async move {
+        let elapsed = ctx.invocation_data::<Started>().await.map(|started| started.0.elapsed());
+        METRICS.command(&ctx.command().qualified_name, true, elapsed);
+        info!(elapsed_ms = elapsed.map(|elapsed| elapsed.as_millis() as u64), "command finished");
+    })
+}
+
+/// log the failure, then tell the invoker the way poise normally would
+pub(crate) fn on_error(error: FrameworkError<'_, BotVars, BotError>) -> BoxFuture<'_, ()> {
+    Box::pin(async move {
+        match &error {
+            FrameworkError::Command { error: err, ctx, .. } => {
+                let elapsed = ctx.invocation_data::<Started>().await.map(|started| started.0.elapsed());
+                METRICS.command(&ctx.command().qualified_name, false, elapsed);
+                error!(error = %err, "command failed");
+            }
+            FrameworkError::Setup { error: err, .. } => error!(error = %err, "setup failed"),
+            other => info!(error = %other, "command not run"),
+        }
+
+        if let Err(err) = poise::builtins::on_error(error).await {
+            error!(error = %err, "couldn't report a command error");
+        }
+    })
+}
@@ -0,0 +1,199 @@
+//! prometheus metrics, served at `/metrics` when `metrics.listen` is set. counters and latencies are
+//! recorded as things happen; queue depth and pointer lag are read from each league when scraped
+
+use crate::util::league::League;
+use crate::BotError;
+use mongodb::event::command::CommandEvent;
+use mongodb::event::EventHandler;
+use prometheus_client::encoding::text::encode;
+use prometheus_client::encoding::EncodeLabelSet;
+use prometheus_client::metrics::counter::Counter;
+use prometheus_client::metrics::family::Family;
+use prometheus_client::metrics::gauge::Gauge;
+use prometheus_client::metrics::histogram::{exponential_buckets, Histogram};
+use prometheus_client::registry::Registry;
+use std::net::SocketAddr;
+use std::sync::LazyLock;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::time::timeout;
+use tracing::{info, warn};
+
+pub(crate) static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+const REQUEST_LINE_LIMIT: u64 = 1024;
+const REQUEST_LINE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct CommandLabels {
+    command: String,
+    outcome: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct SignoffLabels {
+    league: String,
+    outcome: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct LeagueLabels {
+    league: String,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct JobLabels {
+    job: &'static str,
+    league: String,
+    outcome: &'static str,
+}
+
+#[derive(Clone, Debug, Hash, PartialEq, Eq, EncodeLabelSet)]
+struct MongoLabels {
+    command: String,
+    outcome: &'static str,
+}
+
+// commands include `post`, which can wait several minutes on signoff
+fn command_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.05, 2.0, 14))
+}
+
+fn mongo_histogram() -> Histogram {
+    Histogram::new(exponential_buckets(0.001, 2.0, 14))
+}
+
+pub(crate) struct Metrics {
+    registry: Registry,
+    commands: Family<CommandLabels, Counter>,
+    command_seconds: Family<CommandLabels, Histogram, fn() -> Histogram>,
+    signoffs: Family<SignoffLabels, Counter>,
+    unreviewed: Family<LeagueLabels, Gauge>,
+    pointer_lag: Family<LeagueLabels, Gauge>,
+    jobs: Family<JobLabels, Counter>,
+    mongo_seconds: Family<MongoLabels, Histogram, fn() -> Histogram>,
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let mut metrics = Metrics {
+            registry: Registry::with_prefix("ewar"),
+            commands: Family::default(),
+            command_seconds: Family::new_with_constructor(command_histogram),
+            signoffs: Family::default(),
+            unreviewed: Family::default(),
+            pointer_lag: Family::default(),
+            jobs: Family::default(),
+            mongo_seconds: Family::new_with_constructor(mongo_histogram),
+        };
+
+        let registry = &mut metrics.registry;
+        registry.register("commands", "commands run, by whether they succeeded", metrics.commands.clone());
+        registry.register("command_seconds", "how long commands took", metrics.command_seconds.clone());
+        registry.register("signoffs", "posted games whose players signed off in time, or didn't", metrics.signoffs.clone());
+        registry.register("unreviewed_games", "games waiting on moderator review", metrics.unreviewed.clone());
+        registry.register("approve_pointer_lag", "events recorded but not yet processed for ratings", metrics.pointer_lag.clone());
+        registry.register("job_runs", "scheduled job runs per league, by whether they succeeded", metrics.jobs.clone());
+        registry.register("mongo_command_seconds", "how long mongo took to answer each command", metrics.mongo_seconds.clone());
+
+        metrics
+    }
+
+    /// a finished command, and whether it returned an error
+    pub(crate) fn command(&self, command: &str, succeeded: bool, elapsed: Option<Duration>) {
+        let labels = CommandLabels { command: command.to_owned(), outcome: outcome(succeeded) };
+        self.commands.get_or_create(&labels).inc();
+        if let Some(elapsed) = elapsed {
+            self.command_seconds.get_or_create(&labels).observe(elapsed.as_secs_f64());
+        }
+    }
+
+    /// a posted game's signoff stage either filled up or timed out
+    pub(crate) fn signoff(&self, league: &str, completed: bool) {
+        let outcome = if completed { "completed" } else { "timed_out" };
+        self.signoffs.get_or_create(&SignoffLabels { league: league.to_owned(), outcome }).inc();
+    }
+
+    pub(crate) fn job(&self, job: &'static str, league: &str, succeeded: bool) {
+        self.jobs.get_or_create(&JobLabels { job, league: league.to_owned(), outcome: outcome(succeeded) }).inc();
+    }
+
+    fn mongo_command(&self, command: &str, succeeded: bool, duration: Duration) {
+        self.mongo_seconds
+            .get_or_create(&MongoLabels { command: command.to_owned(), outcome: outcome(succeeded) })
+            .observe(duration.as_secs_f64());
+    }
+
+    /// read how far behind each league is, then render everything
+    async fn scrape(&self, leagues: &[League]) -> Result<String, BotError> {
+        for league in leagues {
+            let labels = LeagueLabels { league: league.name.clone() };
+            match league.store.events.count_unreviewed().await {
+                Ok(count) => { self.unreviewed.get_or_create(&labels).set(count as i64); }
+                Err(err) => warn!(league = %league.name, error = %err, "couldn't count unreviewed games"),
+            }
+            match league.store.league_info.league_info().await {
+                Ok(info) => {
+                    let lag = info.available_event_number as i64 - info.first_unreviewed_event_number as i64;
+                    self.pointer_lag.get_or_create(&labels).set(lag);
+                }
+                Err(err) => warn!(league = %league.name, error = %err, "couldn't read league_info"),
+            }
+        }
+
+        let mut body = String::new();
+        encode(&mut body, &self.registry)?;
+        Ok(body)
+    }
+}
+
+fn outcome(succeeded: bool) -> &'static str {
+    if succeeded { "ok" } else { "error" }
+}
+
+/// for `ClientOptions::command_event_handler`, to time every mongo command
+pub(crate) fn mongo_event_handler() -> EventHandler<CommandEvent> {
+    EventHandler::callback(|event| match event {
+        CommandEvent::Succeeded(event) => METRICS.mongo_command(&event.command_name, true, event.duration),
+        CommandEvent::Failed(event) => METRICS.mongo_command(&event.command_name, false, event.duration),
+        _ => {}
+    })
+}
+
+/// answer `GET /metrics` on `listen` until the bot stops; anything else gets a 404
+pub(crate) async fn serve(listen: SocketAddr, leagues: Vec<League>) -> Result<(), BotError> {
+    let listener = TcpListener::bind(listen).await?;
+    info!(%listen, "serving metrics");
+
+    loop {
+        let (stream, _) = listener.accept().await?;
+        let leagues = leagues.clone();
+        tokio::spawn(async move {
+            if let Err(err) = respond(stream, &leagues).await {
+                warn!(error = %err, "couldn't answer metrics request");
+            }
+        });
+    }
+}
+
+async fn respond(mut stream: TcpStream, leagues: &[League]) -> Result<(), BotError> {
+    // only the request line matters; a client that can't send that much promptly gets dropped
+    let mut request_line = Vec::new();
+    let mut reader = BufReader::new((&mut stream).take(REQUEST_LINE_LIMIT));
+    timeout(REQUEST_LINE_TIMEOUT, reader.read_until(b'\n', &mut request_line)).await??;
+    let request_line = String::from_utf8_lossy(&request_line);
+
+    let response = match request_line.split_whitespace().take(2).collect::<Vec<_>>()[..] {
+        ["GET", "/metrics"] => {
+            let body = METRICS.scrape(leagues).await?;
+            format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: text/plain; version=0.0.4; charset=utf-8\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+                body.len())
+        }
+        _ => String::from("HTTP/1.1 404 Not Found\r\ncontent-length: 0\r\nconnection: close\r\n\r\n"),
+    };
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
@@ -0,0 +1,222 @@
+use crate::util::lease::Lease;
+use crate::BotError;
+use bson::{doc, Document};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use futures::TryStreamExt;
+use mongodb::options::IndexOptions;
+use mongodb::{Database, IndexModel};
+use serde::{Deserialize, Serialize};
+
+// the league's database, then the directory every league's player identities are kept in
+type MigrationFn = for<'a> fn(&'a Database, &'a Database) -> BoxFuture<'a, Result<(), BotError>>;
+
+/// one step in the shape of a league database. steps are never edited once released, only added;
+/// each must be safe to run again in case the bot dies partway through
+struct Migration {
+    version: u32,
+    description: &'static str,
+    run: MigrationFn,
+}
+
+static MIGRATIONS: &[Migration] = &[
+    Migration { version: 1, description: "create league_info", run: |mongo, _| Box::pin(create_league_info(mongo)) },
+    Migration { version: 2, description: "backfill fields added since launch", run: |mongo, _| Box::pin(backfill_fields(mongo)) },
+    Migration { version: 3, description: "unique usernames and discord accounts", run: |mongo, _| Box::pin(unique_player_keys(mongo)) },
+    Migration { version: 4, description: "lowercased usernames for player search", run: |mongo, _| Box::pin(lowercase_usernames(mongo)) },
+    Migration { version: 5, description: "shared player identities", run: |mongo, directory| Box::pin(shared_identities(mongo, directory)) },
+];
+
+// every schema_version document is one applied migration
+#[derive(Serialize, Deserialize)]
+struct AppliedMigration {
+    _id: u32,
+    description: String,
+    #[serde(with = "bson::serde_helpers::chrono_datetime_as_bson_datetime")]
+    applied: DateTime<Utc>,
+}
+
+// keeps two processes starting at once from migrating the same database together
+static MIGRATIONS_LEASE: &str = "migrations";
+
+/// the schema this build expects
+pub(crate) fn latest_schema_version() -> u32 {
+    MIGRATIONS.last().map_or(0, |migration| migration.version)
+}
+
+/// the last migration applied to this database, 0 for a fresh one
+pub(crate) async fn schema_version(mongo: &Database) -> Result<u32, BotError> {
+    Ok(mongo.collection::<AppliedMigration>("schema_version")
+        .find_one(doc! {})
+        .sort(doc! { "_id": -1 })
+        .await?
+        .map_or(0, |applied| applied._id))
+}
+
+/// bring a league database up to the schema this build expects, returning what was applied.
+/// refuses to touch a database a newer build has already migrated
+pub(crate) async fn migrate(mongo: &Database, directory: &Database, holder: &str) -> Result<Vec<&'static str>, BotError> {
+    let mut lease = Lease::acquire(mongo, MIGRATIONS_LEASE, holder).await?;
+
+    let current = schema_version(mongo).await?;
+    if current > latest_schema_version() {
+        return Err(format!("database is at schema version {current}, but this build only knows up to {}; \
+                            upgrade the bot", latest_schema_version()).into());
+    }
+
+    let mut applied = Vec::new();
+    for migration in MIGRATIONS.iter().filter(|migration| migration.version > current) {
+        lease.check().await?;
+        (migration.run)(mongo, directory).await
+            .map_err(|err| format!("migration {} ({}) failed: {err}", migration.version, migration.description))?;
+
+        mongo.collection::<AppliedMigration>("schema_version").insert_one(AppliedMigration {
+            _id: migration.version,
+            description: String::from(migration.description),
+            applied: Utc::now(),
+        }).await?;
+        applied.push(migration.description);
+    }
+
+    lease.release().await?;
+    Ok(applied)
+}
+
+async fn create_league_info(mongo: &Database) -> Result<(), BotError> {
+    // an upsert with no match inserts, and does nothing to a league that already exists
+    mongo.collection::<Document>("league_info")
+        .update_one(doc! {}, doc! { "$setOnInsert": {
+            "first_unreviewed_event_number": 0_i64,
+            "available_game_id": 0_i64,
+            "available_event_number": 0_i64,
+            "available_player_id": 1_i64,
+            "leaderboard_blacklist": [],
+            "achievements_announced_before": 0_i64,
+            "live_leaderboard": null,
+        } })
+        .upsert(true)
+        .await?;
+
+    Ok(())
+}
+
+async fn backfill_fields(mongo: &Database) -> Result<(), BotError> {
+    let league_info = mongo.collection::<Document>("league_info");
+    for (field, default) in [
+        ("leaderboard_blacklist", bson::Bson::Array(vec![])),
+        ("achievements_announced_before", bson::Bson::Int64(0)),
+        ("live_leaderboard", bson::Bson::Null),
+    ] {
+        league_info.update_many(doc! { field: { "$exists": false } }, doc! { "$set": { field: default } }).await?;
+    }
+
+    // blacklist entries used to be bare player IDs
+    league_info.update_many(doc! {}, vec![doc! { "$set": { "leaderboard_blacklist": { "$map": {
+        "input": "$leaderboard_blacklist",
+        "as": "entry",
+        "in": { "$cond": [
+            { "$eq": [{ "$type": "$$entry" }, "object"] },
+            "$$entry",
+            { "player": "$$entry", "added_by": null, "reason": null, "when": null, "expires": null },
+        ] },
+    } } } }]).await?;
+
+    let players = mongo.collection::<Document>("players");
+    for (field, default) in [
+        ("suspension", bson::Bson::Null),
+        ("peak", bson::Bson::Null),
+        ("trough", bson::Bson::Null),
+        ("achievements", bson::Bson::Array(vec![])),
+    ] {
+        players.update_many(doc! { field: { "$exists": false } }, doc! { "$set": { field: default } }).await?;
+    }
+
+    mongo.collection::<Document>("events")
+        .update_many(doc! { "approvals": { "$exists": false } }, doc! { "$set": { "approvals": [] } })
+        .await?;
+
+    Ok(())
+}
+
+async fn unique_player_keys(mongo: &Database) -> Result<(), BotError> {
+    let players = mongo.collection::<Document>("players");
+    players.create_index(IndexModel::builder()
+        .keys(doc! { "username": 1 })
+        .options(IndexOptions::builder().name(String::from("username_unique")).unique(true).build())
+        .build()).await?;
+    // players made by moderators may have no discord account at all
+    players.create_index(IndexModel::builder()
+        .keys(doc! { "discord_ids": 1 })
+        .options(IndexOptions::builder()
+            .name(String::from("discord_ids_unique"))
+            .unique(true)
+            .partial_filter_expression(doc! { "discord_ids.0": { "$exists": true } })
+            .build())
+        .build()).await?;
+
+    Ok(())
+}
+
+async fn lowercase_usernames(mongo: &Database) -> Result<(), BotError> {
+    mongo.collection::<Document>("players")
+        .update_many(doc! {}, vec![doc! { "$set": { "username_lower": { "$toLower": "$username" } } }])
+        .await?;
+
+    Ok(())
+}
+
+async fn shared_identities(mongo: &Database, directory: &Database) -> Result<(), BotError> {
+    let identities = directory.collection::<Document>("identities");
+    identities.create_index(IndexModel::builder()
+        .keys(doc! { "username_lower": 1 })
+        .options(IndexOptions::builder().name(String::from("username_unique")).unique(true).build())
+        .build()).await?;
+    identities.create_index(IndexModel::builder()
+        .keys(doc! { "discord_ids": 1 })
+        .options(IndexOptions::builder()
+            .name(String::from("discord_ids_unique"))
+            .unique(true)
+            .partial_filter_expression(doc! { "discord_ids.0": { "$exists": true } })
+            .build())
+        .build()).await?;
+    let identity_info = directory.collection::<Document>("identity_info");
+    identity_info.update_one(doc! {}, doc! { "$setOnInsert": { "available_player_id": 1_i64 } }).upsert(true).await?;
+
+    let players = mongo.collection::<Document>("players");
+    if mongo.name() != directory.name() {
+        // their IDs were drawn separately and would collide with the main league's
+        if players.count_documents(doc! {}).await? > 0 {
+            return Err("this league's players predate shared identities; \
+                        move them into the directory by hand before upgrading".into());
+        }
+    } else {
+        // the main league's players become the first identities, under the IDs they already have
+        let mut cursor = players.find(doc! {})
+            .projection(doc! { "username": 1, "username_lower": 1, "discord_ids": 1 })
+            .await?;
+        while let Some(player) = cursor.try_next().await? {
+            identities.update_one(
+                doc! { "_id": player.get("_id").cloned().unwrap_or(bson::Bson::Null) },
+                doc! {
+                    "$set": {
+                        "username": player.get("username").cloned().unwrap_or(bson::Bson::Null),
+                        "username_lower": player.get("username_lower").cloned().unwrap_or(bson::Bson::Null),
+                        "discord_ids": player.get("discord_ids").cloned().unwrap_or(bson::Bson::Array(vec![])),
+                    },
+                    "$addToSet": { "leagues": mongo.name() },
+                },
+            ).upsert(true).await?;
+        }
+    }
+
+    // player IDs are drawn from the directory now
+    let league_info = mongo.collection::<Document>("league_info");
+    if let Some(next) = league_info.find_one(doc! {}).await?.and_then(|info| info.get("available_player_id").cloned()) {
+        if mongo.name() == directory.name() {
+            identity_info.update_one(doc! {}, doc! { "$max": { "available_player_id": next } }).await?;
+        }
+        league_info.update_many(doc! {}, doc! { "$unset": { "available_player_id": "" } }).await?;
+    }
+
+    Ok(())
+}
@@ -1,17 +1,30 @@
+pub(crate) mod achievements;
+pub(crate) mod autocomplete;
 pub(crate) mod checks;
 pub(crate) mod rating;
 pub(crate) mod constants;
+pub(crate) mod digest;
+pub(crate) mod events;
+pub(crate) mod indexes;
+pub(crate) mod league;
+pub(crate) mod lease;
+pub(crate) mod logging;
+pub(crate) mod metrics;
+pub(crate) mod migrations;
 pub(crate) mod paginate;
+pub(crate) mod processor;
+pub(crate) mod review;
 pub(crate) mod serialization;
 
 use crate::commands::ewar::user::try_lookup_player;
 use crate::commands::ewar::user::UserLookupType::SystemID;
-use crate::model::{ApprovalStatus, Player, StandingEvent, StandingEventInner};
+use crate::model::{ApprovalStatus, BlacklistEntry, Player, RatingMark, StandingEvent, StandingEventInner};
 use crate::{BotError, Context};
 use chrono::Utc;
 use discord_md::generate::{ToMarkdownString, ToMarkdownStringOption};
 use itertools::Itertools;
 use mongodb::Database;
+use pluralizer::pluralize;
 use serenity::all::{CreateEmbed, CreateEmbedAuthor, Mentionable, Permissions};
 use serenity::all::{User, UserId};
 use timeago::TimeUnit::Seconds;
@@ -48,6 +61,11 @@ impl Player {
     }
 }
 
+impl RatingMark {
+    pub(crate) fn describe(&self) -> String {
+        format!("{:.2} on <t:{}:d> (event {})", self.rating, self.when.timestamp(), self.event)
+    }
+}
 
 impl StandingEvent {
     pub(crate) async fn short_summary(&self, mongo: &Database) -> Result<Box<str>, BotError> {
@@ -87,9 +105,14 @@ impl StandingEvent {
                     looked_up.push(try_lookup_player(mongo, SystemID(*player_id)).await?.expect("user joined to league not found"));
                 }
 
-                format!(
-                    "{} joined league with rating {initial_rating}, deviation {initial_deviation}",
-                    looked_up.into_iter().map(|u| u.short_summary()).join(", "))
+                if looked_up.is_empty() {
+                    // the only joiner was merged into an account that joined earlier
+                    String::from("<merged account> joined league")
+                } else {
+                    format!(
+                        "{} joined league with rating {initial_rating}, deviation {initial_deviation}",
+                        looked_up.into_iter().map(|u| u.short_summary()).join(", "))
+                }
             }
             StandingEventInner::Penalty { victims, delta_rating, reason } => {
                 let mut looked_up = Vec::with_capacity(victims.len());
@@ -111,6 +134,63 @@ impl StandingEvent {
                         looked_up.into_iter().map(|u| u.short_summary()).join(", "),
                         delta_deviation)
             }
+            StandingEventInner::Suspend { victims, until, reason } => {
+                let mut looked_up = Vec::with_capacity(victims.len());
+                for player_id in victims.iter() {
+                    looked_up.push(try_lookup_player(mongo, SystemID(*player_id)).await?.expect("suspended user not found"));
+                }
+
+                format!("{} suspended until <t:{}:f> for {reason}",
+                        looked_up.into_iter().map(|u| u.short_summary()).join(", "),
+                        until.timestamp())
+            }
+            StandingEventInner::Unsuspend { victims, reason } => {
+                let mut looked_up = Vec::with_capacity(victims.len());
+                for player_id in victims.iter() {
+                    looked_up.push(try_lookup_player(mongo, SystemID(*player_id)).await?.expect("unsuspended user not found"));
+                }
+
+                format!("{} no longer suspended: {reason}",
+                        looked_up.into_iter().map(|u| u.short_summary()).join(", "))
+            }
+            StandingEventInner::MergePlayers { victims, merged, merged_username } => {
+                let mut looked_up = Vec::with_capacity(victims.len());
+                for player_id in victims.iter() {
+                    looked_up.push(try_lookup_player(mongo, SystemID(*player_id)).await?.expect("merge target not found"));
+                }
+
+                format!("duplicate account {} (ID {merged}) merged into {}",
+                        remove_markdown(merged_username),
+                        looked_up.into_iter().map(|u| u.short_summary()).join(", "))
+            }
+            StandingEventInner::Rename { old_username, new_username, .. } => {
+                format!("{} renamed to {}", remove_markdown(old_username), remove_markdown(new_username))
+            }
+            StandingEventInner::LinkDiscord { victims, discord_id } => {
+                let mut looked_up = Vec::with_capacity(victims.len());
+                for player_id in victims.iter() {
+                    looked_up.push(try_lookup_player(mongo, SystemID(*player_id)).await?.expect("linked user not found"));
+                }
+
+                format!("{} linked to {}",
+                        UserId::new(*discord_id).mention(),
+                        looked_up.into_iter().map(|u| u.reference_no_discord()).join(", "))
+            }
+            StandingEventInner::UnlinkDiscord { victims, discord_id } => {
+                let mut looked_up = Vec::with_capacity(victims.len());
+                for player_id in victims.iter() {
+                    looked_up.push(try_lookup_player(mongo, SystemID(*player_id)).await?.expect("unlinked user not found"));
+                }
+
+                format!("{} unlinked from {}",
+                        UserId::new(*discord_id).mention(),
+                        looked_up.into_iter().map(|u| u.reference_no_discord()).join(", "))
+            }
+            StandingEventInner::SoftReset { victims, season, pull, delta_deviation } => {
+                format!("season {season} closed; {} pulled {:.0}% of the way to the default rating and gained {delta_deviation} deviation",
+                        pluralize("rating", victims.len() as isize, true),
+                        pull * 100.0)
+            }
             _ => String::from("don't know how to summarize this event type")
         };
 
@@ -132,4 +212,32 @@ impl ApprovalStatus {
             None => "<system>".into()
         }).into_boxed_str())
     }
-}
\ No newline at end of file
+}
+impl BlacklistEntry {
+    /// who, why, by whom, and for how long
+    pub(crate) async fn describe(&self, mongo: &Database) -> Result<String, BotError> {
+        let player = try_lookup_player(mongo, SystemID(self.player))
+            .await?
+            .expect("blacklisted player's ID not valid");
+
+        let added_by = match self.added_by {
+            Some(moderator_id) => try_lookup_player(mongo, SystemID(moderator_id))
+                .await?
+                .expect("blacklisting moderator's ID not valid")
+                .short_summary(),
+            None => "<unknown>".into(),
+        };
+
+        Ok(format!(
+            "{}: {} (added by {}{}, {})",
+            player.short_summary(),
+            self.reason.as_deref().map(remove_markdown).unwrap_or(String::from("<no reason given>")),
+            added_by,
+            self.when.map(|when| format!(" <t:{}:d>", when.timestamp())).unwrap_or_default(),
+            match self.expires {
+                Some(expires) => format!("expires <t:{}:R>", expires.timestamp()),
+                None => String::from("never expires"),
+            },
+        ))
+    }
+}
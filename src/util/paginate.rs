@@ -9,6 +9,8 @@ use std::time::Duration;
 
 pub(crate) struct EmbedLinePaginator {
     pages: Vec<String>,
+    // page each input line landed on
+    line_pages: Vec<u8>,
     current_page: u8,
 }
 
@@ -57,6 +59,7 @@ impl EmbedLinePaginator {
         let mut working_chunk = &mut chunks[0];
         let mut num_in_working_chunk = 0usize;
         let mut num_chunks = 1usize;
+        let mut line_pages = Vec::with_capacity(lines.len());
 
         for line in lines {
             if working_chunk.chars().count() + options.sep.len() + line.chars().count() > options.char_limit
@@ -70,14 +73,24 @@ impl EmbedLinePaginator {
             working_chunk.push_str(&*options.sep);
             working_chunk.push_str(&*line);
             num_in_working_chunk += 1;
+            line_pages.push(num_chunks as u8);
         }
 
         Self {
             pages: chunks,
+            line_pages,
             current_page: 1,
         }
     }
 
+    /// open on the page holding this line (counting from 0) instead of the first page
+    pub(crate) fn start_at_line(mut self, line: usize) -> Self {
+        if let Some(page) = self.line_pages.get(line) {
+            self.current_page = *page;
+        }
+        self
+    }
+
     fn embed_for(&self, ctx: Context<'_>, page: u8) -> CreateEmbed {
         base_embed(ctx)
             .description(self.pages[(page - 1) as usize].clone())
@@ -0,0 +1,233 @@
+use crate::commands::ewar::leaderboard::refresh_live_leaderboard;
+use crate::commands::ewar::season::{close_season_inner, SeasonClose};
+use crate::model::{EventNumber, Player, PlayerID, StandingEvent};
+use crate::store::{BuildRegistration, Change, Store};
+use crate::util::achievements::announce_unlocks;
+use crate::util::lease::Lease;
+use crate::util::rating::{advance_approve_pointer, reset_standings, Advanced};
+use crate::BotError;
+use futures::FutureExt;
+use mongodb::Database;
+use serenity::all::{ChannelId, Http};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use tokio::sync::{mpsc, oneshot};
+use tracing::{error, info, info_span, warn, Instrument, Span};
+
+// guards the approve pointer and present-day ratings of a league
+static RATINGS_LEASE: &str = "ratings";
+
+/// work that changes present-day ratings or the approve pointer; only the processor task does it
+enum RatingJob {
+    Advance { stop_before: Option<EventNumber>, done: oneshot::Sender<Result<EventNumber, BotError>> },
+    Reset { done: oneshot::Sender<Result<(), BotError>> },
+    PopEvent { event_number: EventNumber, done: oneshot::Sender<Result<Option<StandingEvent>, BotError>> },
+    Record { changes: Vec<Change>, build: BuildEvent, done: oneshot::Sender<Result<StandingEvent, BotError>> },
+    Register { identity: Option<PlayerID>, build: BuildRegistration, done: oneshot::Sender<Result<Player, BotError>> },
+    CloseSeason { reviewer: PlayerID, pull: f64, delta_deviation: f64, done: oneshot::Sender<Result<SeasonClose, BotError>> },
+}
+
+type BuildEvent = Box<dyn Fn(EventNumber) -> StandingEvent + Send + Sync>;
+
+impl RatingJob {
+    fn kind(&self) -> &'static str {
+        match self {
+            RatingJob::Advance { .. } => "advance",
+            RatingJob::Reset { .. } => "reset",
+            RatingJob::PopEvent { .. } => "pop_event",
+            RatingJob::Record { .. } => "record",
+            RatingJob::Register { .. } => "register",
+            RatingJob::CloseSeason { .. } => "close_season",
+        }
+    }
+
+    fn fail(self, err: BotError) {
+        match self {
+            RatingJob::Advance { done, .. } => {
+                let _ = done.send(Err(err));
+            }
+            RatingJob::Reset { done } => {
+                let _ = done.send(Err(err));
+            }
+            RatingJob::PopEvent { done, .. } => {
+                let _ = done.send(Err(err));
+            }
+            RatingJob::Record { done, .. } => {
+                let _ = done.send(Err(err));
+            }
+            RatingJob::Register { done, .. } => {
+                let _ = done.send(Err(err));
+            }
+            RatingJob::CloseSeason { done, .. } => {
+                let _ = done.send(Err(err));
+            }
+        }
+    }
+}
+
+/// handle to a league's rating processor. jobs run one at a time in the order they were sent,
+/// whether they came from a command or a scheduled job
+#[derive(Clone)]
+pub(crate) struct RatingProcessor {
+    // each job goes with the span of whoever sent it, so its logs land under that command or scheduled job
+    jobs: mpsc::UnboundedSender<(RatingJob, Span)>,
+}
+
+struct ProcessorTask {
+    league: String,
+    store: Store,
+    // for the lease and the live leaderboard
+    mongo: Database,
+    // which process this is, for the ratings lease
+    holder: String,
+    http: Arc<Http>,
+    announce_channel: Option<ChannelId>,
+}
+
+impl RatingProcessor {
+    /// start the task that owns rating writes for one league
+    pub(crate) fn spawn(league: String, store: Store, mongo: Database, holder: String, http: Arc<Http>, announce_channel: Option<ChannelId>) -> Self {
+        let (jobs, mut queue) = mpsc::unbounded_channel::<(RatingJob, Span)>();
+        let task = ProcessorTask { league, store, mongo, holder, http, announce_channel };
+
+        tokio::spawn(async move {
+            while let Some((job, sender)) = queue.recv().await {
+                let span = info_span!(parent: &sender, "rating_job", league = %task.league, job = job.kind());
+                task.run(job).instrument(span).await;
+            }
+        });
+
+        RatingProcessor { jobs }
+    }
+
+    async fn submit<T>(&self, job: impl FnOnce(oneshot::Sender<Result<T, BotError>>) -> RatingJob) -> Result<T, BotError> {
+        let (done, result) = oneshot::channel();
+        self.jobs.send((job(done), Span::current())).map_err(|_| "rating processor is gone")?;
+        result.await.map_err(|_| "rating processor dropped a job")?
+    }
+
+    /// process every reviewed event up to the first unreviewed one (or `stop_before`), returning where the pointer stopped
+    pub(crate) async fn advance(&self, stop_before: Option<EventNumber>) -> Result<EventNumber, BotError> {
+        self.submit(|done| RatingJob::Advance { stop_before, done }).await
+    }
+
+    /// forget present-day ratings; they come back as the pointer is advanced again
+    pub(crate) async fn reset(&self) -> Result<(), BotError> {
+        self.submit(|done| RatingJob::Reset { done }).await
+    }
+
+    /// forget ratings and rebuild them from the whole record
+    pub(crate) async fn replay(&self) -> Result<EventNumber, BotError> {
+        self.reset().await?;
+        self.advance(None).await
+    }
+
+    /// delete the latest event and give its number back, if it is still the latest.
+    /// returns the removed event
+    pub(crate) async fn pop_event(&self, event_number: EventNumber) -> Result<Option<StandingEvent>, BotError> {
+        self.submit(|done| RatingJob::PopEvent { event_number, done }).await
+    }
+
+    /// append an event along with the changes to players it records
+    pub(crate) async fn record(
+        &self,
+        changes: Vec<Change>,
+        build: impl Fn(EventNumber) -> StandingEvent + Send + Sync + 'static,
+    ) -> Result<StandingEvent, BotError> {
+        self.submit(|done| RatingJob::Record { changes, build: Box::new(build), done }).await
+    }
+
+    /// add a player, drawing their ID along with the event recording them unless they join under an `identity`
+    /// they already have in another league
+    pub(crate) async fn register(&self, identity: Option<PlayerID>, build: BuildRegistration) -> Result<Player, BotError> {
+        self.submit(|done| RatingJob::Register { identity, build, done }).await
+    }
+
+    /// archive the standings and soft-reset everyone, once every event is processed
+    pub(crate) async fn close_season(&self, reviewer: PlayerID, pull: f64, delta_deviation: f64) -> Result<SeasonClose, BotError> {
+        self.submit(|done| RatingJob::CloseSeason { reviewer, pull, delta_deviation, done }).await
+    }
+}
+
+impl ProcessorTask {
+    async fn run(&self, job: RatingJob) {
+        // other bot processes may share this league; only the lease holder writes ratings or the pointer
+        let mut lease = match unwound(Lease::acquire(&self.mongo, RATINGS_LEASE, &self.holder)).await {
+            Ok(lease) => lease,
+            Err(err) => return job.fail(err),
+        };
+
+        // nobody waiting on the result is fine; the work is done either way
+        match job {
+            RatingJob::Advance { stop_before, done } => {
+                let _ = done.send(unwound(self.advance(&mut lease, stop_before)).await);
+            }
+            RatingJob::Reset { done } => {
+                let _ = done.send(unwound(reset_standings(&self.store, &mut lease)).await);
+            }
+            RatingJob::PopEvent { event_number, done } => {
+                let popped = unwound(self.store.events.pop(event_number, &mut lease)).await;
+                if let Ok(Some(_)) = popped {
+                    info!(event_number, "popped event");
+                }
+                let _ = done.send(popped);
+            }
+            RatingJob::Record { changes, build, done } => {
+                let _ = done.send(unwound(self.store.record(changes, build, &mut lease)).await);
+            }
+            RatingJob::Register { identity, build, done } => {
+                let _ = done.send(unwound(self.store.players.register(identity, build)).await);
+            }
+            RatingJob::CloseSeason { reviewer, pull, delta_deviation, done } => {
+                let _ = done.send(unwound(self.close_season(&mut lease, reviewer, pull, delta_deviation)).await);
+            }
+        }
+
+        if let Err(err) = lease.release().await {
+            warn!(error = %err, "couldn't release ratings lease");
+        }
+    }
+
+    /// advance, then tell everyone what changed; failing to tell them doesn't undo anything
+    async fn advance(&self, lease: &mut Lease, stop_before: Option<EventNumber>) -> Result<EventNumber, BotError> {
+        let Advanced { pointer, unlocks, ratings_changed } = advance_approve_pointer(&self.store, lease, stop_before).await?;
+
+        if let Err(err) = announce_unlocks(&self.http, &self.store, self.announce_channel, &unlocks).await {
+            warn!(error = %err, "couldn't announce achievements");
+        }
+
+        if ratings_changed {
+            if let Err(err) = refresh_live_leaderboard(&self.http, &self.mongo).await {
+                warn!(error = %err, "couldn't update live leaderboard");
+            }
+        }
+
+        Ok(pointer)
+    }
+
+    /// close the season between two advances, so the archive and the reset both see every reviewed game
+    async fn close_season(&self, lease: &mut Lease, reviewer: PlayerID, pull: f64, delta_deviation: f64) -> Result<SeasonClose, BotError> {
+        // the archive has to reflect every game played this season
+        self.advance(lease, None).await?;
+        let closed = close_season_inner(&self.store, lease, reviewer, pull, delta_deviation).await?;
+        if let SeasonClose::Closed { .. } = closed {
+            self.advance(lease, None).await?;
+        }
+        Ok(closed)
+    }
+}
+
+/// a panic partway through a job becomes that job's error, so the processor lives on to run the next one
+async fn unwound<T>(work: impl Future<Output = Result<T, BotError>>) -> Result<T, BotError> {
+    match AssertUnwindSafe(work).catch_unwind().await {
+        Ok(result) => result,
+        Err(panic) => {
+            let message = panic.downcast_ref::<&str>().copied()
+                .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                .unwrap_or("no message");
+            error!(panic = message, "rating job panicked");
+            Err(format!("rating job panicked: {message}").into())
+        }
+    }
+}
@@ -1,15 +1,14 @@
-use crate::commands::ewar::user::try_lookup_player;
-use crate::commands::ewar::user::UserLookupType::SystemID;
-use crate::model::StandingEventInner::{ChangeStanding, GameEnd, InactivityDecay, JoinLeague, Penalty};
-use crate::model::{EventNumber, LeagueInfo, Player, StandingEvent, StandingEventInner};
+use crate::model::StandingEventInner::{ChangeStanding, GameEnd, InactivityDecay, JoinLeague, LinkDiscord, MergePlayers, Penalty, Rename, SetStanding, SoftReset, Suspend, UnlinkDiscord, Unsuspend};
+use crate::model::{EventNumber, Player, PlayerID, RatingMark, StandingEvent, StandingEventInner, Unlock};
+use crate::store::{Fence, Outcome, Processed, Store};
+use crate::util::achievements::BeforeEvent;
 use crate::util::constants::{DEFAULT_RATING, PROVISIONAL_DEVIATION_THRESHOLD, TRUESKILL_CONFIG};
-use crate::{BotError, BotVars};
-use bson::doc;
-use futures::StreamExt;
+use crate::BotError;
 use itertools::Itertools;
-use mongodb::Database;
 use skillratings::trueskill::{expected_score_multi_team, trueskill_multi_team, TrueSkillRating};
 use skillratings::MultiTeamOutcome;
+use std::collections::{BTreeMap, HashMap};
+use tracing::{debug, info};
 
 pub(crate) trait RatingExtra {
     fn is_provisional(&self) -> bool;
@@ -21,7 +20,7 @@ pub(crate) trait RatingExtra {
 
 impl RatingExtra for TrueSkillRating {
     fn is_provisional(&self) -> bool {
-        self.uncertainty - PROVISIONAL_DEVIATION_THRESHOLD > f64::EPSILON
+        self.uncertainty - *PROVISIONAL_DEVIATION_THRESHOLD > f64::EPSILON
     }
 
     fn leaderboard_rating(&self) -> f64 {
@@ -74,114 +73,273 @@ pub(crate) fn game_affect_ratings(placement: &Vec<TrueSkillRating>) -> Vec<TrueS
         .collect_vec()
 }
 
+/// what one advance of the approve pointer did
+pub(crate) struct Advanced {
+    /// where the pointer stopped
+    pub(crate) pointer: EventNumber,
+    /// achievements unlocked past what was processed before, so not announced yet
+    pub(crate) unlocks: Vec<(PlayerID, Unlock)>,
+    pub(crate) ratings_changed: bool,
+}
+
 /// check for any unreviewed events (right now, these are only games) and update the record of present-day ratings.
 /// the "approve pointer" in the function name, or the first unreviewed event, is advanced until it actually points to an unreviewed event
-/// along the way, we process the results of any standing events we find
-pub(crate) async fn advance_approve_pointer(data: &BotVars, stop_before: Option<EventNumber>) -> Result<EventNumber, BotError> {
-    let mutex = data.core_state_lock.clone();
-    mutex.lock().await;
-
-    let league_info_collection = data.mongo.collection::<LeagueInfo>("league_info");
-    let league_info = league_info_collection.find_one(doc! {}).await?
-        .expect("league_info struct missing");
+/// along the way, we process the results of any standing events we find.
+/// only a league's rating processor calls this, holding the ratings lease, so runs never overlap
+pub(crate) async fn advance_approve_pointer(store: &Store, fence: &mut dyn Fence, stop_before: Option<EventNumber>) -> Result<Advanced, BotError> {
+    let league_info = store.league_info.league_info().await?;
     let mut first_unreviewed_event_number_num = league_info.first_unreviewed_event_number;
+    let mut standings = Standings::load(store, first_unreviewed_event_number_num).await?;
+    let mut unlocks = Vec::new();
+    let mut ratings_changed = false;
 
-    let mut allegedly_unreviewed = data.mongo.collection::<StandingEvent>("events")
-        .find(doc! { "_id": {"$gte": first_unreviewed_event_number_num } })
-        .sort(doc! { "_id": 1 }).await?;
-
-    while let Some(standing_event) = allegedly_unreviewed.next().await {
+    for standing_event in store.events.events_from(first_unreviewed_event_number_num).await? {
         if first_unreviewed_event_number_num >= stop_before.unwrap_or(EventNumber::MAX) { break; }
 
-        let standing_event = standing_event?;
         let StandingEvent { ref approval_status, .. } = standing_event;
         match approval_status {
             None => break,
             Some(approval_status) => {
+                // stop before writing anything if another process may have taken over
+                fence.check().await?;
                 first_unreviewed_event_number_num += 1;
-                if approval_status.approved {
-                    standing_event.process_effect(&data.mongo).await?;
+                let processed = if approval_status.approved {
+                    standing_event.process_effect(&mut standings)?
+                } else {
+                    Processed::default()
+                };
+
+                // move the pointer as we go so a takeover resumes right after the last event done here;
+                // the event's writes land with it, so a holder fenced out here has changed nothing
+                store.league_info.advance_pointer(first_unreviewed_event_number_num, &processed, fence).await?;
+                debug!(event_number = standing_event._id, approved = approval_status.approved, "processed event");
+                standings.apply(&processed);
+                ratings_changed |= !processed.ratings.is_empty();
+                unlocks.extend(processed.unlocks);
+            }
+        }
+    }
+
+    // a replay earns everything over again, but only unlocks past what was processed before are news
+    unlocks.retain(|(_, unlock)| unlock.event >= league_info.achievements_announced_before);
+    if first_unreviewed_event_number_num != league_info.first_unreviewed_event_number {
+        info!(from = league_info.first_unreviewed_event_number, to = first_unreviewed_event_number_num, "approve pointer advanced");
+    }
+
+    Ok(Advanced { pointer: first_unreviewed_event_number_num, unlocks, ratings_changed })
+}
+
+/// present-day standings as one advance goes, kept in memory so processing an event doesn't go back to the
+/// store for them. each event's writes are taken in here once they've landed
+pub(crate) struct Standings {
+    players: BTreeMap<PlayerID, Player>,
+    // approved games each player was in, before the pointer
+    games_played: HashMap<PlayerID, u64>,
+    // worked out again only after ratings or who has played change
+    top_three: Option<Vec<PlayerID>>,
+}
+
+impl Standings {
+    /// as of the approve pointer sitting at `pointer`
+    async fn load(store: &Store, pointer: EventNumber) -> Result<Self, BotError> {
+        Ok(Standings {
+            players: store.players.players().await?.into_iter().map(|player| (player._id, player)).collect(),
+            games_played: store.events.approved_game_counts(pointer).await?,
+            top_three: None,
+        })
+    }
+
+    pub(crate) fn player(&self, id: PlayerID) -> Option<&Player> {
+        self.players.get(&id)
+    }
+
+    /// approved games before the pointer
+    pub(crate) fn games_played(&self, id: PlayerID) -> u64 {
+        self.games_played.get(&id).copied().unwrap_or(0)
+    }
+
+    /// the top three on the leaderboard, blacklist aside
+    pub(crate) fn top_three(&mut self) -> &[PlayerID] {
+        self.top_three.get_or_insert_with(|| self.players.values()
+            .filter(|player| player.last_played.is_some())
+            .sorted_by(|a, b| b.rating_struct().leaderboard_rating()
+                .total_cmp(&a.rating_struct().leaderboard_rating()))
+            .take(3)
+            .map(|player| player._id)
+            .collect_vec())
+    }
+
+    /// take in what was just written for one event, the same way the store did
+    fn apply(&mut self, processed: &Processed) {
+        for (id, rating) in &processed.ratings {
+            if let Some(player) = self.players.get_mut(id) {
+                player.rating = rating.rating;
+                player.deviation = rating.uncertainty;
+            }
+        }
+        if let Some((ids, when)) = &processed.played {
+            for id in ids {
+                if let Some(player) = self.players.get_mut(id) {
+                    player.last_played = player.last_played.max(Some(*when));
                 }
+                *self.games_played.entry(*id).or_default() += 1;
+            }
+        }
+        for (id, mark) in &processed.peaks {
+            if let Some(player) = self.players.get_mut(id) {
+                player.peak = Some(mark.clone());
             }
         }
+        for (id, mark) in &processed.troughs {
+            if let Some(player) = self.players.get_mut(id) {
+                player.trough = Some(mark.clone());
+            }
+        }
+        for (id, unlock) in &processed.unlocks {
+            if let Some(player) = self.players.get_mut(id) {
+                player.achievements.push(unlock.clone());
+            }
+        }
+
+        if !processed.ratings.is_empty() || processed.played.is_some() {
+            self.top_three = None;
+        }
     }
+}
 
-    league_info_collection.update_one(doc! {}, doc! {
-        "$max": { "first_unreviewed_event_number": first_unreviewed_event_number_num as i64 },
-    }).await?;
+/// forget all present-day ratings and move the approve pointer back to the start of the record.
+/// the pointer must be advanced again afterward to rebuild ratings
+pub(crate) async fn reset_standings(store: &Store, fence: &mut dyn Fence) -> Result<(), BotError> {
+    fence.check().await?;
+    store.league_info.reset(fence).await?;
+    info!("standings reset");
 
-    Ok(first_unreviewed_event_number_num)
+    Ok(())
 }
 
 impl StandingEvent {
-    pub(crate) async fn process_effect(&self, mongo: &Database) -> Result<(), BotError> {
+    /// work out what this event does to present-day standings, including any achievements it unlocks.
+    /// nothing is written here; the caller writes it all as the pointer moves past the event
+    pub(crate) fn process_effect(&self, standings: &mut Standings) -> Result<Processed, BotError> {
+        let before = BeforeEvent::snapshot(self, standings);
+        let mut processed = Processed::default();
+
         let inner_processable = match self.inner {
             Penalty { .. } => &self.inner.clone()
-                .try_into_generic_variant().expect("1984"),
+                .try_into_generic_variant().ok_or("penalty has no generic form")?,
             _ => &self.inner
         };
 
         match inner_processable {
             InactivityDecay { victims, delta_deviation } => {
-                mongo.collection::<Player>("players").update_many(
-                    doc! { "_id": { "$in": victims } },
-                    doc! { "$inc": { "deviation": delta_deviation } },
-                ).await?;
-
-                mongo.collection::<Player>("players").update_many(
-                    doc! { "_id": { "$in": victims } },
-                    doc! { "$min": { "deviation": DEFAULT_RATING.uncertainty } },
-                ).await?;
+                for (victim, rating) in current_ratings(standings, victims) {
+                    processed.ratings.push((victim, TrueSkillRating {
+                        rating: rating.rating,
+                        uncertainty: (rating.uncertainty + delta_deviation).min(DEFAULT_RATING.uncertainty),
+                    }));
+                }
             }
             GameEnd(game) => {
-                mongo.collection::<Player>("players").update_many(
-                    doc! {"_id": {"$in": &game.ranking}},
-                    doc! {"$max": {"last_played": bson::DateTime::from_chrono(self.when)}},
-                ).await?;
+                processed.played = Some((game.ranking.clone(), self.when));
 
                 let mut old_ratings = Vec::with_capacity(game.ranking.len());
                 for party_id in game.ranking.iter() {
-                    let player = try_lookup_player(mongo, SystemID(*party_id)).await?.expect("party to game DNE");
+                    let player = standings.player(*party_id)
+                        .ok_or_else(|| format!("player {party_id} in game at event {} DNE", self._id))?;
                     old_ratings.push(player.rating_struct());
                 }
 
                 let new_ratings = game_affect_ratings(&old_ratings);
-                for (party_id, new_rating) in game.ranking.iter().zip(new_ratings.into_iter()) {
-                    mongo.collection::<Player>("players").update_one(
-                        doc! { "_id" : *party_id },
-                        doc! {"$set": {"rating": new_rating.rating, "deviation": new_rating.uncertainty}},
-                    ).await?;
-                }
+
+                // remember how likely each result was and what it did, for stats and records
+                let deltas = old_ratings.iter().zip(new_ratings.iter())
+                    .map(|(old_rating, new_rating)| new_rating.leaderboard_rating() - old_rating.leaderboard_rating())
+                    .collect_vec();
+                processed.outcome = Some(Outcome { event: self._id, expected: expected_outcome(&old_ratings), deltas });
+
+                processed.ratings.extend(game.ranking.iter().copied().zip(new_ratings));
             }
             ChangeStanding { victims, delta_rating, delta_deviation, .. } => {
-                if let Some(delta_rating) = delta_rating {
-                    mongo.collection::<Player>("players").update_many(
-                        doc! { "_id": { "$in": victims } },
-                        doc! { "$inc": { "rating": delta_rating } },
-                    ).await?;
-                }
-
-                if let Some(delta_deviation) = delta_deviation {
-                    mongo.collection::<Player>("players").update_many(
-                        doc! { "_id": { "$in": victims } },
-                        doc! { "$inc": { "deviation": delta_deviation } },
-                    ).await?;
+                for (victim, rating) in current_ratings(standings, victims) {
+                    processed.ratings.push((victim, TrueSkillRating {
+                        rating: rating.rating + delta_rating.unwrap_or(0.0),
+                        uncertainty: rating.uncertainty + delta_deviation.unwrap_or(0.0),
+                    }));
                 }
             }
             JoinLeague { victims, initial_rating, initial_deviation } => {
-                mongo.collection::<Player>("players").update_many(
-                    doc! { "_id": { "$in": victims } },
-                    doc! { "$set": { "rating": initial_rating, "deviation": initial_deviation } },
-                ).await?;
+                for victim in victims {
+                    processed.ratings.push((*victim, TrueSkillRating {
+                        rating: *initial_rating,
+                        uncertainty: *initial_deviation,
+                    }));
+                }
+            }
+            SoftReset { victims, pull, delta_deviation, .. } => {
+                for (victim, rating) in current_ratings(standings, victims) {
+                    processed.ratings.push((victim, TrueSkillRating {
+                        rating: rating.rating + pull * (DEFAULT_RATING.rating - rating.rating),
+                        uncertainty: (rating.uncertainty + delta_deviation).min(DEFAULT_RATING.uncertainty),
+                    }));
+                }
             }
+            // these don't touch ratings and are applied when issued, not as the pointer moves
+            Suspend { .. } | Unsuspend { .. } | MergePlayers { .. }
+            | Rename { .. } | LinkDiscord { .. } | UnlinkDiscord { .. } => {}
             _ => return Err("don't know how to handle this event type yet".into())
         }
 
+        self.update_rating_marks(standings, &mut processed)?;
+        self.award_achievements(standings, &before, &mut processed)?;
+        Ok(processed)
+    }
+
+    /// note new highs and lows for everyone whose rating this event touched
+    fn update_rating_marks(&self, standings: &Standings, processed: &mut Processed) -> Result<(), BotError> {
+        for (player_id, rating) in processed.ratings.iter() {
+            let player = standings.player(*player_id)
+                .ok_or_else(|| format!("player {player_id} rated by event {} DNE", self._id))?;
+            let mark = RatingMark {
+                rating: rating.leaderboard_rating(),
+                event: self._id,
+                when: self.when,
+            };
+
+            if player.peak.as_ref().is_none_or(|peak| mark.rating > peak.rating) {
+                processed.peaks.push((*player_id, mark.clone()));
+            }
+
+            if !rating.is_provisional() && player.trough.as_ref().is_none_or(|trough| mark.rating < trough.rating) {
+                processed.troughs.push((*player_id, mark));
+            }
+        }
+
         Ok(())
     }
 }
 
+/// the present-day rating of each player that still exists, in the order given
+fn current_ratings(standings: &Standings, players: &[PlayerID]) -> Vec<(PlayerID, TrueSkillRating)> {
+    players.iter()
+        .filter_map(|player_id| standings.player(*player_id).map(|player| (*player_id, player.rating_struct())))
+        .collect()
+}
+
 impl StandingEventInner {
+    /// players whose rating this event can change
+    pub(crate) fn rated_players(&self) -> &[PlayerID] {
+        match self {
+            Penalty { victims, .. }
+            | InactivityDecay { victims, .. }
+            | SetStanding { victims, .. }
+            | ChangeStanding { victims, .. }
+            | JoinLeague { victims, .. }
+            | SoftReset { victims, .. } => victims,
+            GameEnd(game) => &game.ranking,
+            _ => &[],
+        }
+    }
+
     /// convert to a different type to simplify handling
     fn try_into_generic_variant(self) -> Option<Self> {
         match self {
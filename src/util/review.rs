@@ -0,0 +1,91 @@
+use crate::config::ReviewConfig;
+use crate::model::{Game, GameID, PlayerID, StandingEvent};
+use std::num::NonZeroUsize;
+
+/// conflict-of-interest rules for reviewing and posting games
+#[derive(Clone)]
+pub(crate) struct ReviewPolicy {
+    /// moderators may review games they played in
+    pub(crate) allow_self_review: bool,
+    /// a moderator posting a game they played in must go through signoff and another moderator's review
+    pub(crate) second_moderator_for_own_games: bool,
+    /// distinct moderator approvals needed before a game enters the record
+    pub(crate) approval_quorum: NonZeroUsize,
+}
+
+impl Default for ReviewPolicy {
+    fn default() -> Self {
+        Self {
+            allow_self_review: false,
+            second_moderator_for_own_games: true,
+            approval_quorum: NonZeroUsize::MIN,
+        }
+    }
+}
+
+impl ReviewPolicy {
+    pub(crate) fn from_config(config: &ReviewConfig) -> Self {
+        Self {
+            allow_self_review: config.allow_self_review,
+            second_moderator_for_own_games: !config.allow_moderator_self_post,
+            approval_quorum: config.approval_quorum,
+        }
+    }
+
+    /// whether a moderator posting this game may skip signoff and count as a reviewer
+    pub(crate) fn moderator_may_self_post(&self, poster: PlayerID, ranking: &[PlayerID]) -> bool {
+        !(self.second_moderator_for_own_games && ranking.contains(&poster))
+    }
+
+    /// check whether this moderator may cast this review at all
+    pub(crate) fn check_review(&self, reviewer: PlayerID, event: &StandingEvent, game: &Game, approved: bool) -> Result<(), ReviewConflict> {
+        if !self.allow_self_review && game.ranking.contains(&reviewer) {
+            return Err(ReviewConflict::SelfReview { game_id: game.game_id });
+        }
+
+        if approved && event.approvals.contains(&reviewer) {
+            return Err(ReviewConflict::AlreadyApproved {
+                have: event.approvals.len(),
+                need: self.approval_quorum.get(),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// check whether this moderator may decide on this player's rename
+    pub(crate) fn check_rename_review(&self, reviewer: PlayerID, target: PlayerID) -> Result<(), ReviewConflict> {
+        if !self.allow_self_review && reviewer == target {
+            return Err(ReviewConflict::OwnRename);
+        }
+
+        Ok(())
+    }
+
+    pub(crate) fn quorum_met(&self, approvals: &[PlayerID]) -> bool {
+        approvals.len() >= self.approval_quorum.get()
+    }
+
+    pub(crate) fn approval_progress(&self, approvals: &[PlayerID]) -> String {
+        format!("{}/{} moderator approvals", approvals.len(), self.approval_quorum)
+    }
+}
+
+pub(crate) enum ReviewConflict {
+    SelfReview { game_id: GameID },
+    AlreadyApproved { have: usize, need: usize },
+    OwnRename,
+}
+
+impl ReviewConflict {
+    pub(crate) fn explain(&self) -> String {
+        match self {
+            ReviewConflict::SelfReview { game_id } => format!(
+                ":x: you played in game {game_id}, so you can't review it; another moderator has to"),
+            ReviewConflict::AlreadyApproved { have, need } => format!(
+                ":x: you already approved this game; it has {have}/{need} moderator approvals and needs a different moderator to approve it"),
+            ReviewConflict::OwnRename => String::from(
+                ":x: you can't approve or reject your own rename; another moderator has to"),
+        }
+    }
+}